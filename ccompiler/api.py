@@ -1,13 +1,258 @@
+import glob
+import hashlib
+import json
 import os
-from fastapi import FastAPI
-from pydantic import BaseModel
+import re
+import resource
+import selectors
+import shutil
+import signal
+import threading
+import time
+import uuid
+from contextlib import contextmanager
+from dataclasses import asdict
+from typing import Iterator, Literal, Optional
+from fastapi import FastAPI, Request
+from fastapi.responses import JSONResponse, StreamingResponse
+from pydantic import BaseModel, Field
 from pydantic.dataclasses import dataclass
-from tempfile import TemporaryDirectory, NamedTemporaryFile
+from tempfile import TemporaryDirectory
 import base64
 import subprocess
 
 app = FastAPI()
 
+# Limits, overridable from the environment.
+MAX_REQUEST_BYTES = int(os.environ.get("MAX_REQUEST_BYTES", 1024 * 1024))
+MAX_OUTPUT_BYTES = int(os.environ.get("MAX_OUTPUT_BYTES", 256 * 1024))
+WALL_TIME_SECONDS = float(os.environ.get("WALL_TIME_SECONDS", 30))
+CPU_TIME_SECONDS = int(os.environ.get("CPU_TIME_SECONDS", 20))
+MEMORY_BYTES = int(os.environ.get("MEMORY_BYTES", 512 * 1024 * 1024))
+FILE_SIZE_BYTES = int(os.environ.get("FILE_SIZE_BYTES", 16 * 1024 * 1024))
+MAX_WORKERS = int(os.environ.get("MAX_WORKERS", 4))
+# How long a request waits for a free worker before it is turned away.
+QUEUE_TIMEOUT_SECONDS = float(os.environ.get("QUEUE_TIMEOUT_SECONDS", 10))
+# Compile jobs kept at once, running or waiting for a worker.
+MAX_JOBS = int(os.environ.get("MAX_JOBS", MAX_WORKERS * 4))
+# How long a finished job's events can still be read.
+JOB_TTL_SECONDS = float(os.environ.get("JOB_TTL_SECONDS", 60))
+# How often waiting and running steps look for a cancel.
+POLL_SECONDS = 0.2
+# "bwrap" runs every tool in a bubblewrap sandbox; "none" is only for local
+# development, where the tools can read anything the service can.
+SANDBOX = os.environ.get("SANDBOX", "bwrap")
+# Read-only paths the tools need: the toolchain, its libraries and system headers.
+SANDBOX_PATHS = [path for path in
+                 os.environ.get("SANDBOX_PATHS", "/usr:/lib:/lib64:/bin:/opt/z88dk").split(":")
+                 if os.path.exists(path)]
+# Environment passed into the sandbox; everything else is cleared.
+SANDBOX_ENV = ["PATH", "ZCCCFG", "LANG"]
+SANDBOX_UID = 65534
+
+if SANDBOX not in ("bwrap", "none"):
+    raise RuntimeError(f"Unknown SANDBOX {SANDBOX!r}, expected bwrap or none")
+if SANDBOX == "bwrap" and shutil.which("bwrap") is None:
+    raise RuntimeError("bwrap not found: install bubblewrap, or set SANDBOX=none "
+                       "for local development")
+
+workers = threading.BoundedSemaphore(MAX_WORKERS)
+
+
+class ServiceError(Exception):
+    """A failure reported to the caller as `{"code": ..., "message": ...}`."""
+
+    def __init__(self, status: int, code: str, message: str):
+        super().__init__(message)
+        self.status = status
+        self.code = code
+        self.message = message
+
+
+def invalid_request(message: str) -> ServiceError:
+    return ServiceError(400, "invalid_request", message)
+
+
+@app.exception_handler(ServiceError)
+def service_error_handler(_request: Request, error: ServiceError):
+    return JSONResponse(status_code=error.status,
+                        content={"code": error.code, "message": error.message})
+
+
+@app.middleware("http")
+async def limit_request_size(request: Request, call_next):
+    length = request.headers.get("content-length")
+    if request.method == "POST" and (length is None or not length.isdigit()):
+        return JSONResponse(status_code=411,
+                            content={"code": "length_required",
+                                     "message": "Content-Length is required"})
+    if length is not None and length.isdigit() and int(length) > MAX_REQUEST_BYTES:
+        return JSONResponse(status_code=413,
+                            content={"code": "too_large",
+                                     "message": f"Request over {MAX_REQUEST_BYTES} bytes"})
+    return await call_next(request)
+
+
+def cancelled_error() -> ServiceError:
+    return ServiceError(409, "cancelled", "Compilation cancelled")
+
+
+@contextmanager
+def worker_slot(cancelled: Optional[threading.Event] = None):
+    """Holds one of the MAX_WORKERS build slots, or fails with `busy`. Gives up
+    waiting with `cancelled` once the event is set."""
+    deadline = time.monotonic() + QUEUE_TIMEOUT_SECONDS
+    while not workers.acquire(timeout=min(POLL_SECONDS, max(deadline - time.monotonic(), 0))):
+        if cancelled is not None and cancelled.is_set():
+            raise cancelled_error()
+        if time.monotonic() >= deadline:
+            raise ServiceError(503, "busy", "All compiler workers are busy, try again")
+    try:
+        yield
+    finally:
+        workers.release()
+
+
+def limit_resources():
+    resource.setrlimit(resource.RLIMIT_CPU, (CPU_TIME_SECONDS, CPU_TIME_SECONDS + 1))
+    resource.setrlimit(resource.RLIMIT_AS, (MEMORY_BYTES, MEMORY_BYTES))
+    resource.setrlimit(resource.RLIMIT_FSIZE, (FILE_SIZE_BYTES, FILE_SIZE_BYTES))
+    resource.setrlimit(resource.RLIMIT_CORE, (0, 0))
+
+
+# Signals a process gets when it runs into one of the rlimits above.
+RESOURCE_SIGNALS = {signal.SIGXCPU, signal.SIGXFSZ, signal.SIGKILL, signal.SIGSEGV}
+
+
+def sandboxed(command: list[str], cwd: str) -> list[str]:
+    """Wraps a tool so it sees only the toolchain, LIB_DIR and its working
+    directory, runs as nobody with a clean environment, and has no network."""
+    if SANDBOX == "none":
+        return command
+    wrapper = ["bwrap", "--unshare-all", "--unshare-user",
+               "--die-with-parent", "--new-session",
+               "--uid", str(SANDBOX_UID), "--gid", str(SANDBOX_UID),
+               "--clearenv", "--setenv", "HOME", cwd]
+    for name in SANDBOX_ENV:
+        if name in os.environ:
+            wrapper += ["--setenv", name, os.environ[name]]
+    for path in SANDBOX_PATHS:
+        wrapper += ["--ro-bind", path, path]
+    wrapper += ["--proc", "/proc", "--dev", "/dev", "--tmpfs", "/tmp",
+                "--ro-bind", LIB_DIR, LIB_DIR,
+                "--bind", cwd, cwd,
+                "--chdir", cwd, "--"]
+    return wrapper + command
+
+
+def killed_by(returncode: int) -> Optional[int]:
+    """The signal that ended a tool, if any. bwrap reports it as 128 + signal."""
+    if returncode < 0:
+        return -returncode
+    if SANDBOX == "bwrap" and returncode > 128:
+        return returncode - 128
+    return None
+
+
+def resource_error(command: list[str], returncode: int) -> Optional[ServiceError]:
+    killed = killed_by(returncode)
+    if killed not in RESOURCE_SIGNALS:
+        return None
+    return ServiceError(507, "resource_exhausted",
+                        f"{command[0]} ran out of CPU time, memory or disk "
+                        f"({signal.Signals(killed).name})")
+
+
+def run_limited(command: list[str], cwd: str,
+                capture_stdout: bool = True) -> subprocess.CompletedProcess:
+    """Runs a tool sandboxed and under the resource limits, in its own process
+    group so the whole toolchain is killed on timeout. Output is capped at
+    MAX_OUTPUT_BYTES."""
+    process = subprocess.Popen(
+        sandboxed(command, cwd),
+        cwd=cwd,
+        stdout=subprocess.PIPE if capture_stdout else subprocess.DEVNULL,
+        stderr=subprocess.PIPE,
+        preexec_fn=limit_resources,
+        start_new_session=True,
+    )
+    try:
+        stdout, stderr = process.communicate(timeout=WALL_TIME_SECONDS)
+    except subprocess.TimeoutExpired:
+        os.killpg(process.pid, signal.SIGKILL)
+        process.communicate()
+        raise ServiceError(408, "timeout",
+                           f"{command[0]} took longer than {WALL_TIME_SECONDS:g}s")
+    error = resource_error(command, process.returncode)
+    if error is not None:
+        raise error
+    return subprocess.CompletedProcess(command, process.returncode,
+                                       cap_output(stdout or b""), cap_output(stderr or b""))
+
+
+def run_streamed(command: list[str], cwd: str, job: "Job") -> subprocess.CompletedProcess:
+    """Like run_limited, but sends each output line to the job as it is printed
+    and kills the tool when the job is cancelled."""
+    process = subprocess.Popen(
+        sandboxed(command, cwd),
+        cwd=cwd,
+        stdout=subprocess.PIPE,
+        stderr=subprocess.PIPE,
+        preexec_fn=limit_resources,
+        start_new_session=True,
+    )
+    output = {"stdout": b"", "stderr": b""}
+    partial = {"stdout": b"", "stderr": b""}
+
+    def send(stream: str, line: bytes):
+        if len(output[stream]) <= MAX_OUTPUT_BYTES:
+            job.emit({"type": "output", "stream": stream,
+                      "line": line.decode("utf-8", "replace").rstrip("\r\n")})
+        output[stream] += line
+
+    def stop(error: ServiceError):
+        os.killpg(process.pid, signal.SIGKILL)
+        process.wait()
+        raise error
+
+    deadline = time.monotonic() + WALL_TIME_SECONDS
+    with selectors.DefaultSelector() as selector:
+        selector.register(process.stdout, selectors.EVENT_READ, "stdout")
+        selector.register(process.stderr, selectors.EVENT_READ, "stderr")
+        while selector.get_map():
+            if job.cancelled.is_set():
+                stop(cancelled_error())
+            remaining = deadline - time.monotonic()
+            if remaining <= 0:
+                stop(ServiceError(408, "timeout",
+                                  f"{command[0]} took longer than {WALL_TIME_SECONDS:g}s"))
+            for key, _ in selector.select(timeout=min(remaining, POLL_SECONDS)):
+                stream = key.data
+                chunk = os.read(key.fd, 4096)
+                if not chunk:
+                    selector.unregister(key.fileobj)
+                    if partial[stream]:
+                        send(stream, partial[stream])
+                    continue
+                *lines, partial[stream] = (partial[stream] + chunk).split(b"\n")
+                for line in lines:
+                    send(stream, line + b"\n")
+    process.stdout.close()
+    process.stderr.close()
+    process.wait()
+    error = resource_error(command, process.returncode)
+    if error is not None:
+        raise error
+    return subprocess.CompletedProcess(command, process.returncode,
+                                       cap_output(output["stdout"]),
+                                       cap_output(output["stderr"]))
+
+
+def cap_output(output: bytes) -> bytes:
+    if len(output) <= MAX_OUTPUT_BYTES:
+        return output
+    return output[:MAX_OUTPUT_BYTES] + b"\n[output truncated]\n"
+
 
 @dataclass
 class CompileData:
@@ -15,6 +260,9 @@ class CompileData:
     b64stdout: bytes
     b64stderr: bytes
     b64data: bytes
+    # Assembler listings and linker map, only when asked for.
+    b64listing: bytes = b""
+    b64map: bytes = b""
 
 
 @dataclass
@@ -29,68 +277,360 @@ class SyntaxCheckData:
 
 
 FILENAME = "main"
+# Bundled hardware library, always on the include path and linked in.
+LIB_DIR = os.path.join(os.path.dirname(os.path.abspath(__file__)), "lib")
+# Copied next to the project so build outputs stay in the temp dir.
+LIB_SOURCES = ["emu.c"]
+RESERVED_NAMES = {"emu.h", "emu.c"}
+
+
+def toolchain_version() -> str:
+    """Identifies everything besides the request that decides a build's output:
+    the zcc release, the bundled library and this service's flag mapping."""
+    if "TOOLCHAIN_VERSION" in os.environ:
+        return os.environ["TOOLCHAIN_VERSION"]
+    digest = hashlib.sha256()
+    try:
+        banner = subprocess.run(["zcc"], capture_output=True, timeout=10)
+        digest.update(banner.stdout + banner.stderr)
+    except (OSError, subprocess.TimeoutExpired):
+        pass
+    for path in sorted(glob.glob(os.path.join(LIB_DIR, "*"))) + [os.path.abspath(__file__)]:
+        with open(path, "rb") as f:
+            digest.update(f.read())
+    return digest.hexdigest()
+
+
+TOOLCHAIN_VERSION = toolchain_version()
+FILE_NAME_RE = re.compile(r"^[A-Za-z0-9_][A-Za-z0-9_.-]*\.[ch]$")
+
+
+class SourceFileModel(BaseModel):
+    name: str
+    b64data: str
+
+
+DEFINE_NAME_RE = re.compile(r"^[A-Za-z_][A-Za-z0-9_]*$")
+DEFINE_VALUE_RE = re.compile(r"^[A-Za-z0-9_.+-]*$")
+MAX_DEFINES = 16
+# The only flags options can turn into; anything else is rejected.
+OPTIMIZATION_FLAGS = {0: "-O0", 1: "-O1", 2: "-O2", 3: "-O3"}
+BACKEND_FLAGS = {"sccz80": "-clib=new", "sdcc": "-clib=sdcc_iy"}
+CRT_FLAGS = {"startup0": "-startup=0", "startup31": "-startup=31", "none": "--no-crt"}
+
+
+class DefineModel(BaseModel):
+    name: str
+    value: Optional[str] = None
+
+
+class CompileOptionsModel(BaseModel):
+    optimization: Literal[0, 1, 2, 3] = 3
+    backend: Literal["sccz80", "sdcc"] = "sccz80"
+    origin: int = Field(default=0, ge=0, le=0xFFFF)
+    crt: Literal["startup0", "startup31", "none"] = "startup0"
+    math: bool = True
+    listing: bool = False
+    defines: list[DefineModel] = Field(default_factory=list, max_length=MAX_DEFINES)
+
+
+def define_flags(defines: list[DefineModel]) -> list[str]:
+    flags = []
+    for define in defines:
+        if not DEFINE_NAME_RE.match(define.name):
+            raise invalid_request(f"Invalid define name: {define.name}")
+        if define.value is None:
+            flags.append(f"-D{define.name}")
+        elif DEFINE_VALUE_RE.match(define.value):
+            flags.append(f"-D{define.name}={define.value}")
+        else:
+            raise invalid_request(f"Invalid define value: {define.name}")
+    return flags
 
-def compile_data(b64data_in: str) -> CompileData:
-    data_in = base64.b64decode(b64data_in).decode("utf-8")
+
+def compile_flags(options: CompileOptionsModel) -> list[str]:
+    flags = [OPTIMIZATION_FLAGS[options.optimization],
+             CRT_FLAGS[options.crt],
+             BACKEND_FLAGS[options.backend],
+             f"-zorg={options.origin}"]
+    if options.listing:
+        flags += ["--list", "-m"]
+    return flags + define_flags(options.defines)
+
+
+# Preprocessor directives, assembler directives inside inline asm and
+# preprocessor operators that open a file by name.
+FILE_REFERENCE_RE = re.compile(
+    r"(?P<directive>(?:#|%:)[ \t]*(?:include_next|include|import|embed)\b)"
+    r"|(?P<operator>\b__has_(?:include_next|include|embed)\b)"
+    r"|(?P<asm>\b(?:include|binary|incbin)\b)",
+    re.IGNORECASE)
+FILE_OPERAND_RE = re.compile(r'\s*\(?\s*\\?(?:"([^"\n]*)|<([^>\n]*)>)')
+
+
+def strip_comments(code: str) -> str:
+    """Blanks out comments, leaving string and character literals alone, and
+    joins continued lines, as the preprocessor sees them."""
+    code = code.replace("??=", "#").replace("\\\r\n", "").replace("\\\n", "")
+    out = []
+    i = 0
+    while i < len(code):
+        if code.startswith("//", i):
+            end = code.find("\n", i)
+            i = len(code) if end < 0 else end
+            out.append(" ")
+        elif code.startswith("/*", i):
+            end = code.find("*/", i + 2)
+            i = len(code) if end < 0 else end + 2
+            out.append(" ")
+        elif code[i] in "\"'":
+            quote = code[i]
+            end = i + 1
+            while end < len(code) and code[end] not in (quote, "\n"):
+                end += 2 if code[end] == "\\" else 1
+            out.append(code[i:end + 1])
+            i = end + 1
+        else:
+            out.append(code[i])
+            i += 1
+    return "".join(out)
+
+
+def check_file_references(name: str, code: str):
+    """Rejects includes and embeds by absolute path, through `..` or named by a
+    macro, so sources cannot pull in files outside the project. The sandbox is
+    what keeps other files out of reach; this turns attempts into a clear 400."""
+    code = strip_comments(code)
+    for match in FILE_REFERENCE_RE.finditer(code):
+        operand = FILE_OPERAND_RE.match(code, match.end())
+        if operand is None:
+            if match.group("asm"):
+                # A plain word or an assembler include of a symbol.
+                continue
+            raise invalid_request(f"{name}: {match.group(0).strip()} needs a quoted "
+                                  f"or <bracketed> file name")
+        path = operand.group(1) if operand.group(1) is not None else operand.group(2)
+        parts = re.split(r"[\\/]", path)
+        if path.startswith(("/", "\\")) or ":" in path or ".." in parts:
+            raise invalid_request(f"{name}: cannot include {path!r}, only files "
+                                  f"in the project and the bundled headers")
+
+
+def decode_source(name: str, b64data: str) -> str:
+    try:
+        return base64.b64decode(b64data, validate=True).decode("utf-8")
+    except ValueError:
+        raise invalid_request(f"{name} is not base64-encoded UTF-8")
+
+
+def write_project(files: list[SourceFileModel], temp_dir: str) -> list[str]:
+    """Writes the project files into temp_dir and returns the C sources."""
+    names = [file.name for file in files]
+    if len(set(names)) != len(names):
+        raise invalid_request("Duplicate file names")
+    for name in names:
+        if not FILE_NAME_RE.match(name) or name in RESERVED_NAMES:
+            raise invalid_request(f"Invalid file name: {name}")
+    sources = [name for name in names if name.endswith(".c")]
+    if not sources:
+        raise invalid_request("No C source file")
+    codes = {file.name: decode_source(file.name, file.b64data) for file in files}
+    for name, code in codes.items():
+        check_file_references(name, code)
+    for name, code in codes.items():
+        with open(os.path.join(temp_dir, name), "w") as f:
+            f.write(code)
+    return sources
+
+
+def compile_data(files: list[SourceFileModel], options: CompileOptionsModel,
+                 job: Optional["Job"] = None) -> CompileData:
+    """Compiles each source to an object, then links them. With a job, phases
+    and output lines are sent to it as the build goes."""
+    flags = compile_flags(options)
     with TemporaryDirectory() as temp_dir:
-        with open(f"{temp_dir}/{FILENAME}.c", "w") as f:
-            f.write(data_in)
-        command = ["zcc", "+z80", "-vn", "-O3", "-startup=0", "-clib=new",
-                   "-o", f"{FILENAME}.out", "-create-app",
-                   f"{FILENAME}.c", "-lm"]
-        result = subprocess.run(
-            command,
-            cwd=temp_dir,
-            stdout=subprocess.PIPE,
-            stderr=subprocess.PIPE,
-        )
+        sources = write_project(files, temp_dir)
+        for source in LIB_SOURCES:
+            shutil.copy(os.path.join(LIB_DIR, source), temp_dir)
+
+        def run(command: list[str]) -> subprocess.CompletedProcess:
+            if job is None:
+                return run_limited(command, cwd=temp_dir)
+            return run_streamed(command, temp_dir, job)
+
+        stdout = b""
+        stderr = b""
+        objects = []
+        for source in [*sources, *LIB_SOURCES]:
+            if job is not None:
+                job.emit({"type": "phase", "phase": "compiling", "file": source})
+            obj = source[:-len(".c")] + ".o"
+            result = run(["zcc", "+z80", "-vn", *flags, f"-I{LIB_DIR}",
+                          "-c", "-o", obj, source])
+            stdout += result.stdout
+            stderr += result.stderr
+            if result.returncode != 0:
+                return CompileData(rc=result.returncode,
+                                   b64stdout=base64.b64encode(stdout),
+                                   b64stderr=base64.b64encode(stderr),
+                                   b64data=b"")
+            objects.append(obj)
+        if job is not None:
+            job.emit({"type": "phase", "phase": "linking"})
+        command = ["zcc", "+z80", "-vn", *flags,
+                   "-o", f"{FILENAME}.out", "-create-app", *objects]
+        if options.math:
+            command.append("-lm")
+        result = run(command)
+        stdout += result.stdout
+        stderr += result.stderr
         data: bytes = b""
         try:
             with open(f"{temp_dir}/{FILENAME}.bin", "rb") as f:
                 data = f.read()
         except  FileNotFoundError:
             data = b""
+        listing = b""
+        linker_map = b""
+        if options.listing:
+            listing = read_outputs(temp_dir, "*.lis")
+            linker_map = read_outputs(temp_dir, "*.map")
         return CompileData(rc=result.returncode,
-                           b64stdout=base64.b64encode(result.stdout),
-                           b64stderr=base64.b64encode(result.stderr),
-                           b64data=base64.b64encode(data))
+                           b64stdout=base64.b64encode(stdout),
+                           b64stderr=base64.b64encode(stderr),
+                           b64data=base64.b64encode(data),
+                           b64listing=base64.b64encode(listing),
+                           b64map=base64.b64encode(linker_map))
+
+
+def read_outputs(temp_dir: str, pattern: str) -> bytes:
+    """Concatenates build outputs, each after a `; ---- name ----` header."""
+    output = b""
+    for path in sorted(glob.glob(os.path.join(temp_dir, pattern))):
+        with open(path, "rb") as f:
+            output += f"; ---- {os.path.basename(path)} ----\n".encode("utf-8")
+            output += f.read()
+    return output
+
+
+class Job:
+    """A compile running in the background. Its events are kept so a follower
+    sees all of them however late it starts reading."""
+
+    def __init__(self):
+        self.id = uuid.uuid4().hex
+        self.events: list[dict] = []
+        self.finished_at: Optional[float] = None
+        self.cancelled = threading.Event()
+        self.changed = threading.Condition()
+
+    def emit(self, event: dict):
+        with self.changed:
+            self.events.append(event)
+            self.changed.notify_all()
+
+    def finish(self, event: dict):
+        with self.changed:
+            self.events.append(event)
+            self.finished_at = time.monotonic()
+            self.changed.notify_all()
+
+    def follow(self) -> Iterator[dict]:
+        """Yields every event, waiting for new ones until the job finishes."""
+        index = 0
+        while True:
+            with self.changed:
+                while index == len(self.events) and self.finished_at is None:
+                    self.changed.wait()
+                pending = self.events[index:]
+                index = len(self.events)
+                finished = self.finished_at is not None
+            yield from pending
+            if finished and index == len(self.events):
+                return
+
+
+jobs: dict[str, Job] = {}
+jobs_lock = threading.Lock()
+
+
+def run_job(job: Job, files: list[SourceFileModel], options: CompileOptionsModel):
+    try:
+        with worker_slot(job.cancelled):
+            result = compile_data(files, options, job)
+        fields = {name: value.decode() if isinstance(value, bytes) else value
+                  for name, value in asdict(result).items()}
+        job.finish({"type": "result", **fields})
+    except ServiceError as error:
+        job.finish({"type": "error", "code": error.code, "message": error.message})
+    except Exception as error:
+        job.finish({"type": "error", "code": "internal", "message": str(error)})
+
+
+def start_job(files: list[SourceFileModel], options: CompileOptionsModel) -> Job:
+    compile_flags(options)
+    now = time.monotonic()
+    with jobs_lock:
+        for job_id, job in list(jobs.items()):
+            if job.finished_at is not None and now - job.finished_at > JOB_TTL_SECONDS:
+                del jobs[job_id]
+        if len(jobs) >= MAX_JOBS:
+            raise ServiceError(503, "busy", "Too many compilations queued, try again")
+        job = Job()
+        jobs[job.id] = job
+    job.emit({"type": "phase", "phase": "queued"})
+    threading.Thread(target=run_job, args=(job, files, options), daemon=True).start()
+    return job
+
+
+def find_job(job_id: str) -> Job:
+    with jobs_lock:
+        job = jobs.get(job_id)
+    if job is None:
+        raise ServiceError(404, "not_found", "No such compilation")
+    return job
+
+
+def job_events(job: Job) -> Iterator[bytes]:
+    """The job's events as JSON lines. A follower that goes away before the job
+    finishes cancels it."""
+    try:
+        for event in job.follow():
+            yield (json.dumps(event) + "\n").encode("utf-8")
+    finally:
+        if job.finished_at is None:
+            job.cancelled.set()
 
 
 def format_str(b64data_in: str) -> FormatData:
-    data_in = base64.b64decode(b64data_in).decode("utf-8")
-    with NamedTemporaryFile(mode="w+", suffix=".c") as f:
-        f.write(data_in)
-        f.flush()
+    data_in = decode_source("code", b64data_in)
+    with TemporaryDirectory() as temp_dir:
+        path = os.path.join(temp_dir, f"{FILENAME}.c")
+        with open(path, "w") as f:
+            f.write(data_in)
         command = [
             "astyle",
             "--style=attach",
             "--indent=spaces=4",
             "--suffix=none",
-            f.name
+            path
         ]
-        result = subprocess.run(
-            command,
-            stdout=subprocess.DEVNULL,
-            stderr=subprocess.DEVNULL,
-        )
+        result = run_limited(command, cwd=temp_dir, capture_stdout=False)
         data = b""
         if result.returncode == 0:
-            f.seek(0)
-            data = f.read().encode("utf-8")
+            with open(path, "rb") as f:
+                data = f.read()
         return FormatData(b64data=base64.b64encode(data))
 
 
-def syntax_check(b64data_in: str) -> SyntaxCheckData:
-    data_in = base64.b64decode(b64data_in).decode("utf-8")
-    with NamedTemporaryFile(mode="w+", suffix=".c") as f:
-        f.write(data_in)
-        f.flush()
-        command = ["gcc", "-fsyntax-only", f.name]
-        result = subprocess.run(
-            command,
-            stdout=subprocess.DEVNULL,
-            stderr=subprocess.PIPE,
-        )
+def syntax_check(files: list[SourceFileModel], options: CompileOptionsModel) -> SyntaxCheckData:
+    """Checks the project with gcc, with the options' defines so code that
+    depends on them is checked as it would build."""
+    flags = define_flags(options.defines)
+    with TemporaryDirectory() as temp_dir:
+        sources = write_project(files, temp_dir)
+        command = ["gcc", "-fsyntax-only", f"-I{LIB_DIR}", *flags, *sources]
+        result = run_limited(command, cwd=temp_dir, capture_stdout=False)
         return SyntaxCheckData(rc=result.returncode,
                                b64stderr=base64.b64encode(result.stderr))
 
@@ -99,16 +639,48 @@ class RequestDataModel(BaseModel):
     b64data: str
 
 
+class ProjectRequestModel(BaseModel):
+    files: list[SourceFileModel]
+
+
+class CompileRequestModel(ProjectRequestModel):
+    options: CompileOptionsModel = Field(default_factory=CompileOptionsModel)
+
+
+@app.get("/version")
+def version_endpoint():
+    return {"version": TOOLCHAIN_VERSION}
+
+
 @app.post("/compile")
-def compile_data_endpoint(item: RequestDataModel):
-    return compile_data(item.b64data)
+def compile_data_endpoint(item: CompileRequestModel):
+    with worker_slot():
+        return compile_data(item.files, item.options)
+
+
+@app.post("/jobs")
+def start_job_endpoint(item: CompileRequestModel):
+    return {"id": start_job(item.files, item.options).id}
+
+
+@app.get("/jobs/{job_id}/events")
+def job_events_endpoint(job_id: str):
+    return StreamingResponse(job_events(find_job(job_id)), media_type="application/x-ndjson")
+
+
+@app.post("/jobs/{job_id}/cancel")
+def cancel_job_endpoint(job_id: str):
+    find_job(job_id).cancelled.set()
+    return {"id": job_id}
 
 
 @app.post("/format")
 def format_data_endpoint(item: RequestDataModel):
-    return format_str(item.b64data)
+    with worker_slot():
+        return format_str(item.b64data)
 
 
 @app.post("/syntax_check")
-def syntax_check_endpoint(item: RequestDataModel):
-    return syntax_check(item.b64data)
+def syntax_check_endpoint(item: CompileRequestModel):
+    with worker_slot():
+        return syntax_check(item.files, item.options)
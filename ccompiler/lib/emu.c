@@ -0,0 +1,57 @@
+#include "emu.h"
+
+void set_pixel(uint16_t x, uint16_t y, uint8_t color) {
+    if (x < DISPLAY_WIDTH && y < DISPLAY_HEIGHT) {
+        emu_display[y][x] = color;
+    }
+}
+
+uint8_t get_pixel(uint16_t x, uint16_t y) {
+    if (x < DISPLAY_WIDTH && y < DISPLAY_HEIGHT) {
+        return emu_display[y][x];
+    }
+    return 0;
+}
+
+void clear_display(uint8_t color) {
+    for (uint16_t y = 0; y < DISPLAY_HEIGHT; y++) {
+        for (uint16_t x = 0; x < DISPLAY_WIDTH; x++) {
+            emu_display[y][x] = color;
+        }
+    }
+}
+
+void fill_rect(uint16_t x, uint16_t y, uint16_t width, uint16_t height, uint8_t color) {
+    for (uint16_t row = y; row < y + height && row < DISPLAY_HEIGHT; row++) {
+        draw_hline(x, row, width, color);
+    }
+}
+
+void draw_hline(uint16_t x, uint16_t y, uint16_t width, uint8_t color) {
+    if (y >= DISPLAY_HEIGHT) {
+        return;
+    }
+    for (uint16_t column = x; column < x + width && column < DISPLAY_WIDTH; column++) {
+        emu_display[y][column] = color;
+    }
+}
+
+void draw_vline(uint16_t x, uint16_t y, uint16_t height, uint8_t color) {
+    if (x >= DISPLAY_WIDTH) {
+        return;
+    }
+    for (uint16_t row = y; row < y + height && row < DISPLAY_HEIGHT; row++) {
+        emu_display[row][x] = color;
+    }
+}
+
+void halt(void) {
+#if defined(__SDCC)
+    __asm__("halt");
+#elif defined(__SCCZ80)
+    asm("halt");
+#else
+    for (;;) {
+    }
+#endif
+}
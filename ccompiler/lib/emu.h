@@ -0,0 +1,34 @@
+#ifndef EMU_H
+#define EMU_H
+
+#include <stdint.h>
+
+/* Memory mapped display, one RGB332 byte per pixel, row by row. */
+#define DISPLAY_ADDRESS 0x4000
+#define DISPLAY_WIDTH 192
+#define DISPLAY_HEIGHT 128
+
+#define emu_display (*(volatile uint8_t (*)[DISPLAY_HEIGHT][DISPLAY_WIDTH])DISPLAY_ADDRESS)
+
+/* 3 bits red, 3 bits green, 2 bits blue. */
+#define RGB(r, g, b) ((uint8_t)(((r) & 0xE0) | (((g) & 0xE0) >> 3) | (((b) & 0xC0) >> 6)))
+
+#define COLOR_BLACK RGB(0, 0, 0)
+#define COLOR_WHITE RGB(255, 255, 255)
+#define COLOR_RED RGB(255, 0, 0)
+#define COLOR_GREEN RGB(0, 255, 0)
+#define COLOR_BLUE RGB(0, 0, 255)
+
+/* Pixels outside the display are ignored. */
+void set_pixel(uint16_t x, uint16_t y, uint8_t color);
+/* Returns 0 outside the display. */
+uint8_t get_pixel(uint16_t x, uint16_t y);
+void clear_display(uint8_t color);
+void fill_rect(uint16_t x, uint16_t y, uint16_t width, uint16_t height, uint8_t color);
+void draw_hline(uint16_t x, uint16_t y, uint16_t width, uint8_t color);
+void draw_vline(uint16_t x, uint16_t y, uint16_t height, uint8_t color);
+
+/* Stops the CPU; the emulator reports the program as halted. */
+void halt(void);
+
+#endif
@@ -1,17 +1,5 @@
 #include <stdint.h>
-#include <math.h>
-#define WIDTH 256
-#define HEIGHT 192
-
-typedef uint8_t DISPLAY[HEIGHT][WIDTH];
-DISPLAY *display = (DISPLAY *)0x2000;
-uint16_t last_index = 0;
-
-void set_pixel(uint16_t x, uint16_t y, uint8_t color){
-    if (x < WIDTH && y < HEIGHT) {
-            (*display)[y][x] = color;
-        }
-}
+#include "emu.h"
 
 void set_circle(int16_t x0, int16_t y0, int16_t radius, uint8_t color) {
     int16_t f = 1 - radius;
@@ -46,24 +34,16 @@ void set_circle(int16_t x0, int16_t y0, int16_t radius, uint8_t color) {
     }
 }
 
-void clear_screen(uint8_t color){
-    for(uint16_t y = 0; y < HEIGHT; y++){
-        for(uint16_t x = 0; x < WIDTH; x++){
-            set_pixel(x, y, color);
-        }
-    }
-}
-
 int main(void) {
-    clear_screen(0x74);
+    clear_display(0x74);
     uint8_t x = 0;
     uint8_t y = 0;
     uint8_t z = 3;
     for(;;){
         uint8_t color = (x&0xF0 | (y>>4))+z;
         set_circle(x, y, color>>1, color);
-        x= (x+5)%WIDTH;
-        y= (y+7)%HEIGHT;
+        x= (x+5)%DISPLAY_WIDTH;
+        y= (y+7)%DISPLAY_HEIGHT;
         z+=3;
     }
     return 0;
@@ -1,24 +1,7 @@
 #include <stdint.h>
 #include <math.h>
-#define WIDTH 256
-#define HEIGHT 192
+#include "emu.h"
 //OPTIMIZE by SIN LUT
-typedef uint8_t DISPLAY[HEIGHT][WIDTH];
-DISPLAY *display = (DISPLAY *)0x3000;
-
-void set_pixel(uint16_t x, uint16_t y, uint8_t color){
-    if (x < WIDTH && y < HEIGHT) {
-            (*display)[y][x] = color;
-        }
-}
-
-void clear_display(uint8_t color){
-    for (uint16_t y = 0; y < HEIGHT; y++) {
-        for (uint16_t x = 0; x < WIDTH; x++) {
-            (*display)[y][x] = color;
-        }
-    }
-}
 
 void draw_wave(uint8_t *y_cache,uint8_t color){
     uint8_t x = 0;
@@ -45,7 +28,7 @@ void draw_wave(uint8_t *y_cache,uint8_t color){
 void calc_wave(uint8_t amplitude, float frequency, uint8_t *y_cache){
     uint8_t x = 0;
     do {
-        y_cache[x] = (uint8_t)(amplitude * sinf((float)x * frequency / 10.0f) + (HEIGHT / 2));
+        y_cache[x] = (uint8_t)(amplitude * sinf((float)x * frequency / 10.0f) + (DISPLAY_HEIGHT / 2));
         x++;
     } while (x != 0);
 }
@@ -56,8 +39,8 @@ void draw_loop(void){
     clear_display(background_color);
     uint8_t amplitude = 20;
     float frequency = 1.0f;
-    uint8_t y_cache[WIDTH];
-    uint8_t y_cache_clone[WIDTH];
+    uint8_t y_cache[256];
+    uint8_t y_cache_clone[256];
     uint8_t* y_cache_ptr = y_cache;
     uint8_t* y_cache_clone_ptr = y_cache_clone;
     for (;;) {
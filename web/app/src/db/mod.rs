@@ -26,7 +26,17 @@ pub async fn establish_connection() -> DbPool {
 }
 
 use crate::db::models::user::UserData;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::compile_cache::CompileCache;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::compile_jobs::CompileJobs;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::compiler_client::CompilerClient;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::rate_limit::RateLimiter;
 use leptos::prelude::LeptosOptions;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
 
 #[cfg(not(target_arch = "wasm32"))]
 #[derive(Clone)]
@@ -34,4 +44,13 @@ pub struct AppState {
     pub leptos_options: LeptosOptions,
     pub pool: DbPool,
     pub reqwest_client: reqwest::Client,
+    /// Compile and format requests per user.
+    pub compile_limiter: Arc<RateLimiter>,
+    /// Syntax checks per user, sent while typing so allowed more often.
+    pub syntax_check_limiter: Arc<RateLimiter>,
+    pub compile_cache: Arc<CompileCache>,
+    /// Owners of running streamed compiles.
+    pub compile_jobs: Arc<CompileJobs>,
+    /// The C compiler service; a fake in tests.
+    pub compiler: Arc<dyn CompilerClient>,
 }
@@ -0,0 +1,233 @@
+use super::disassembler::{region_at, DataRegion};
+use super::flow::{decode, Decoded, Flow};
+use super::symbols::SymbolTable;
+use emu_lib::memory::{Memory, MemoryDevice};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Upper bound on decoded instructions so a run through empty memory stays cheap.
+const MAX_INSTRUCTIONS: usize = 20_000;
+const RST_VECTORS: [u16; 7] = [0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start: u16,
+    pub instructions: Vec<Decoded>,
+    pub successors: Vec<u16>,
+    pub call: Option<u16>,
+}
+
+impl BasicBlock {
+    pub fn end(&self) -> u16 {
+        self.instructions
+            .last()
+            .map(|instruction| instruction.address)
+            .unwrap_or(self.start)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Routine {
+    pub entry: u16,
+    pub name: String,
+    pub blocks: Vec<u16>,
+    pub callees: BTreeSet<u16>,
+    pub callers: BTreeSet<u16>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Analysis {
+    pub blocks: BTreeMap<u16, BasicBlock>,
+    pub routines: BTreeMap<u16, Routine>,
+    pub truncated: bool,
+}
+
+impl Analysis {
+    pub fn routine_blocks(&self, entry: u16) -> Vec<&BasicBlock> {
+        self.routines
+            .get(&entry)
+            .map(|routine| {
+                routine
+                    .blocks
+                    .iter()
+                    .filter_map(|start| self.blocks.get(start))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Reset and interrupt entry points plus every symbol. Vectors that only hold
+/// zeroes are unused and would otherwise decode as one long run of `NOP`s.
+pub fn entry_points(memory: &Memory, symbols: &SymbolTable) -> BTreeSet<u16> {
+    let mut entries = BTreeSet::from([0x0000]);
+    entries.extend(
+        RST_VECTORS
+            .iter()
+            .filter(|vector| memory.read_8(**vector).map_or(false, |op| op != 0))
+            .copied(),
+    );
+    entries.extend(symbols.iter().map(|(_, address)| *address));
+    entries
+}
+
+/// Recursive-descent disassembly: follows jumps and calls from the entry points,
+/// then cuts the reached code into basic blocks and groups them into routines.
+pub fn analyse(
+    memory: &Memory,
+    regions: &[DataRegion],
+    symbols: &SymbolTable,
+    entries: BTreeSet<u16>,
+) -> Analysis {
+    let mut visited: BTreeMap<u16, Decoded> = BTreeMap::new();
+    let mut leaders: BTreeSet<u16> = entries.clone();
+    let mut routine_entries = entries.clone();
+    let mut work: Vec<u16> = entries.into_iter().collect();
+    let mut truncated = false;
+
+    while let Some(start) = work.pop() {
+        let mut pc = start;
+        loop {
+            if visited.contains_key(&pc) || region_at(regions, pc).is_some() {
+                break;
+            }
+            if visited.len() >= MAX_INSTRUCTIONS {
+                truncated = true;
+                break;
+            }
+            let Some(decoded) = decode(memory, pc) else {
+                break;
+            };
+            let next = decoded.next();
+            let flow = decoded.flow;
+            visited.insert(pc, decoded);
+            match flow {
+                Flow::Next => {
+                    pc = next;
+                    continue;
+                }
+                Flow::Jump {
+                    target,
+                    conditional,
+                } => {
+                    leaders.insert(target);
+                    work.push(target);
+                    if !conditional {
+                        break;
+                    }
+                }
+                Flow::Call { target, .. } => {
+                    leaders.insert(target);
+                    routine_entries.insert(target);
+                    work.push(target);
+                }
+                Flow::Return { conditional } => {
+                    if !conditional {
+                        break;
+                    }
+                }
+                Flow::IndirectJump | Flow::Halt => break,
+            }
+            leaders.insert(next);
+            pc = next;
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &leader in leaders.iter().filter(|leader| visited.contains_key(leader)) {
+        let mut block = BasicBlock {
+            start: leader,
+            instructions: vec![],
+            successors: vec![],
+            call: None,
+        };
+        let mut pc = leader;
+        while let Some(decoded) = visited.get(&pc) {
+            block.instructions.push(decoded.clone());
+            let next = decoded.next();
+            match decoded.flow {
+                Flow::Next => {
+                    if leaders.contains(&next) || !visited.contains_key(&next) {
+                        block.successors.push(next);
+                        break;
+                    }
+                    pc = next;
+                    continue;
+                }
+                Flow::Jump {
+                    target,
+                    conditional,
+                } => {
+                    block.successors.push(target);
+                    if conditional {
+                        block.successors.push(next);
+                    }
+                }
+                Flow::Call { target, .. } => {
+                    block.call = Some(target);
+                    block.successors.push(next);
+                }
+                Flow::Return { conditional: true } => block.successors.push(next),
+                Flow::Return { .. } | Flow::IndirectJump | Flow::Halt => {}
+            }
+            break;
+        }
+        block
+            .successors
+            .retain(|successor| visited.contains_key(successor));
+        blocks.insert(leader, block);
+    }
+
+    let mut routines = BTreeMap::new();
+    for &entry in routine_entries.iter().filter(|entry| blocks.contains_key(entry)) {
+        let mut members = BTreeSet::new();
+        let mut work = vec![entry];
+        while let Some(start) = work.pop() {
+            if !members.insert(start) {
+                continue;
+            }
+            if let Some(block) = blocks.get(&start) {
+                // Other routine entries reached by falling through stay separate.
+                work.extend(
+                    block
+                        .successors
+                        .iter()
+                        .filter(|successor| !routine_entries.contains(successor))
+                        .copied(),
+                );
+            }
+        }
+        let callees = members
+            .iter()
+            .filter_map(|start| blocks.get(start).and_then(|block| block.call))
+            .collect();
+        let name = match symbols.nearest(entry) {
+            Some((name, address)) if address == entry => name.to_string(),
+            _ => format!("sub_{:04X}", entry),
+        };
+        routines.insert(
+            entry,
+            Routine {
+                entry,
+                name,
+                blocks: members.into_iter().collect(),
+                callees,
+                callers: BTreeSet::new(),
+            },
+        );
+    }
+    let edges: Vec<(u16, u16)> = routines
+        .values()
+        .flat_map(|routine| routine.callees.iter().map(|callee| (routine.entry, *callee)))
+        .collect();
+    for (caller, callee) in edges {
+        if let Some(routine) = routines.get_mut(&callee) {
+            routine.callers.insert(caller);
+        }
+    }
+
+    Analysis {
+        blocks,
+        routines,
+        truncated,
+    }
+}
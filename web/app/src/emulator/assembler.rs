@@ -0,0 +1,154 @@
+use super::symbols::{parse_number, SymbolTable};
+use emu_lib::cpu::instruction::InstructionParser;
+use emu_lib::cpu::z80::parser::Z80_PARSER;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsmError {
+    pub line_number: usize,
+    pub line: String,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AsmOutput {
+    pub bytes: Vec<u8>,
+    pub symbols: SymbolTable,
+    /// Address of the first byte emitted by each 1-based source line.
+    pub lines: BTreeMap<usize, u16>,
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split("//").next().unwrap_or("").trim()
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a leading `label:` off a line, returning the label and the remainder.
+fn split_label(line: &str) -> (Option<&str>, &str) {
+    if let Some((label, rest)) = line.split_once(':') {
+        let label = label.trim();
+        if is_label(label) {
+            return (Some(label), rest.trim());
+        }
+    }
+    (None, line)
+}
+
+/// Parses the operands of `DB`: comma separated numbers and quoted strings.
+fn parse_db(operands: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    let mut rest = operands.trim();
+    loop {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let (text, tail) = quoted
+                .split_once('"')
+                .ok_or_else(|| "Unterminated string".to_string())?;
+            bytes.extend(text.bytes());
+            rest = tail.trim_start();
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let item = rest[..end].trim();
+            let value = parse_number(item)
+                .filter(|value| *value <= 0xFF)
+                .ok_or_else(|| format!("Invalid byte \"{}\"", item))?;
+            bytes.push(value as u8);
+            rest = &rest[end..];
+        }
+        match rest.strip_prefix(',') {
+            Some(tail) => rest = tail.trim_start(),
+            None if rest.is_empty() => return Ok(bytes),
+            None => return Err(format!("Unexpected \"{}\"", rest)),
+        }
+    }
+}
+
+/// Splits a directive name off a line, e.g. `DB 1, 2` into `("DB", "1, 2")`.
+fn split_directive(line: &str) -> (String, &str) {
+    let (name, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    (name.to_ascii_uppercase(), operands.trim())
+}
+
+/// Assembles one 1-based source line into `output`.
+fn assemble_line(output: &mut AsmOutput, line_number: usize, raw: &str) -> Result<(), AsmError> {
+    let line = strip_comment(raw);
+    if line.is_empty() {
+        return Ok(());
+    }
+    let error = |message: String| AsmError {
+        line_number,
+        line: line.to_string(),
+        message,
+    };
+    let (label, rest) = split_label(line);
+    if let Some(label) = label {
+        if output.symbols.get(label).is_some() {
+            return Err(error(format!("Duplicate label \"{}\"", label)));
+        }
+        output
+            .symbols
+            .insert(label.to_string(), output.bytes.len() as u16);
+    }
+    if rest.is_empty() {
+        return Ok(());
+    }
+    let address = output.bytes.len() as u16;
+    match split_directive(rest) {
+        (name, operands) if name == "DB" => {
+            output.bytes.extend(parse_db(operands).map_err(error)?);
+            output.lines.insert(line_number, address);
+            return Ok(());
+        }
+        (name, operands) if name == "ORG" => {
+            let address = parse_number(operands)
+                .ok_or_else(|| error(format!("Invalid address \"{}\"", operands)))?;
+            if (address as usize) < output.bytes.len() {
+                return Err(error(format!(
+                    "ORG {:#06X} is before the current address {:#06X}",
+                    address,
+                    output.bytes.len()
+                )));
+            }
+            output.bytes.resize(address as usize, 0);
+            return Ok(());
+        }
+        _ => {}
+    }
+    match Z80_PARSER.ins_from_asm_string(rest) {
+        Ok(instruction) => {
+            output.bytes.extend(instruction.to_bytes());
+            output.lines.insert(line_number, address);
+            Ok(())
+        }
+        Err(_) => Err(error(format!("Invalid instruction: \"{}\"", rest))),
+    }
+}
+
+/// Assembles the editor buffer one instruction per line. Programs are loaded at
+/// address 0, so a label's address is the number of bytes emitted before it.
+/// `ORG` pads with zeroes up to an address and `DB` emits raw bytes.
+pub fn assemble(source: &str) -> Result<AsmOutput, AsmError> {
+    let mut output = AsmOutput::default();
+    for (index, raw) in source.lines().enumerate() {
+        assemble_line(&mut output, index + 1, raw)?;
+    }
+    Ok(output)
+}
+
+/// Every line [`assemble`] would reject, not just the first. Lines in error are
+/// skipped, so later addresses may be off but each line is still checked.
+pub fn check(source: &str) -> Vec<AsmError> {
+    let mut output = AsmOutput::default();
+    source
+        .lines()
+        .enumerate()
+        .filter_map(|(index, raw)| assemble_line(&mut output, index + 1, raw).err())
+        .collect()
+}
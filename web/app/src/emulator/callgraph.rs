@@ -0,0 +1,274 @@
+use super::analysis::{analyse, entry_points, Analysis, BasicBlock};
+use super::{emu_style, EmulatorCfgContext, EmulatorContext};
+use leptos::prelude::*;
+
+const BLOCK_WIDTH: u32 = 230;
+const LINE_HEIGHT: u32 = 14;
+const BLOCK_PADDING: u32 = 6;
+const BLOCK_GAP: u32 = 16;
+const LANE_WIDTH: u32 = 8;
+
+struct BlockLayout {
+    start: u16,
+    y: u32,
+    height: u32,
+    lines: Vec<String>,
+}
+
+fn layout_blocks(blocks: &[&BasicBlock]) -> Vec<BlockLayout> {
+    let mut y = BLOCK_GAP / 2;
+    blocks
+        .iter()
+        .map(|block| {
+            let lines: Vec<String> = block
+                .instructions
+                .iter()
+                .map(|instruction| format!("{:04X}  {}", instruction.address, instruction.text))
+                .collect();
+            let height = lines.len() as u32 * LINE_HEIGHT + BLOCK_PADDING * 2;
+            let layout = BlockLayout {
+                start: block.start,
+                y,
+                height,
+                lines,
+            };
+            y += height + BLOCK_GAP;
+            layout
+        })
+        .collect()
+}
+
+/// Draws a routine's basic blocks top to bottom in address order, with each edge
+/// routed through its own lane to the right of the blocks.
+fn cfg_view(
+    analysis: &Analysis,
+    entry: u16,
+    show_disasm: impl Fn(u16) + Copy + Send + Sync + 'static,
+) -> impl IntoView {
+    let blocks = analysis.routine_blocks(entry);
+    let layouts = layout_blocks(&blocks);
+    let position = |start: u16| layouts.iter().find(|layout| layout.start == start);
+    let mut edges = vec![];
+    for (block, from) in blocks.iter().zip(layouts.iter()) {
+        for successor in &block.successors {
+            if let Some(to) = position(*successor) {
+                edges.push((from.y + from.height, to.y));
+            }
+        }
+    }
+    let height = layouts
+        .last()
+        .map(|layout| layout.y + layout.height + BLOCK_GAP)
+        .unwrap_or(BLOCK_GAP);
+    let width = BLOCK_WIDTH + (edges.len() as u32 + 2) * LANE_WIDTH;
+    let edges = edges
+        .into_iter()
+        .enumerate()
+        .map(|(lane, (from_y, to_y))| {
+            let lane_x = BLOCK_WIDTH + (lane as u32 + 1) * LANE_WIDTH;
+            let from_y = from_y - BLOCK_PADDING;
+            let to_y = to_y + BLOCK_PADDING;
+            let path = format!(
+                "M {} {} H {} V {} H {}",
+                BLOCK_WIDTH, from_y, lane_x, to_y, BLOCK_WIDTH
+            );
+            let arrow = format!(
+                "{},{} {},{} {},{}",
+                BLOCK_WIDTH,
+                to_y,
+                BLOCK_WIDTH + 5,
+                to_y - 3,
+                BLOCK_WIDTH + 5,
+                to_y + 3
+            );
+            view! {
+                <path d=path />
+                <polygon points=arrow />
+            }
+        })
+        .collect_view();
+    let nodes = layouts
+        .into_iter()
+        .map(|layout| {
+            let start = layout.start;
+            let text = layout
+                .lines
+                .into_iter()
+                .enumerate()
+                .map(|(index, line)| {
+                    view! {
+                        <text x=BLOCK_PADDING y=layout.y + BLOCK_PADDING + (index as u32 + 1) * LINE_HEIGHT - 3>
+                            {line}
+                        </text>
+                    }
+                })
+                .collect_view();
+            view! {
+                <g class=emu_style::cfgblock on:click=move |_| show_disasm(start)>
+                    <rect x=0 y=layout.y width=BLOCK_WIDTH height=layout.height />
+                    {text}
+                </g>
+            }
+        })
+        .collect_view();
+    view! {
+        <svg width=width height=height viewBox=format!("0 0 {} {}", width, height)>
+            <g class=emu_style::cfgedges>{edges}</g>
+            {nodes}
+        </svg>
+    }
+}
+
+#[island]
+pub fn CallGraph() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let analysis = RwSignal::new(None::<Analysis>);
+    let selected = RwSignal::new(None::<u16>);
+    let show_disasm = move |address: u16| {
+        emu_cfg_ctx.update(|cfg| cfg.disasm_config.start = Some(address));
+    };
+    let run_analysis = move |_| {
+        let result = emu_ctx.with_untracked(|emu| {
+            emu_cfg_ctx.with_untracked(|cfg| {
+                let entries = entry_points(&emu.emu.memory, &cfg.symbols);
+                analyse(
+                    &emu.emu.memory,
+                    &cfg.editor.data_regions,
+                    &cfg.symbols,
+                    entries,
+                )
+            })
+        });
+        emu_cfg_ctx.update(|cfg| {
+            let summary = format!(
+                "Found {} routines in {} basic blocks",
+                result.routines.len(),
+                result.blocks.len()
+            );
+            if result.truncated {
+                cfg.logstore.log_warning(
+                    "Analysis truncated",
+                    format!("{}, stopped at the instruction limit", summary),
+                );
+            } else {
+                cfg.logstore.log_info("Analysis complete", summary);
+            }
+        });
+        if selected
+            .get_untracked()
+            .is_some_and(|entry| !result.routines.contains_key(&entry))
+        {
+            selected.set(None);
+        }
+        analysis.set(Some(result));
+    };
+    let select = move |entry: u16| {
+        selected.set(Some(entry));
+        show_disasm(entry);
+    };
+    let routine_link = move |entry: u16, name: String| {
+        view! {
+            <span class=emu_style::link on:click=move |_| select(entry)>
+                {name}
+            </span>
+        }
+    };
+    let rows = move || {
+        analysis.with(|analysis| {
+            let Some(analysis) = analysis else {
+                return view! {
+                    <tr>
+                        <td colspan=5>Press Analyse to discover routines</td>
+                    </tr>
+                }
+                .into_any();
+            };
+            let name_of = |entry: &u16| {
+                analysis
+                    .routines
+                    .get(entry)
+                    .map(|routine| routine.name.clone())
+                    .unwrap_or_else(|| format!("{:04X}", entry))
+            };
+            analysis
+                .routines
+                .values()
+                .map(|routine| {
+                    let entry = routine.entry;
+                    let callees = routine
+                        .callees
+                        .iter()
+                        .map(|callee| routine_link(*callee, name_of(callee)))
+                        .collect_view();
+                    let callers = routine
+                        .callers
+                        .iter()
+                        .map(|caller| routine_link(*caller, name_of(caller)))
+                        .collect_view();
+                    view! {
+                        <tr class=move || {
+                            if selected.get() == Some(entry) { emu_style::selectedroutine } else { "" }
+                        }>
+                            <td>{routine_link(entry, routine.name.clone())}</td>
+                            <td>{format!("{:04X}", entry)}</td>
+                            <td>{routine.blocks.len()}</td>
+                            <td>{callees}</td>
+                            <td>{callers}</td>
+                        </tr>
+                    }
+                })
+                .collect_view()
+                .into_any()
+        })
+    };
+    let summary = move || {
+        analysis.with(|analysis| {
+            analysis
+                .as_ref()
+                .map(|analysis| {
+                    format!(
+                        "{} routines, {} blocks{}",
+                        analysis.routines.len(),
+                        analysis.blocks.len(),
+                        if analysis.truncated { " (truncated)" } else { "" }
+                    )
+                })
+                .unwrap_or_default()
+        })
+    };
+    let graph = move || {
+        analysis.with(|analysis| {
+            match (analysis, selected.get()) {
+                (Some(analysis), Some(entry)) => cfg_view(analysis, entry, show_disasm).into_any(),
+                _ => view! { <span>Select a routine to show its control flow</span> }.into_any(),
+            }
+        })
+    };
+    view! {
+        <div class=emu_style::callgraph>
+            <div class=emu_style::sectop>
+                <span>Call graph</span>
+            </div>
+            <div class=emu_style::callgraphtools>
+                <input type="button" value="Analyse" on:click=run_analysis />
+                <span>{summary}</span>
+            </div>
+            <div class=emu_style::callgraphflex>
+                <table>
+                    <thead>
+                        <tr>
+                            <th>Routine</th>
+                            <th>Address</th>
+                            <th>Blocks</th>
+                            <th>Calls</th>
+                            <th>Called by</th>
+                        </tr>
+                    </thead>
+                    <tbody>{rows}</tbody>
+                </table>
+                <div class=emu_style::cfg>{graph}</div>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,488 @@
+use super::editor::{CompileLanguage, Diagnostic, Severity};
+use super::highlight::{highlight, TokenKind};
+use super::{emu_style, EmulatorCfgContext, EmulatorContext};
+use leptos::ev::KeyboardEvent;
+use leptos::html;
+use leptos::prelude::*;
+use leptos::web_sys::HtmlTextAreaElement;
+use stylance::classes;
+
+const TAB: &str = "    ";
+
+fn token_class(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::Plain => "",
+        TokenKind::Keyword => emu_style::tkkeyword,
+        TokenKind::Type => emu_style::tktype,
+        TokenKind::Preprocessor => emu_style::tkpreprocessor,
+        TokenKind::Mnemonic => emu_style::tkmnemonic,
+        TokenKind::Register => emu_style::tkregister,
+        TokenKind::Directive => emu_style::tkdirective,
+        TokenKind::Label => emu_style::tklabel,
+        TokenKind::Number => emu_style::tknumber,
+        TokenKind::String => emu_style::tkstring,
+        TokenKind::Comment => emu_style::tkcomment,
+    }
+}
+
+pub(super) fn severity_class(severity: Option<Severity>) -> &'static str {
+    match severity {
+        Some(Severity::Error) => emu_style::diagerror,
+        Some(Severity::Warning) => emu_style::diagwarning,
+        None => "",
+    }
+}
+
+/// The worst of `severities`, errors first.
+pub(super) fn worst_severity(severities: impl Iterator<Item = Severity>) -> Option<Severity> {
+    severities.min_by_key(|severity| *severity != Severity::Error)
+}
+
+/// The worst severity reported for `line`.
+fn line_severity(diagnostics: &[Diagnostic], line: usize) -> Option<Severity> {
+    worst_severity(
+        diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.line == line)
+            .map(|diagnostic| diagnostic.severity),
+    )
+}
+
+/// Byte offset of a textarea position, which the DOM counts in UTF-16 units.
+fn byte_offset(text: &str, utf16: u32) -> usize {
+    let mut units = 0;
+    for (index, c) in text.char_indices() {
+        if units >= utf16 as usize {
+            return index;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn utf16_offset(text: &str, byte: usize) -> u32 {
+    text[..byte].encode_utf16().count() as u32
+}
+
+fn line_start(text: &str, position: usize) -> usize {
+    text[..position].rfind('\n').map_or(0, |index| index + 1)
+}
+
+/// Buffer contents and selection, in bytes, after a keyboard edit.
+struct Edit {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+fn replace_range(text: &str, start: usize, end: usize, insert: &str) -> Edit {
+    let cursor = start + insert.len();
+    Edit {
+        text: format!("{}{}{}", &text[..start], insert, &text[end..]),
+        start: cursor,
+        end: cursor,
+    }
+}
+
+/// A newline that keeps the current line's indentation, one level deeper after
+/// an opening brace in C.
+fn insert_newline(lang: CompileLanguage, text: &str, start: usize, end: usize) -> Edit {
+    let line = &text[line_start(text, start)..start];
+    let mut indent: String = line.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+    if lang == CompileLanguage::C && line.trim_end().ends_with('{') {
+        indent.push_str(TAB);
+    }
+    replace_range(text, start, end, &format!("\n{}", indent))
+}
+
+/// Indents or dedents every line touched by the selection and selects them.
+fn indent_lines(text: &str, start: usize, end: usize, dedent: bool) -> Edit {
+    let first = line_start(text, start);
+    let last = if end > start && text[..end].ends_with('\n') {
+        end - 1
+    } else {
+        end
+    };
+    let block_end = text[last..].find('\n').map_or(text.len(), |index| last + index);
+    let block = text[first..block_end]
+        .split('\n')
+        .map(|line| {
+            if !dedent {
+                return format!("{}{}", TAB, line);
+            }
+            let remove = match line.strip_prefix('\t') {
+                Some(_) => 1,
+                None => line.chars().take(TAB.len()).take_while(|c| *c == ' ').count(),
+            };
+            line[remove..].to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Edit {
+        text: format!("{}{}{}", &text[..first], block, &text[block_end..]),
+        start: first,
+        end: first + block.len(),
+    }
+}
+
+fn selection(textarea: &HtmlTextAreaElement, text: &str) -> (usize, usize) {
+    let start = textarea.selection_start().ok().flatten().unwrap_or(0);
+    let end = textarea.selection_end().ok().flatten().unwrap_or(start);
+    (byte_offset(text, start), byte_offset(text, end))
+}
+
+fn select(textarea: &HtmlTextAreaElement, text: &str, start: usize, end: usize) {
+    let _ = textarea.focus();
+    let _ = textarea.set_selection_range(utf16_offset(text, start), utf16_offset(text, end));
+}
+
+/// Byte offset of a 1-based line and column, clamped to the line's end.
+fn position_of(text: &str, line: usize, column: Option<usize>) -> usize {
+    let start = text
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>();
+    let line_text = text[start..].split('\n').next().unwrap_or("");
+    let column = column.unwrap_or(1).saturating_sub(1);
+    start
+        + line_text
+            .char_indices()
+            .nth(column)
+            .map_or(line_text.len(), |(index, _)| index)
+}
+
+/// Source editor with line numbers, highlighting, auto-indent and find/replace.
+/// A transparent textarea sits over the highlighted text and takes the input.
+/// The gutter shows the buffer's diagnostics, and clicking a line number toggles
+/// a breakpoint on the code that line was built to.
+#[island]
+pub fn CodeEditor(lang: CompileLanguage) -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let textarea_ref: NodeRef<html::Textarea> = NodeRef::new();
+    let code_ref: NodeRef<html::Pre> = NodeRef::new();
+    let gutter_ref: NodeRef<html::Div> = NodeRef::new();
+    let find_ref: NodeRef<html::Input> = NodeRef::new();
+    let show_find = RwSignal::new(false);
+    let needle = RwSignal::new(String::new());
+    let replacement = RwSignal::new(String::new());
+
+    let source = Memo::new(move |_| emu_cfg_ctx.with(|cfg| cfg.editor.buffer(lang).to_string()));
+    let lines = Memo::new(move |_| source.with(|source| highlight(lang, source)));
+    let diagnostics =
+        Memo::new(move |_| emu_cfg_ctx.with(|cfg| cfg.editor.open_file_diagnostics(lang)));
+    let problems =
+        Memo::new(move |_| emu_cfg_ctx.with(|cfg| cfg.editor.diagnostics(lang).to_vec()));
+    let breakpoint_lines = Memo::new(move |_| {
+        let breakpoints = emu_ctx.with(|emu| emu.emu.breakpoints.clone());
+        let count = lines.with(Vec::len);
+        emu_cfg_ctx.with(|cfg| {
+            (1..=count)
+                .filter(|line| {
+                    cfg.editor
+                        .line_address(lang, *line)
+                        .is_some_and(|address| breakpoints.contains(&address))
+                })
+                .collect::<Vec<usize>>()
+        })
+    });
+    let match_count = Memo::new(move |_| {
+        needle.with(|needle| {
+            if needle.is_empty() {
+                0
+            } else {
+                source.with(|source| source.matches(needle.as_str()).count())
+            }
+        })
+    });
+
+    let apply = move |textarea: &HtmlTextAreaElement, edit: Edit| {
+        textarea.set_value(&edit.text);
+        select(textarea, &edit.text, edit.start, edit.end);
+        emu_cfg_ctx.update(|cfg| cfg.editor.write_buffer(lang, edit.text));
+    };
+    let on_input = move |_| {
+        if let Some(textarea) = textarea_ref.get_untracked() {
+            let text = textarea.value();
+            emu_cfg_ctx.update(|cfg| cfg.editor.write_buffer(lang, text));
+        }
+    };
+    let on_keydown = move |ev: KeyboardEvent| {
+        let Some(textarea) = textarea_ref.get_untracked() else {
+            return;
+        };
+        if (ev.ctrl_key() || ev.meta_key()) && ev.key() == "f" {
+            ev.prevent_default();
+            show_find.set(true);
+            // The find bar is only mounted on the next frame.
+            request_animation_frame(move || {
+                if let Some(find) = find_ref.get_untracked() {
+                    let _ = find.focus();
+                }
+            });
+            return;
+        }
+        let text = textarea.value();
+        let (start, end) = selection(&textarea, &text);
+        let edit = match ev.key().as_str() {
+            "Tab" if ev.shift_key() => indent_lines(&text, start, end, true),
+            "Tab" if text[start..end].contains('\n') => indent_lines(&text, start, end, false),
+            "Tab" => replace_range(&text, start, end, TAB),
+            "Enter" if !ev.is_composing() => insert_newline(lang, &text, start, end),
+            _ => return,
+        };
+        ev.prevent_default();
+        apply(&textarea, edit);
+    };
+    let on_scroll = move |_| {
+        let Some(textarea) = textarea_ref.get_untracked() else {
+            return;
+        };
+        if let Some(code) = code_ref.get_untracked() {
+            code.set_scroll_top(textarea.scroll_top());
+            code.set_scroll_left(textarea.scroll_left());
+        }
+        if let Some(gutter) = gutter_ref.get_untracked() {
+            gutter.set_scroll_top(textarea.scroll_top());
+        }
+    };
+
+    let find_next = move || {
+        let Some(textarea) = textarea_ref.get_untracked() else {
+            return;
+        };
+        let needle = needle.get_untracked();
+        if needle.is_empty() {
+            return;
+        }
+        let text = textarea.value();
+        let (_, from) = selection(&textarea, &text);
+        let found = text[from..]
+            .find(&needle)
+            .map(|index| from + index)
+            .or_else(|| text.find(&needle));
+        if let Some(start) = found {
+            select(&textarea, &text, start, start + needle.len());
+        }
+    };
+    let replace_one = move || {
+        let Some(textarea) = textarea_ref.get_untracked() else {
+            return;
+        };
+        let text = textarea.value();
+        let (start, end) = selection(&textarea, &text);
+        let needle = needle.get_untracked();
+        if !needle.is_empty() && text[start..end] == needle {
+            apply(
+                &textarea,
+                replace_range(&text, start, end, &replacement.get_untracked()),
+            );
+        }
+        find_next();
+    };
+    let replace_all = move || {
+        let Some(textarea) = textarea_ref.get_untracked() else {
+            return;
+        };
+        let needle = needle.get_untracked();
+        if needle.is_empty() {
+            return;
+        }
+        let text = textarea.value().replace(&needle, &replacement.get_untracked());
+        apply(
+            &textarea,
+            Edit {
+                text,
+                start: 0,
+                end: 0,
+            },
+        );
+    };
+    let reveal = move |line: usize, column: Option<usize>| {
+        let Some(textarea) = textarea_ref.get_untracked() else {
+            return;
+        };
+        let text = textarea.value();
+        let position = position_of(&text, line, column);
+        select(&textarea, &text, position, position);
+        // Rows share one height, so centre the line from the scroll height.
+        let row = textarea.scroll_height() / text.split('\n').count().max(1) as i32;
+        textarea.set_scroll_top(row * (line as i32 - 1) - textarea.client_height() / 2);
+    };
+    let reveal_in_file = move |file: Option<String>, line: usize, column: Option<usize>| {
+        let index = file.and_then(|file| {
+            emu_cfg_ctx.with_untracked(|cfg| {
+                cfg.editor
+                    .c_file_index(&file)
+                    .filter(|index| *index != cfg.editor.c_active)
+            })
+        });
+        match index {
+            Some(index) => {
+                emu_cfg_ctx.update(|cfg| cfg.editor.select_c_file(index));
+                // The textarea only holds the other file's text on the next frame.
+                request_animation_frame(move || reveal(line, column));
+            }
+            None => reveal(line, column),
+        }
+    };
+    let toggle_breakpoint = move |line: usize| {
+        match emu_cfg_ctx.with_untracked(|cfg| cfg.editor.line_address(lang, line)) {
+            Some(address) => emu_ctx.update(|emu| {
+                let breakpoints = &mut emu.emu.breakpoints;
+                match breakpoints.iter().position(|breakpoint| *breakpoint == address) {
+                    Some(index) => {
+                        breakpoints.remove(index);
+                    }
+                    None => breakpoints.push(address),
+                }
+            }),
+            None => emu_cfg_ctx.update(|cfg| {
+                cfg.logstore.log_warning(
+                    "No code on line",
+                    format!("Line {} has no code from the last build to break on", line),
+                )
+            }),
+        }
+    };
+
+    let gutter_rows = move || {
+        let count = lines.with(Vec::len);
+        let breakpoints = breakpoint_lines.get();
+        diagnostics.with(|diagnostics| {
+            (1..=count)
+                .map(|line| {
+                    let class = classes!(
+                        emu_style::gutterline,
+                        if breakpoints.contains(&line) { emu_style::gutterbreak } else { "" },
+                        severity_class(line_severity(diagnostics, line))
+                    );
+                    let title = diagnostics
+                        .iter()
+                        .filter(|diagnostic| diagnostic.line == line)
+                        .map(|diagnostic| match diagnostic.column {
+                            Some(column) => format!("{}: {}", column, diagnostic.message),
+                            None => diagnostic.message.clone(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    view! {
+                        <div class=class title=title on:click=move |_| toggle_breakpoint(line)>
+                            {line}
+                        </div>
+                    }
+                })
+                .collect_view()
+        })
+    };
+    let code_lines = move || {
+        let lines = lines.get();
+        diagnostics.with(|diagnostics| {
+            lines
+                .into_iter()
+                .enumerate()
+                .map(|(index, tokens)| {
+                    let class = classes!(
+                        emu_style::codeline,
+                        severity_class(line_severity(diagnostics, index + 1))
+                    );
+                    let tokens = tokens
+                        .into_iter()
+                        .map(|(kind, text)| view! { <span class=token_class(kind)>{text}</span> })
+                        .collect_view();
+                    view! { <div class=class>{tokens}</div> }
+                })
+                .collect_view()
+        })
+    };
+
+    let problem_rows = move || {
+        problems
+            .get()
+            .into_iter()
+            .map(|diagnostic| {
+                let (line, column) = (diagnostic.line, diagnostic.column);
+                let mut location = match column {
+                    Some(column) => format!("{}:{}", line, column),
+                    None => line.to_string(),
+                };
+                if let Some(file) = &diagnostic.file {
+                    location = format!("{}:{}", file, location);
+                }
+                let file = diagnostic.file.clone();
+                view! {
+                    <div
+                        class=severity_class(Some(diagnostic.severity))
+                        on:click=move |_| reveal_in_file(file.clone(), line, column)
+                    >
+                        <span>{location}</span>
+                        <span>{diagnostic.message}</span>
+                    </div>
+                }
+            })
+            .collect_view()
+    };
+
+    view! {
+        <div class=emu_style::codeeditor>
+            <Show when=move || show_find.get()>
+                <div class=emu_style::findbar>
+                    <input
+                        type="text"
+                        placeholder="Find"
+                        node_ref=find_ref
+                        prop:value=needle
+                        on:input=move |ev| needle.set(event_target_value(&ev))
+                        on:keydown=move |ev: KeyboardEvent| {
+                            if ev.key() == "Enter" {
+                                ev.prevent_default();
+                                find_next();
+                            } else if ev.key() == "Escape" {
+                                show_find.set(false);
+                            }
+                        }
+                    />
+                    <input
+                        type="text"
+                        placeholder="Replace"
+                        prop:value=replacement
+                        on:input=move |ev| replacement.set(event_target_value(&ev))
+                    />
+                    <input type="button" value="Next" on:click=move |_| find_next() />
+                    <input type="button" value="Replace" on:click=move |_| replace_one() />
+                    <input type="button" value="All" on:click=move |_| replace_all() />
+                    <span>{move || format!("{} found", match_count.get())}</span>
+                    <input type="button" value="x" on:click=move |_| show_find.set(false) />
+                </div>
+            </Show>
+            <div class=emu_style::codebody>
+                <div class=emu_style::gutter node_ref=gutter_ref>
+                    {gutter_rows}
+                </div>
+                <div class=emu_style::codearea>
+                    <pre class=emu_style::codehighlight node_ref=code_ref aria-hidden="true">
+                        {code_lines}
+                    </pre>
+                    <textarea
+                        spellcheck="false"
+                        wrap="off"
+                        node_ref=textarea_ref
+                        prop:value=move || source.get()
+                        on:input=on_input
+                        on:keydown=on_keydown
+                        on:scroll=on_scroll
+                    ></textarea>
+                </div>
+            </div>
+            <Show when=move || problems.with(|problems| !problems.is_empty())>
+                <div class=emu_style::problems>
+                    <div class=emu_style::problemscount>
+                        {move || format!("Problems ({})", problems.with(Vec::len))}
+                    </div>
+                    {problem_rows}
+                </div>
+            </Show>
+        </div>
+    }
+}
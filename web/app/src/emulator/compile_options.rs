@@ -0,0 +1,144 @@
+use super::{emu_style, EmulatorCfgContext};
+use crate::utils::ccompiler::{Backend, CompileOptions, Crt, Define};
+use leptos::ev::Event;
+use leptos::prelude::*;
+use leptos::web_sys::HtmlInputElement;
+
+/// Compiler settings for the C project, saved with the editor buffers.
+#[island]
+pub fn CompileOptionsPanel() -> impl IntoView {
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let options = Memo::new(move |_| emu_cfg_ctx.with(|cfg| cfg.editor.compile_options.clone()));
+    let set_options = move |edit: &dyn Fn(&mut CompileOptions)| {
+        emu_cfg_ctx.update(|cfg| edit(&mut cfg.editor.compile_options));
+    };
+
+    let on_origin = move |ev: Event| {
+        let value = event_target_value(&ev);
+        let value = value.trim().trim_start_matches("0x");
+        match u16::from_str_radix(value, 16) {
+            Ok(origin) => set_options(&|options| options.origin = origin),
+            Err(_) => {
+                let input: HtmlInputElement = event_target(&ev);
+                input.set_value(&format!("{:04X}", options.get_untracked().origin));
+            }
+        }
+    };
+    let on_defines = move |ev: Event| match Define::parse_list(&event_target_value(&ev)) {
+        Ok(defines) => set_options(&|options| options.defines = defines.clone()),
+        Err(err) => {
+            let input: HtmlInputElement = event_target(&ev);
+            input.set_value(&Define::format_list(&options.get_untracked().defines));
+            emu_cfg_ctx.update(|cfg| {
+                cfg.logstore
+                    .log_warning("Invalid define", format!("Defines not changed: {}", err))
+            });
+        }
+    };
+
+    let backend_options = Backend::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, backend)| view! { <option value=index.to_string()>{backend.name()}</option> })
+        .collect_view();
+    let crt_options = Crt::ALL
+        .iter()
+        .enumerate()
+        .map(|(index, crt)| view! { <option value=index.to_string()>{crt.name()}</option> })
+        .collect_view();
+
+    view! {
+        <div class=emu_style::compileoptions>
+            <div class=emu_style::projectgroup>"Options"</div>
+            <label>
+                "Optimisation"
+                <select
+                    prop:value=move || options.get().optimization.to_string()
+                    on:change=move |ev| {
+                        if let Ok(level) = event_target_value(&ev).parse::<u8>() {
+                            set_options(&|options| options.optimization = level);
+                        }
+                    }
+                >
+                    <option value="0">-O0</option>
+                    <option value="1">-O1</option>
+                    <option value="2">-O2</option>
+                    <option value="3">-O3</option>
+                </select>
+            </label>
+            <label>
+                "Backend"
+                <select
+                    prop:value=move || {
+                        let backend = options.get().backend;
+                        Backend::ALL.iter().position(|other| *other == backend).unwrap_or(0).to_string()
+                    }
+                    on:change=move |ev| {
+                        let index = event_target_value(&ev).parse::<usize>().ok();
+                        if let Some(backend) = index.and_then(|index| Backend::ALL.get(index)) {
+                            set_options(&|options| options.backend = *backend);
+                        }
+                    }
+                >
+                    {backend_options}
+                </select>
+            </label>
+            <label>
+                "CRT"
+                <select
+                    prop:value=move || {
+                        let crt = options.get().crt;
+                        Crt::ALL.iter().position(|other| *other == crt).unwrap_or(0).to_string()
+                    }
+                    on:change=move |ev| {
+                        let index = event_target_value(&ev).parse::<usize>().ok();
+                        if let Some(crt) = index.and_then(|index| Crt::ALL.get(index)) {
+                            set_options(&|options| options.crt = *crt);
+                        }
+                    }
+                >
+                    {crt_options}
+                </select>
+            </label>
+            <label>
+                "Origin"
+                <input
+                    type="text"
+                    prop:value=move || format!("{:04X}", options.get().origin)
+                    on:change=on_origin
+                />
+            </label>
+            <label>
+                <input
+                    type="checkbox"
+                    prop:checked=move || options.get().math
+                    on:change=move |ev| {
+                        let math = event_target_checked(&ev);
+                        set_options(&|options| options.math = math);
+                    }
+                />
+                "Math library"
+            </label>
+            <label>
+                <input
+                    type="checkbox"
+                    prop:checked=move || options.get().listing
+                    on:change=move |ev| {
+                        let listing = event_target_checked(&ev);
+                        set_options(&|options| options.listing = listing);
+                    }
+                />
+                "Listing"
+            </label>
+            <label>
+                "Defines"
+                <input
+                    type="text"
+                    placeholder="NAME=VALUE"
+                    prop:value=move || Define::format_list(&options.get().defines)
+                    on:change=on_defines
+                />
+            </label>
+        </div>
+    }
+}
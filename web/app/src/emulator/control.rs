@@ -1,3 +1,8 @@
+use super::flow::{decode, is_repeating, Flow};
+use super::heatmap::AccessTracker;
+use super::profiler::Profile;
+use super::registers::RegisterSnapshot;
+use super::workspace::ResetWorkspaceButton;
 use super::{emu_style, EmulatorCfgContext, EmulatorContext};
 use crate::utils::logger::LogLevel;
 use emu_lib::cpu::instruction::ExecutableInstruction;
@@ -16,18 +21,109 @@ use stylance::classes;
 use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
 use js_sys::Date;
 
+/// Clock of the original Z80-based home computers, in Hz.
+pub const DEFAULT_FREQUENCY: usize = 3_579_545;
+
 pub struct ControlContext {
     pub target_frequency: RwSignal<usize>,
-    pub real_frequency: RwSignal<Option<usize>>
+    pub real_frequency: RwSignal<Option<usize>>,
+    pub running: RwSignal<bool>,
+    /// Stop condition of the current run, if it was started by a run-until command.
+    pub until: RwSignal<Option<RunUntil>>,
 }
 
 impl Default for ControlContext {
     fn default() -> Self {
         Self {
-            target_frequency: RwSignal::new(3_579_545),
+            target_frequency: RwSignal::new(DEFAULT_FREQUENCY),
             real_frequency: RwSignal::new(None),
+            running: RwSignal::new(false),
+            until: RwSignal::new(None),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunUntil {
+    /// Back at `address` with the stack no deeper than `sp`, so recursive calls
+    /// into the same routine do not stop the run.
+    StepOver { address: u16, sp: u16 },
+    /// A return pops the stack above `sp`, leaving the current routine.
+    StepOut { sp: u16 },
+    RunToCursor { address: u16 },
+}
+
+impl RunUntil {
+    /// Checked after the instruction at `pc` has executed.
+    fn reached(&self, emu: &Emulator<Z80>, pc: u16) -> bool {
+        let registers = &emu.cpu.registers;
+        match *self {
+            RunUntil::StepOver { address, sp } => registers.pc == address && registers.sp >= sp,
+            RunUntil::StepOut { sp } => {
+                registers.sp > sp
+                    && matches!(
+                        decode(&emu.memory, pc).map(|decoded| decoded.flow),
+                        Some(Flow::Return { .. })
+                    )
+            }
+            RunUntil::RunToCursor { address } => registers.pc == address,
         }
     }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            RunUntil::StepOver { .. } => "Step over",
+            RunUntil::StepOut { .. } => "Step out",
+            RunUntil::RunToCursor { .. } => "Run to cursor",
+        }
+    }
+}
+
+/// Step over runs `CALL`, `RST`, `DJNZ` and repeating block instructions until
+/// they fall through to the next instruction; anything else is a single step.
+fn step_over_target(emu: &Emulator<Z80>) -> Option<RunUntil> {
+    let decoded = decode(&emu.memory, emu.cpu.registers.pc)?;
+    let over = matches!(decoded.flow, Flow::Call { .. }) || is_repeating(&decoded.bytes);
+    over.then(|| RunUntil::StepOver {
+        address: decoded.next(),
+        sp: emu.cpu.registers.sp,
+    })
+}
+
+/// Starts a run that stops itself once `until` is reached. The Run button picks
+/// the run up through `ControlContext::running`.
+pub fn run_until(emu_cfg: &mut EmulatorCfgContext, until: RunUntil) {
+    if emu_cfg.control.running.get_untracked() {
+        emu_cfg.logstore.log_warning(
+            "Emulator already running",
+            format!("{} ignored: the emulator is already running", until.describe()),
+        );
+        return;
+    }
+    emu_cfg.control.until.set(Some(until));
+    emu_cfg.control.running.set(true);
+}
+
+fn step_once(emu: &mut EmulatorContext, emu_cfg: &mut EmulatorCfgContext) {
+    emu.registers_before = RegisterSnapshot::capture(&emu.emu);
+    let tracker = &emu_cfg.tracker;
+    let pc = emu.emu.cpu.registers.pc;
+    tracker.start();
+    let result = emu.emu.step();
+    tracker.record_execute(&emu.emu.memory, pc);
+    tracker.finish();
+    emu_cfg.logstore.set_clock(emu.emu.cycles as u64);
+    if let Err(err) = result {
+        emu_cfg.logstore.log_error(
+            "Step error",
+            format!("Step error at {:#04X}: {}", emu.emu.cpu.registers.pc, err),
+        );
+    } else {
+        emu_cfg.logstore.log_info(
+            "Step",
+            format!("Step at {:#04X}", emu.emu.cpu.registers.pc),
+        );
+    }
 }
 
 #[island]
@@ -39,36 +135,106 @@ fn StepButton() -> impl IntoView {
             type="button"
             value="Step"
             on:click=move |_| {
-                emu_ctx
-                    .update(|emu| {
-                        emu_cfg_ctx
-                            .update(|emu_cfg| {
-                                if let Err(err) = emu.emu.step() {
-                                    emu_cfg
-                                        .logstore
-                                        .log_error(
-                                            "Step error",
-                                            format!(
-                                                "Step error at {:#04X}: {}",
-                                                emu.emu.cpu.registers.pc,
-                                                err,
-                                            ),
-                                        );
-                                } else {
-                                    emu_cfg
-                                        .logstore
-                                        .log_info(
-                                            "Step",
-                                            format!("Step at {:#04X}", emu.emu.cpu.registers.pc),
-                                        );
-                                }
-                            });
-                    })
+                emu_ctx.update(|emu| emu_cfg_ctx.update(|emu_cfg| step_once(emu, emu_cfg)))
             }
         />
     }
 }
 
+#[island]
+fn StepOverButton() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let step_over = move |_| {
+        let target = emu_ctx.with_untracked(|emu| step_over_target(&emu.emu));
+        match target {
+            Some(target) => emu_cfg_ctx.update(|emu_cfg| run_until(emu_cfg, target)),
+            None => emu_ctx.update(|emu| emu_cfg_ctx.update(|emu_cfg| step_once(emu, emu_cfg))),
+        }
+    };
+    view! { <input type="button" value="Step over" on:click=step_over /> }
+}
+
+#[island]
+fn StepOutButton() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let step_out = move |_| {
+        let sp = emu_ctx.with_untracked(|emu| emu.emu.cpu.registers.sp);
+        emu_cfg_ctx.update(|emu_cfg| run_until(emu_cfg, RunUntil::StepOut { sp }));
+    };
+    view! { <input type="button" value="Step out" on:click=step_out /> }
+}
+
+/// Runs at least `ticks` cycles one instruction at a time, reporting each
+/// instruction's address and cycle count to `observe`. Stops like `run_ticks` does
+/// on halts, errors and breakpoints, and early with `Ok(true)` when `observe`
+/// returns true.
+pub fn run_stepped(
+    emu: &mut Emulator<Z80>,
+    ticks: f64,
+    mut observe: impl FnMut(&Emulator<Z80>, u16, u64) -> bool,
+) -> Result<bool, StopReason> {
+    let mut elapsed = 0u64;
+    while (elapsed as f64) < ticks {
+        if emu.cpu.halted() {
+            return Err(StopReason::Halt);
+        }
+        let pc = emu.cpu.registers.pc;
+        let before = emu.cycles;
+        emu.step().map_err(|err| StopReason::Error(err.to_string()))?;
+        let cycles = (emu.cycles - before) as u64;
+        elapsed += cycles.max(1);
+        if observe(emu, pc, cycles) {
+            return Ok(true);
+        }
+        if emu.breakpoints.contains(&emu.cpu.registers.pc) {
+            return Err(StopReason::Breakpoint);
+        }
+    }
+    Ok(false)
+}
+
+/// The profiler and the heatmap, told about each instruction of a run while
+/// they are enabled.
+#[derive(Clone)]
+pub struct RunObservers {
+    profile: Option<RwSignal<Profile>>,
+    pub tracker: AccessTracker,
+}
+
+impl RunObservers {
+    pub fn new(emu_cfg: &EmulatorCfgContext) -> Self {
+        let profiler = &emu_cfg.profiler;
+        RunObservers {
+            profile: profiler.enabled.get_untracked().then_some(profiler.profile),
+            tracker: emu_cfg.tracker.clone(),
+        }
+    }
+
+    /// Starts recording; returns whether anything needs `observe` calls.
+    pub fn start(&self) -> bool {
+        let tracking = self.tracker.start();
+        tracking || self.profile.is_some()
+    }
+
+    /// Records the instruction at `pc` that just took `cycles`.
+    pub fn observe(&self, emu: &Emulator<Z80>, pc: u16, cycles: u64) {
+        if let Some(profile) = self.profile {
+            profile.update_untracked(|profile| profile.record(pc, cycles));
+        }
+        self.tracker.record_execute(&emu.memory, pc);
+    }
+
+    /// Stops recording and refreshes the panels once.
+    pub fn finish(&self) {
+        if let Some(profile) = self.profile {
+            profile.notify();
+        }
+        self.tracker.finish();
+    }
+}
+
 fn step_fn<FST, FSF>(
     mut step_count: usize,
     chunk_ticks: Memo<f64>,
@@ -84,6 +250,12 @@ where
     FST: Fn(f64) + 'static,
     FSF: Fn(Option<usize>) + 'static,
 {
+    // A run stopped since this chunk was scheduled, possibly with another
+    // instance swapped in, must not step again.
+    if !running.get_untracked() {
+        set_frequency(None);
+        return;
+    }
     let now = Date::now();
     let elapsed = now - start_time;
     let chunk_dur = chunk_duration();
@@ -154,41 +326,80 @@ fn RunButton() -> impl IntoView {
         Duration::from_millis((1000.0 / refresh_rate.get() as f64) as u64).as_millis_f64()
     });
 
-    let running = RwSignal::new(false);
+    let running = emu_cfg_ctx.with_untracked(|emu_cfg| emu_cfg.control.running);
+    let until = emu_cfg_ctx.with_untracked(|emu_cfg| emu_cfg.control.until);
     let stop = move || {
         running.set(false);
+        until.set(None);
         emu_cfg_ctx.update(|emu_cfg| {
             emu_cfg.logstore.log_info("Emulator stopped", "Emulator stopped".to_string());
         });
     };
 
     let step_ticks = move |ticks: f64| {
+        let observers = emu_cfg_ctx.with_untracked(RunObservers::new);
+        let target = until.get_untracked();
         emu_ctx.update(|emu| {
-            if let Err(err) = emu.emu.run_ticks(
-                ticks,
-                &Some(move |emu: &mut Emulator<_>, instruction: &dyn ExecutableInstruction<_>| {}),
-            ) {
-                emu_cfg_ctx.update(|emu_cfg| match err {
-                    StopReason::Halt => {
-                        emu_cfg.logstore.log_info(
-                            "Emulator stopped: halt",
-                            "Emulator stopped due to a halt".to_string(),
-                        );
-                    }
-                    StopReason::Error(err) => {
-                        emu_cfg.logstore.log_error("Error", err);
-                    }
-                    StopReason::Breakpoint => {
-                        emu_cfg.logstore.log_info(
-                            "Emulator stopped: breakpoint",
-                            format!(
-                                "Emulator stopped due to a breakpoint at {:#04X}",
-                                emu.emu.cpu.registers.pc
-                            ),
-                        );
+            emu.registers_before = RegisterSnapshot::capture(&emu.emu);
+            let observing = observers.start();
+            // Per-instruction observers need the slower stepping loop.
+            let result = if observing || target.is_some() {
+                run_stepped(&mut emu.emu, ticks, |emu, pc, cycles| {
+                    observers.observe(emu, pc, cycles);
+                    target.is_some_and(|target| observers.tracker.paused(|| target.reached(emu, pc)))
+                })
+            } else {
+                emu
+                    .emu
+                    .run_ticks(
+                        ticks,
+                        &Some(move |emu: &mut Emulator<_>, instruction: &dyn ExecutableInstruction<_>| {}),
+                    )
+                    .map(|_| false)
+            };
+            observers.finish();
+            let cycles = emu.emu.cycles as u64;
+            emu_cfg_ctx.update_untracked(|emu_cfg| emu_cfg.logstore.set_clock(cycles));
+            match result {
+                Ok(false) => {}
+                Ok(true) => {
+                    if let Some(target) = target {
+                        emu_cfg_ctx.update(|emu_cfg| {
+                            emu_cfg.logstore.log_info(
+                                "Emulator stopped: target reached",
+                                format!(
+                                    "{} finished at {:#04X}",
+                                    target.describe(),
+                                    emu.emu.cpu.registers.pc
+                                ),
+                            );
+                        });
                     }
-                });
-                stop();
+                    stop();
+                }
+                Err(err) => {
+                    emu_cfg_ctx.update(|emu_cfg| match err {
+                        StopReason::Halt => {
+                            emu_cfg.logstore.log_info(
+                                "Emulator stopped: halt",
+                                "Emulator stopped due to a halt".to_string(),
+                            );
+                        }
+                        StopReason::Error(err) => {
+                            emu_cfg.logstore.log_error("Error", err);
+                        }
+                        StopReason::Breakpoint => {
+                            emu_cfg.logstore.log_info(
+                                "Emulator stopped: breakpoint",
+                                format!(
+                                    "Emulator stopped due to a breakpoint at {:#04X}",
+                                    emu.emu.cpu.registers.pc
+                                ),
+                            );
+                        }
+                    });
+                    stop();
+                }
             }
         })
     };
@@ -199,27 +410,37 @@ fn RunButton() -> impl IntoView {
         });
     };
 
-    let start = move || {
-        if running.get() {
-            return;
-        }
-        emu_cfg_ctx.update(|emu_cfg| {
-            emu_cfg.logstore.log_info("Emulator started", "Emulator started".to_string());
-        });
-        running.set(true);
-        let now = Date::now();
-        step_fn(
-            1, // step_count
-            chunk_ticks.clone(),
-            chunk_duration.clone(),
-            step_ticks,
-            set_frequency,
-            running.clone(),
-            0.0, // total_ticks
-            now, // start_time
-            0.0, // tick_accum
-        );
-    };
+    // Runs are started by setting `running`, from here or from a run-until command.
+    Effect::watch(
+        move || running.get(),
+        move |running, previous, _| {
+            if !*running {
+                // A stopped run, however it was stopped, leaves no target behind.
+                until.set(None);
+                return;
+            }
+            if previous == Some(&true) {
+                return;
+            }
+            emu_cfg_ctx.update(|emu_cfg| {
+                emu_cfg.logstore.log_info("Emulator started", "Emulator started".to_string());
+            });
+            let now = Date::now();
+            step_fn(
+                1, // step_count
+                chunk_ticks.clone(),
+                chunk_duration.clone(),
+                step_ticks,
+                set_frequency,
+                running.clone(),
+                0.0, // total_ticks
+                now, // start_time
+                0.0, // tick_accum
+            );
+        },
+        false,
+    );
+    let start = move || running.set(true);
 
     let switch = move || {
         if running.get() {
@@ -362,6 +583,7 @@ fn ResetButton() -> impl IntoView {
         emu_ctx.update(|emu| {
             emu.emu.cpu = Z80::default();
             emu.emu.reset_counters();
+            emu.registers_before = RegisterSnapshot::capture(&emu.emu);
         });
     };
     view! { <input type="button" value="Reset" on:click=on_reset /> }
@@ -421,6 +643,29 @@ fn ClearMemoryButton() -> impl IntoView {
 //     }
 // }
 
+/// Hands `data` to the browser as a file download through a temporary anchor.
+pub fn download_file(filename: &str, mime: &str, data: &[u8]) {
+    let uint8_array = js_sys::Uint8Array::from(data);
+    let blob = Blob::new_with_u8_array_sequence_and_options(
+        &js_sys::Array::of1(&uint8_array),
+        BlobPropertyBag::new().type_(mime),
+    )
+    .expect("Error creating Blob");
+    let url = Url::create_object_url_with_blob(&blob).expect("Error creating URL");
+    let document = web_sys::window().unwrap().document().unwrap();
+    let a = document
+        .create_element("a")
+        .unwrap()
+        .dyn_into::<HtmlAnchorElement>()
+        .unwrap();
+    a.set_href(&url);
+    a.set_download(filename);
+    document.body().unwrap().append_child(&a).unwrap();
+    a.click();
+    document.body().unwrap().remove_child(&a).unwrap();
+    Url::revoke_object_url(&url).expect("Error revoking URL");
+}
+
 #[island]
 fn SaveButton() -> impl IntoView {
     let emu_signal = expect_context::<RwSignal<EmulatorContext>>();
@@ -435,34 +680,7 @@ fn SaveButton() -> impl IntoView {
                     let emu_signal = emu_signal.clone();
                     spawn_local(async move {
                         let data = emu_signal.with_untracked(|emu| emu.emu.memory.save().expect("Error saving memory"));
-                        let uint8_array = js_sys::Uint8Array::from(&*data);
-
-                        let blob = Blob::new_with_u8_array_sequence_and_options(&js_sys::Array::of1(&uint8_array),
-                                                                                        BlobPropertyBag::new().type_("application/octet-stream"))
-                            .expect("Error creating Blob");
-                        let url = Url::create_object_url_with_blob(&blob).expect("Error creating URL");
-
-                            // Step 4: Create a temporary anchor element
-                            let document = web_sys::window().unwrap().document().unwrap();
-                            let a = document.create_element("a").unwrap()
-                                .dyn_into::<HtmlAnchorElement>()
-                                .unwrap();
-
-                            // Step 5: Set the download attributes
-                            a.set_href(&url);
-                            a.set_download("emu_memory.bin");
-
-                            // Step 6: Append the anchor to the DOM
-                            document.body().unwrap().append_child(&a).unwrap();
-
-                            // Step 7: Programmatically click the anchor
-                            a.click();
-
-                            // Step 8: Remove the anchor from the DOM
-                            document.body().unwrap().remove_child(&a).unwrap();
-
-                            // Step 9: Revoke the object URL
-                            Url::revoke_object_url(&url).expect("Error revoking URL");
+                        download_file("emu_memory.bin", "application/octet-stream", &data);
                 })
                 }
             />
@@ -571,6 +789,8 @@ pub fn Control() -> impl IntoView {
     view! {
         <div class=emu_style::emucontrol>
             <StepButton />
+            <StepOverButton />
+            <StepOutButton />
             <RunButton />
             <FrequencySelect />
             <HaltButton />
@@ -578,6 +798,7 @@ pub fn Control() -> impl IntoView {
             <ClearMemoryButton />
             <SaveButton />
             <LoadButton />
+            <ResetWorkspaceButton />
             <EmuLog />
         </div>
     }
@@ -0,0 +1,248 @@
+use super::assembler::check;
+use super::editor::{CompileLanguage, Diagnostic, Severity};
+use super::EmulatorCfgContext;
+use crate::utils::ccompiler::c_syntax_check;
+use leptos::prelude::*;
+use leptos::task::spawn_local;
+use regex::Regex;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Quiet time after the last keystroke before the buffer is checked.
+const CHECK_DELAY: Duration = Duration::from_millis(800);
+
+/// `file:line[:col]: error[ 123]: message`, as printed by gcc and sdcc.
+static COMPILER_MESSAGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^([^:\s][^:]*):(\d+):(?:(\d+):)?\s*(fatal error|syntax error|error|warning)[^:]*:\s*(.*)$",
+    )
+    .unwrap()
+});
+/// sdcc reports syntax error columns at the end of the message instead.
+static TRAILING_COLUMN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"column (\d+)\s*$").unwrap());
+
+/// Structured diagnostics from compiler stderr. Lines that are not messages,
+/// such as notes and source excerpts, are skipped, and so are repeats from a
+/// header included by several files.
+pub fn parse_compiler_output(stderr: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    let parsed = stderr.lines().filter_map(|line| {
+        let captures = COMPILER_MESSAGE.captures(line.trim_end())?;
+        let message = captures[5].trim().to_string();
+        let column = captures
+            .get(3)
+            .and_then(|column| column.as_str().parse().ok())
+            .or_else(|| {
+                TRAILING_COLUMN
+                    .captures(&message)
+                    .and_then(|column| column[1].parse().ok())
+            });
+        Some(Diagnostic {
+            file: Some(captures[1].to_string()),
+            line: captures[2].parse().ok()?,
+            column,
+            severity: match &captures[4] {
+                "warning" => Severity::Warning,
+                _ => Severity::Error,
+            },
+            message,
+        })
+    });
+    for diagnostic in parsed {
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+/// Diagnostics for a failed check whose output could not be parsed, so the
+/// failure still shows up somewhere.
+fn unparsed_failure(stderr: &str) -> Vec<Diagnostic> {
+    let message = stderr.lines().find(|line| !line.trim().is_empty());
+    vec![Diagnostic {
+        file: None,
+        line: 1,
+        column: None,
+        severity: Severity::Error,
+        message: message.unwrap_or("Syntax check failed").to_string(),
+    }]
+}
+
+/// Diagnostics for C syntax check output, including failures without messages.
+pub fn syntax_check_diagnostics(rc: i32, stderr: &str) -> Vec<Diagnostic> {
+    let diagnostics = parse_compiler_output(stderr);
+    if diagnostics.is_empty() && rc != 0 {
+        return unparsed_failure(stderr);
+    }
+    diagnostics
+}
+
+pub fn asm_diagnostics(source: &str) -> Vec<Diagnostic> {
+    check(source)
+        .into_iter()
+        .map(|err| Diagnostic {
+            file: None,
+            line: err.line_number,
+            column: None,
+            severity: Severity::Error,
+            message: err.message,
+        })
+        .collect()
+}
+
+/// Re-checks the active buffer once typing pauses: assembly locally, the whole C
+/// project through the syntax check endpoint. Answers for text that has since
+/// changed are dropped.
+pub fn live_check(emu_cfg_ctx: RwSignal<EmulatorCfgContext>) {
+    // C is checked again when its defines change, as they can change the result.
+    let buffer = Memo::new(move |_| {
+        emu_cfg_ctx.with(|cfg| {
+            let lang = cfg.editor.active_lang;
+            let defines = match lang {
+                CompileLanguage::C => cfg.editor.compile_options.defines.clone(),
+                CompileLanguage::ASM => vec![],
+            };
+            (lang, cfg.editor.buffer(lang).to_string(), defines)
+        })
+    });
+    let pending = StoredValue::new(None::<TimeoutHandle>);
+    let generation = StoredValue::new(0u64);
+    let run_check = move |lang: CompileLanguage, source: String| {
+        generation.update_value(|generation| *generation += 1);
+        let current = generation.get_value();
+        match lang {
+            CompileLanguage::ASM => {
+                let diagnostics = asm_diagnostics(&source);
+                emu_cfg_ctx.update(|cfg| cfg.editor.set_diagnostics(lang, diagnostics));
+            }
+            CompileLanguage::C => {
+                let (files, options) = emu_cfg_ctx.with_untracked(|cfg| {
+                    (
+                        cfg.editor.c_files.clone(),
+                        cfg.editor.compile_options.clone(),
+                    )
+                });
+                if files.iter().all(|file| file.code.trim().is_empty()) {
+                    emu_cfg_ctx.update(|cfg| cfg.editor.set_diagnostics(lang, vec![]));
+                    return;
+                }
+                spawn_local(async move {
+                    // Signed-out users and network failures keep the last diagnostics.
+                    let Ok(result) = c_syntax_check(files, options).await else {
+                        return;
+                    };
+                    if generation.get_value() != current {
+                        return;
+                    }
+                    let diagnostics = syntax_check_diagnostics(result.rc, &result.stderr);
+                    emu_cfg_ctx.update(|cfg| cfg.editor.set_diagnostics(lang, diagnostics));
+                })
+            }
+        }
+    };
+    Effect::watch(
+        move || buffer.get(),
+        move |(lang, source, _), _, _| {
+            if let Some(handle) = pending.get_value() {
+                handle.clear();
+            }
+            let (lang, source) = (*lang, source.clone());
+            let handle = set_timeout_with_handle(move || run_check(lang, source), CHECK_DELAY);
+            pending.set_value(handle.ok());
+        },
+        false,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(
+        file: &str,
+        line: usize,
+        column: Option<usize>,
+        severity: Severity,
+        message: &str,
+    ) -> Diagnostic {
+        Diagnostic {
+            file: Some(file.to_string()),
+            line,
+            column,
+            severity,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn gcc_messages_with_columns() {
+        let stderr = "main.c: In function 'main':\n\
+            main.c:4:9: error: 'SPEED' undeclared (first use in this function)\n    \
+            4 | int x = SPEED;\n      \
+            |         ^~~~~\n\
+            util.h:2:5: warning: unused variable 'y' [-Wunused-variable]\n";
+        assert_eq!(
+            parse_compiler_output(stderr),
+            vec![
+                diagnostic(
+                    "main.c",
+                    4,
+                    Some(9),
+                    Severity::Error,
+                    "'SPEED' undeclared (first use in this function)"
+                ),
+                diagnostic(
+                    "util.h",
+                    2,
+                    Some(5),
+                    Severity::Warning,
+                    "unused variable 'y' [-Wunused-variable]"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sdcc_trailing_column_and_numbered_warnings() {
+        let stderr = "main.c:3: syntax error: token -> '}' ; column 1\n\
+            main.c:7: warning 112: function 'f' implicit declaration\n";
+        assert_eq!(
+            parse_compiler_output(stderr),
+            vec![
+                diagnostic(
+                    "main.c",
+                    3,
+                    Some(1),
+                    Severity::Error,
+                    "token -> '}' ; column 1"
+                ),
+                diagnostic(
+                    "main.c",
+                    7,
+                    None,
+                    Severity::Warning,
+                    "function 'f' implicit declaration"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeats_from_a_shared_header_are_dropped() {
+        let stderr = "util.h:1:1: error: unknown type name 'u8'\n\
+            util.h:1:1: error: unknown type name 'u8'\n\
+            util.h:1:1: error: unknown type name 'u8'\n";
+        assert_eq!(parse_compiler_output(stderr).len(), 1);
+    }
+
+    #[test]
+    fn failures_without_messages_still_report() {
+        let diagnostics = syntax_check_diagnostics(1, "\nzcc: cannot run the compiler\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].file, None);
+        assert_eq!(diagnostics[0].message, "zcc: cannot run the compiler");
+        assert!(syntax_check_diagnostics(0, "").is_empty());
+    }
+}
@@ -1,7 +1,20 @@
+use super::control::{download_file, run_until, RunUntil};
+use super::history::write_bytes;
+use super::listing::export_listing;
 use super::{emu_style, EmulatorCfgContext, EmulatorContext};
 use emu_lib::cpu::instruction::{ExecutableInstruction, InstructionParser};
 use emu_lib::cpu::z80::parser::Z80_PARSER;
+use emu_lib::memory::{Memory, MemoryDevice};
+use leptos::ev::KeyboardEvent;
+use leptos::html;
 use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use stylance::classes;
+
+/// Bytes shown per `DB` line inside a data region.
+const DATA_ROW_BYTES: u16 = 4;
+/// How far back to look when resynchronising a backwards scroll.
+const MAX_BACKTRACK: u16 = 24;
 
 #[derive(Clone, Copy, Debug)]
 pub enum DisassemblerDisplayMode {
@@ -9,8 +22,9 @@ pub enum DisassemblerDisplayMode {
     Bytes,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub struct DisassemblerContext {
+    /// First address shown; `None` follows the program counter.
     pub start: Option<u16>,
     pub rows: u16,
 }
@@ -24,6 +38,130 @@ impl Default for DisassemblerContext {
     }
 }
 
+/// Inclusive address range that the disassembler shows as `DB` bytes.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct DataRegion {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl DataRegion {
+    pub fn contains(&self, address: u16) -> bool {
+        self.start <= address && address <= self.end
+    }
+}
+
+pub fn region_at(regions: &[DataRegion], address: u16) -> Option<DataRegion> {
+    regions.iter().find(|region| region.contains(address)).copied()
+}
+
+/// Marks `start..=end` as data, merging with any regions it touches.
+pub fn mark_data(regions: &mut Vec<DataRegion>, start: u16, end: u16) {
+    let mut merged = DataRegion { start, end };
+    regions.retain(|region| {
+        let touches = region.start <= merged.end.saturating_add(1)
+            && merged.start <= region.end.saturating_add(1);
+        if touches {
+            merged.start = merged.start.min(region.start);
+            merged.end = merged.end.max(region.end);
+        }
+        !touches
+    });
+    regions.push(merged);
+    regions.sort_by_key(|region| region.start);
+}
+
+/// Removes `start..=end` from the data regions, splitting regions that straddle it.
+pub fn unmark_data(regions: &mut Vec<DataRegion>, start: u16, end: u16) {
+    let mut kept = vec![];
+    for region in regions.drain(..) {
+        if region.end < start || end < region.start {
+            kept.push(region);
+            continue;
+        }
+        if region.start < start {
+            kept.push(DataRegion {
+                start: region.start,
+                end: start - 1,
+            });
+        }
+        if end < region.end {
+            kept.push(DataRegion {
+                start: end + 1,
+                end: region.end,
+            });
+        }
+    }
+    *regions = kept;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisasmLine {
+    pub address: u16,
+    pub length: u16,
+    /// Set for `DB` lines inside a marked data region.
+    pub data: bool,
+}
+
+pub fn line_at(memory: &Memory, regions: &[DataRegion], address: u16) -> DisasmLine {
+    if let Some(region) = region_at(regions, address) {
+        let row_start = region.start + (address - region.start) / DATA_ROW_BYTES * DATA_ROW_BYTES;
+        let remaining = region.end - address;
+        return DisasmLine {
+            address,
+            length: (DATA_ROW_BYTES - (address - row_start)).min(remaining.saturating_add(1)),
+            data: true,
+        };
+    }
+    let length = match Z80_PARSER.ins_from_machinecode(memory, address) {
+        Ok(instruction) => instruction.common().length as u16,
+        Err(_) => 1,
+    };
+    DisasmLine {
+        address,
+        length: length.max(1),
+        data: false,
+    }
+}
+
+pub fn disasm_lines(
+    memory: &Memory,
+    regions: &[DataRegion],
+    start: u16,
+    rows: u16,
+) -> Vec<DisasmLine> {
+    let mut address = start;
+    (0..rows)
+        .map(|_| {
+            let line = line_at(memory, regions, address);
+            address = address.wrapping_add(line.length);
+            line
+        })
+        .collect()
+}
+
+/// Finds the start of the line before `address`. Variable-length code has no
+/// fixed boundaries, so this decodes forward from progressively closer addresses
+/// and takes the first sweep that lands exactly on `address`.
+pub fn previous_line(memory: &Memory, regions: &[DataRegion], address: u16) -> u16 {
+    let before = address.wrapping_sub(1);
+    if let Some(region) = region_at(regions, before) {
+        return region.start + (before - region.start) / DATA_ROW_BYTES * DATA_ROW_BYTES;
+    }
+    for back in (1..=MAX_BACKTRACK).rev() {
+        let mut current = address.wrapping_sub(back);
+        let mut last = current;
+        while current != address && address.wrapping_sub(current) <= back {
+            last = current;
+            current = current.wrapping_add(line_at(memory, regions, current).length);
+        }
+        if current == address {
+            return last;
+        }
+    }
+    before
+}
+
 #[component]
 pub fn DisassemblerTHead() -> impl IntoView {
     view! {
@@ -39,8 +177,8 @@ pub fn DisassemblerTHead() -> impl IntoView {
 }
 
 #[island]
-pub fn DisassemblerTRow(address: usize) -> impl IntoView {
-    // let ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+pub fn DisassemblerTRow(address: usize, data_len: Option<u16>) -> impl IntoView {
+    let ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
     let emu = expect_context::<RwSignal<EmulatorContext>>();
     let instruction = move || {
         // "N/A".to_string()
@@ -53,7 +191,26 @@ pub fn DisassemblerTRow(address: usize) -> impl IntoView {
                 .map_err(|err| err.to_string())
         })
     };
+    let data_bytes = move |len: u16| {
+        emu.with(|emu| {
+            (0..len)
+                .map(|offset| {
+                    emu.emu
+                        .memory
+                        .read_8((address as u16).wrapping_add(offset))
+                        .unwrap_or(0)
+                })
+                .collect::<Vec<u8>>()
+        })
+    };
     let ins_bytes = Memo::new(move |_| {
+        if let Some(len) = data_len {
+            return data_bytes(len)
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
         if let Ok(instruction) = instruction() {
             instruction
                 .to_bytes()
@@ -66,6 +223,14 @@ pub fn DisassemblerTRow(address: usize) -> impl IntoView {
         }
     });
     let ins_string = Memo::new(move |_| {
+        if let Some(len) = data_len {
+            let bytes = data_bytes(len)
+                .iter()
+                .map(|b| format!("0x{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(", ");
+            return format!("DB {}", bytes);
+        }
         if let Ok(instruction) = instruction() {
             instruction.to_string()
         } else {
@@ -75,6 +240,7 @@ pub fn DisassemblerTRow(address: usize) -> impl IntoView {
     let is_breakpoint = Memo::new(move |_| {
         emu.with(|emu| emu.emu.breakpoints.iter().any(|&bp| bp as usize == address))
     });
+    let is_pc = Memo::new(move |_| emu.with(|emu| emu.emu.cpu.registers.pc as usize == address));
     let breakpoint = move || {
         if is_breakpoint() {
             "⬤".to_string()
@@ -91,13 +257,134 @@ pub fn DisassemblerTRow(address: usize) -> impl IntoView {
             }
         });
     };
+    let editing = RwSignal::new(false);
+    // Only a decoded instruction is valid patch input; `DB` rows and undecodable
+    // bytes start empty so that confirming them unedited is a no-op.
+    let patch_value = move || {
+        if data_len.is_some() || address > (u16::MAX as usize) {
+            return String::new();
+        }
+        emu.with_untracked(|emu| {
+            Z80_PARSER
+                .ins_from_machinecode(&emu.emu.memory, address as u16)
+                .map(|instruction| instruction.to_string())
+                .unwrap_or_default()
+        })
+    };
+    let patch_input: NodeRef<html::Input> = NodeRef::new();
+    Effect::new(move |_| {
+        if let Some(input) = patch_input.get() {
+            let _ = input.focus();
+        }
+    });
+    // Assembles the typed instruction over the current one. Shorter encodings are
+    // padded with NOPs; longer ones overwrite what follows, so warn about it.
+    let patch = move |text: String| {
+        editing.set(false);
+        let text = text.trim().to_string();
+        if text.is_empty() || address > (u16::MAX as usize) {
+            return;
+        }
+        let address = address as u16;
+        emu.update(|emu| {
+            ctx.update(|ctx| {
+                let mut bytes = match Z80_PARSER.ins_from_asm_string(&text) {
+                    Ok(instruction) => instruction.to_bytes(),
+                    Err(_) => {
+                        ctx.logstore.log_error(
+                            "Patch error",
+                            format!("Patch error: invalid instruction \"{}\"", text),
+                        );
+                        return;
+                    }
+                };
+                let old_len = data_len.unwrap_or_else(|| {
+                    line_at(&emu.emu.memory, &ctx.editor.data_regions, address).length
+                }) as usize;
+                if bytes.len() < old_len {
+                    bytes.resize(old_len, 0x00);
+                } else if bytes.len() > old_len {
+                    ctx.logstore.log_warning(
+                        "Patch overwrites next instruction",
+                        format!(
+                            "Patch at {:#06X} is {} bytes, replacing a {} byte instruction",
+                            address,
+                            bytes.len(),
+                            old_len
+                        ),
+                    );
+                }
+                let description = format!("Patch {:#06X}: {}", address, text);
+                let (entry, result) = write_bytes(
+                    &mut emu.emu.memory,
+                    description.clone(),
+                    bytes
+                        .iter()
+                        .enumerate()
+                        .map(|(offset, byte)| (address.wrapping_add(offset as u16), *byte)),
+                );
+                ctx.history.record(entry);
+                match result {
+                    Ok(()) => ctx.logstore.log_info("Instruction patched", description),
+                    Err(err) => ctx
+                        .logstore
+                        .log_error("Patch error", format!("Patch error: {}", err)),
+                }
+            });
+        });
+    };
+    let profile = ctx.with_untracked(|ctx| ctx.profiler.profile);
+    let heat = Memo::new(move |_| profile.with(|profile| profile.heat(address as u16)));
+    // Profiler heat as a bar behind the address, scaled to the hottest address.
+    let heat_style = move || {
+        let heat = heat.get();
+        if heat > 0.0 {
+            format!(
+                "linear-gradient(to right, rgba(255, 87, 34, {:.2}) {:.0}%, transparent 0)",
+                0.3 + heat * 0.5,
+                heat * 100.0
+            )
+        } else {
+            String::new()
+        }
+    };
+    let row_class = move || {
+        classes!(
+            if is_pc() { emu_style::currentpc } else { "" },
+            if data_len.is_some() { emu_style::datarow } else { "" }
+        )
+    };
     view! {
-        <tr>
-            <th>{move || format!("{:04X}", address)}</th>
+        <tr class=row_class>
+            <th
+                style:background=heat_style
+                title="Double-click to run to here"
+                on:dblclick=move |_| {
+                    ctx.update(|ctx| {
+                        run_until(ctx, RunUntil::RunToCursor { address: address as u16 })
+                    })
+                }
+            >
+                {move || format!("{:04X}", address)}
+            </th>
             <td class=emu_style::breakpoint on:click=toggle_breakpoint>
                 {breakpoint}
             </td>
-            <td>{ins_string}</td>
+            <td title="Double-click to patch" on:dblclick=move |_| editing.set(true)>
+                <Show when=move || editing.get() fallback=move || ins_string.get()>
+                    <input
+                        node_ref=patch_input
+                        class=emu_style::patchinput
+                        prop:value=patch_value()
+                        on:keydown=move |ev| match ev.key().as_str() {
+                            "Enter" => patch(event_target_value(&ev)),
+                            "Escape" => editing.set(false),
+                            _ => {}
+                        }
+                        on:blur=move |_| editing.set(false)
+                    />
+                </Show>
+            </td>
             <td>{ins_bytes}</td>
         </tr>
     }
@@ -108,46 +395,225 @@ pub fn DisassemblerTBody() -> impl IntoView {
     let ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
     let disasm = Memo::new(move |_| ctx.with(|ctx| ctx.disasm_config));
     let emu = expect_context::<RwSignal<EmulatorContext>>();
-    let pc = Memo::new(move |_| {
-        emu.with(|emu| emu.emu.cpu.registers.pc)
-    });
     let table_rows = move || {
-        let mut offset = 0;
-        let mut rows = vec![];
         let start = match disasm.with(|disasm| disasm.start) {
             Some(start) => start,
             None => emu.with(|emu| emu.emu.cpu.registers.pc),
         };
-        for _ in 0..disasm.with(|disasm| disasm.rows) {
-            let address: usize = start as usize + offset;
-            if address > (u16::MAX as usize) {
-                rows.push(view! { <DisassemblerTRow address /> });
-            } else {
-                rows.push(view! { <DisassemblerTRow address /> });
+        let rows = disasm.with(|disasm| disasm.rows);
+        let lines = emu.with(|emu| {
+            ctx.with(|ctx| disasm_lines(&emu.emu.memory, &ctx.editor.data_regions, start, rows))
+        });
+        lines
+            .into_iter()
+            .map(|line| {
+                let address = line.address as usize;
+                let data_len = if line.data { Some(line.length) } else { None };
+                view! { <DisassemblerTRow address data_len /> }
+            })
+            .collect_view()
+    };
+    view! { <tbody>{table_rows}</tbody> }
+}
+
+#[island]
+fn DisassemblerTools() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let goto_value = RwSignal::new(String::new());
+    let data_start = RwSignal::new(String::new());
+    let data_end = RwSignal::new(String::new());
+    let export_start = RwSignal::new(String::new());
+    let export_end = RwSignal::new(String::new());
+    let following = Memo::new(move |_| emu_cfg_ctx.with(|cfg| cfg.disasm_config.start.is_none()));
+
+    let goto = move || {
+        let value = goto_value.get_untracked();
+        emu_cfg_ctx.update(|cfg| match cfg.symbols.resolve(&value) {
+            Some(address) => cfg.disasm_config.start = Some(address),
+            None => cfg
+                .logstore
+                .log_error("Invalid address", format!("Invalid address: {}", value)),
+        });
+    };
+    let set_following = move |follow: bool| {
+        let pc = emu_ctx.with_untracked(|emu| emu.emu.cpu.registers.pc);
+        emu_cfg_ctx.update(|cfg| {
+            cfg.disasm_config.start = if follow { None } else { Some(pc) };
+        });
+    };
+    let change_data = move |mark: bool| {
+        emu_cfg_ctx.update(|cfg| {
+            let start = cfg.symbols.resolve(&data_start.get_untracked());
+            let end = cfg.symbols.resolve(&data_end.get_untracked());
+            match (start, end) {
+                (Some(start), Some(end)) if start <= end => {
+                    if mark {
+                        mark_data(&mut cfg.editor.data_regions, start, end);
+                        cfg.logstore.log_info(
+                            "Data region marked",
+                            format!("Marked {:#06X}-{:#06X} as data", start, end),
+                        );
+                    } else {
+                        unmark_data(&mut cfg.editor.data_regions, start, end);
+                        cfg.logstore.log_info(
+                            "Data region cleared",
+                            format!("Marked {:#06X}-{:#06X} as code", start, end),
+                        );
+                    }
+                }
+                _ => cfg.logstore.log_error(
+                    "Invalid address range",
+                    "Data region error: invalid address range".to_string(),
+                ),
             }
-            let ins_size = emu.with(|emu| {
-                if let Ok(instruction) =
-                    Z80_PARSER.ins_from_machinecode(&emu.emu.memory, address as u16)
-                {
-                    instruction.common().length
-                } else {
-                    1
+        });
+    };
+    let export = move || {
+        let listing = emu_ctx.with_untracked(|emu| {
+            emu_cfg_ctx.with_untracked(|cfg| {
+                let start = cfg.symbols.resolve(&export_start.get_untracked());
+                let end = cfg.symbols.resolve(&export_end.get_untracked());
+                match (start, end) {
+                    (Some(start), Some(end)) if start <= end => Some(export_listing(
+                        &emu.emu.memory,
+                        start,
+                        end,
+                        &cfg.editor.data_regions,
+                        &cfg.symbols,
+                    )),
+                    _ => None,
                 }
-            });
-            offset += ins_size as usize;
+            })
+        });
+        match listing {
+            Some(listing) => {
+                download_file("disassembly.asm", "text/plain", listing.as_bytes());
+                emu_cfg_ctx.update(|cfg| {
+                    cfg.logstore.log_info(
+                        "Listing exported",
+                        format!("Exported {} lines of disassembly", listing.lines().count()),
+                    )
+                });
+            }
+            None => emu_cfg_ctx.update(|cfg| {
+                cfg.logstore.log_error(
+                    "Invalid address range",
+                    "Export error: invalid address range".to_string(),
+                )
+            }),
         }
-        rows
     };
-    view! { <tbody>{table_rows}</tbody> }
+    view! {
+        <div class=emu_style::disasmtools>
+            <div>
+                <input
+                    placeholder="Goto"
+                    prop:value=goto_value
+                    on:input=move |ev| goto_value.set(event_target_value(&ev))
+                    on:keydown=move |ev| {
+                        if ev.key() == "Enter" {
+                            goto();
+                        }
+                    }
+                />
+                <input type="button" value="Go" on:click=move |_| goto() />
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=following
+                        on:change=move |ev| set_following(event_target_checked(&ev))
+                    />
+                    Follow PC
+                </label>
+            </div>
+            <div>
+                <span>Data:</span>
+                <input
+                    placeholder="Start"
+                    prop:value=data_start
+                    on:input=move |ev| data_start.set(event_target_value(&ev))
+                />
+                <input
+                    placeholder="End"
+                    prop:value=data_end
+                    on:input=move |ev| data_end.set(event_target_value(&ev))
+                />
+                <input type="button" value="Mark" on:click=move |_| change_data(true) />
+                <input type="button" value="Unmark" on:click=move |_| change_data(false) />
+            </div>
+            <div>
+                <span>Export:</span>
+                <input
+                    placeholder="Start"
+                    prop:value=export_start
+                    on:input=move |ev| export_start.set(event_target_value(&ev))
+                />
+                <input
+                    placeholder="End"
+                    prop:value=export_end
+                    on:input=move |ev| export_end.set(event_target_value(&ev))
+                />
+                <input type="button" value=".asm" on:click=move |_| export() />
+            </div>
+        </div>
+    }
 }
+
 #[island]
 pub fn Disassembler() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    // Scrolling detaches the view from PC; re-enable following from the toolbar.
+    let scroll = move |lines: i32| {
+        emu_ctx.with_untracked(|emu| {
+            emu_cfg_ctx.update(|cfg| {
+                let regions = &cfg.editor.data_regions;
+                let mut start = cfg
+                    .disasm_config
+                    .start
+                    .unwrap_or(emu.emu.cpu.registers.pc);
+                for _ in 0..lines.unsigned_abs() {
+                    start = if lines > 0 {
+                        let line = line_at(&emu.emu.memory, regions, start);
+                        start.wrapping_add(line.length)
+                    } else {
+                        previous_line(&emu.emu.memory, regions, start)
+                    };
+                }
+                cfg.disasm_config.start = Some(start);
+            });
+        });
+    };
+    let on_keydown = move |ev: KeyboardEvent| {
+        let page = emu_cfg_ctx.with_untracked(|cfg| cfg.disasm_config.rows as i32 - 1);
+        let lines = match ev.key().as_str() {
+            "ArrowDown" => 1,
+            "ArrowUp" => -1,
+            "PageDown" => page,
+            "PageUp" => -page,
+            _ => return,
+        };
+        ev.prevent_default();
+        scroll(lines);
+    };
     view! {
         <div class=emu_style::disassembler>
             <div class=emu_style::sectop>
                 <span>Disassembler</span>
             </div>
-            <table class=emu_style::disassemblertable>
+            <DisassemblerTools />
+            <table
+                class=emu_style::disassemblertable
+                tabindex="0"
+                on:keydown=on_keydown
+                on:wheel=move |ev| {
+                    ev.prevent_default();
+                    let delta_y = ev.delta_y();
+                    let lines = (delta_y.abs() / 50.0).ceil() as i32 * delta_y.signum() as i32;
+                    scroll(lines);
+                }
+            >
                 <DisassemblerTHead />
                 <DisassemblerTBody />
             </table>
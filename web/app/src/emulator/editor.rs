@@ -1,13 +1,25 @@
+use super::assembler::assemble;
+use super::code_editor::CodeEditor;
+use super::diagnostics::{live_check, syntax_check_diagnostics};
+use super::disassembler::DataRegion;
+use super::generated::{parse_listing, parse_map, GeneratedAsm, GeneratedLine};
+use super::compile_options::CompileOptionsPanel;
+use super::project::ProjectFiles;
+use super::symbols::SymbolTable;
 use super::{emu_style, EmulatorCfgContext, EmulatorContext};
-use crate::utils::ccompiler::{c_compile, c_format, c_syntax_check, CompilerError};
-use emu_lib::cpu::instruction::InstructionParser;
-use emu_lib::cpu::z80::parser::Z80_PARSER;
-use leptos::ev::{Event, Targeted};
+use crate::utils::ccompiler::{
+    c_compile_cancel, c_compile_stream, c_format, c_syntax_check, CompileData, CompileEvent,
+    CompileEventReader, CompileOptions, CompilePhase, CompilerError, OutputStream, SourceFile,
+};
+use emu_lib::cpu::z80::Z80;
+use emu_lib::emulator::Emulator;
+use emu_lib::memory::MemoryDevice;
+use futures::StreamExt;
 use leptos::logging::log;
 use leptos::prelude::*;
 use leptos::task::spawn_local;
-use leptos::web_sys::HtmlTextAreaElement;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use stylance::classes;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
@@ -15,48 +27,195 @@ pub enum CompileLanguage {
     ASM,
     C,
 }
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Name of the C file a new project starts with.
+pub const MAIN_FILE: &str = "main.c";
+
+/// A problem in an editor buffer at a 1-based line and, when known, column.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// Project file the problem is in; `None` for the assembly buffer.
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub struct EditorContext {
     pub active_lang: CompileLanguage,
-    pub c_buffer: String,
+    /// Sources and headers of the C project, compiled together.
+    pub c_files: Vec<SourceFile>,
+    /// Index in `c_files` of the file open in the editor.
+    pub c_active: usize,
+    #[serde(default)]
+    pub compile_options: CompileOptions,
     pub asm_buffer: String,
+    /// Address ranges the disassembler treats as data, kept with the program.
+    pub data_regions: Vec<DataRegion>,
+    /// Rhai source for the script console, kept with the program.
+    pub script: String,
+    /// Problems shown in the gutter of each buffer; recomputed, so not saved.
+    #[serde(skip)]
+    pub c_diagnostics: Vec<Diagnostic>,
+    #[serde(skip)]
+    pub asm_diagnostics: Vec<Diagnostic>,
+    /// Address of the code each ASM line assembled to in the last build.
+    #[serde(skip)]
+    pub asm_addresses: BTreeMap<usize, u16>,
+    /// Assembly listing of the last C build, when it asked for one.
+    #[serde(skip)]
+    pub generated_asm: Vec<GeneratedLine>,
 }
 
 impl Default for EditorContext {
     fn default() -> Self {
         EditorContext {
             active_lang: CompileLanguage::ASM,
-            c_buffer: String::new(),
+            c_files: vec![SourceFile::new(MAIN_FILE, String::new())],
+            c_active: 0,
+            compile_options: CompileOptions::default(),
             asm_buffer: String::new(),
+            data_regions: vec![],
+            script: String::new(),
+            c_diagnostics: vec![],
+            asm_diagnostics: vec![],
+            asm_addresses: BTreeMap::new(),
+            generated_asm: vec![],
         }
     }
 }
 
 impl EditorContext {
+    pub fn buffer(&self, lang: CompileLanguage) -> &str {
+        match lang {
+            CompileLanguage::ASM => &self.asm_buffer,
+            CompileLanguage::C => self.active_c_file().map_or("", |file| &file.code),
+        }
+    }
+
     pub fn write_buffer(&mut self, lang: CompileLanguage, buffer: String) {
         match lang {
             CompileLanguage::ASM => self.asm_buffer = buffer,
-            CompileLanguage::C => self.c_buffer = buffer,
+            CompileLanguage::C => {
+                if let Some(file) = self.c_files.get_mut(self.c_active) {
+                    file.code = buffer;
+                }
+            }
+        }
+    }
+
+    pub fn active_c_file(&self) -> Option<&SourceFile> {
+        self.c_files.get(self.c_active)
+    }
+
+    pub fn c_file_index(&self, name: &str) -> Option<usize> {
+        self.c_files.iter().position(|file| file.name == name)
+    }
+
+    /// Replaces the contents of a C file by name, wherever the editor is.
+    pub fn write_c_file(&mut self, name: &str, code: String) {
+        if let Some(index) = self.c_file_index(name) {
+            self.c_files[index].code = code;
         }
     }
-}
-#[island]
-pub fn EditorText(lang: CompileLanguage) -> impl IntoView {
-    let emu_ctx_signal = expect_context::<RwSignal<EmulatorCfgContext>>();
-    let set_buffer = move |ev: Targeted<Event, HtmlTextAreaElement>| {
-        emu_ctx_signal.update(|emu_ctx| {
-            emu_ctx.editor.write_buffer(lang, ev.target().value());
-        });
-    };
-    let get_buffer = move || {
-        emu_ctx_signal.with(|emu_ctx| match lang {
-            CompileLanguage::ASM => emu_ctx.editor.asm_buffer.clone(),
-            CompileLanguage::C => emu_ctx.editor.c_buffer.clone(),
-        })
-    };
-    view! { <textarea spellcheck="false" on:input:target=set_buffer prop:value=get_buffer></textarea> }
-}
 
+    pub fn select_c_file(&mut self, index: usize) {
+        if index < self.c_files.len() {
+            self.c_active = index;
+        }
+    }
+
+    fn check_new_name(&self, name: &str) -> Result<(), String> {
+        if !SourceFile::valid_name(name) {
+            return Err(format!("\"{}\" is not a valid .c or .h file name", name));
+        }
+        if self.c_file_index(name).is_some() {
+            return Err(format!("{} already exists", name));
+        }
+        Ok(())
+    }
+
+    /// Adds an empty file to the C project and opens it.
+    pub fn add_c_file(&mut self, name: &str) -> Result<(), String> {
+        self.check_new_name(name)?;
+        self.c_files.push(SourceFile::new(name, String::new()));
+        self.c_active = self.c_files.len() - 1;
+        Ok(())
+    }
+
+    /// Whether `index` is the project's only source file, which must stay one.
+    fn is_last_source(&self, index: usize) -> Result<bool, String> {
+        let file = self.c_files.get(index).ok_or("No such file")?;
+        let sources = self.c_files.iter().filter(|file| !file.is_header()).count();
+        Ok(!file.is_header() && sources == 1)
+    }
+
+    /// Renames a file of the C project; the last source file cannot become a header.
+    pub fn rename_c_file(&mut self, index: usize, name: &str) -> Result<(), String> {
+        self.check_new_name(name)?;
+        let renamed = SourceFile::new(name, String::new());
+        if renamed.is_header() && self.is_last_source(index)? {
+            return Err("A project needs at least one .c file".to_string());
+        }
+        let file = self.c_files.get_mut(index).ok_or("No such file")?;
+        file.name = renamed.name;
+        Ok(())
+    }
+
+    /// Removes a file from the C project; the last source file cannot be removed.
+    pub fn remove_c_file(&mut self, index: usize) -> Result<(), String> {
+        if self.is_last_source(index)? {
+            return Err("A project needs at least one .c file".to_string());
+        }
+        self.c_files.remove(index);
+        if self.c_active > index || self.c_active == self.c_files.len() {
+            self.c_active = self.c_active.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    pub fn diagnostics(&self, lang: CompileLanguage) -> &[Diagnostic] {
+        match lang {
+            CompileLanguage::ASM => &self.asm_diagnostics,
+            CompileLanguage::C => &self.c_diagnostics,
+        }
+    }
+
+    /// Diagnostics for the file open in the editor, for the gutter and inline marks.
+    pub fn open_file_diagnostics(&self, lang: CompileLanguage) -> Vec<Diagnostic> {
+        let name = match lang {
+            CompileLanguage::ASM => None,
+            CompileLanguage::C => self.active_c_file().map(|file| file.name.as_str()),
+        };
+        self.diagnostics(lang)
+            .iter()
+            .filter(|diagnostic| diagnostic.file.is_none() || diagnostic.file.as_deref() == name)
+            .cloned()
+            .collect()
+    }
+
+    pub fn set_diagnostics(&mut self, lang: CompileLanguage, diagnostics: Vec<Diagnostic>) {
+        match lang {
+            CompileLanguage::ASM => self.asm_diagnostics = diagnostics,
+            CompileLanguage::C => self.c_diagnostics = diagnostics,
+        }
+    }
+
+    /// Address a source line was built to, if the last build produced code for it.
+    pub fn line_address(&self, lang: CompileLanguage, line: usize) -> Option<u16> {
+        match lang {
+            CompileLanguage::ASM => self.asm_addresses.get(&line).copied(),
+            CompileLanguage::C => None,
+        }
+    }
+}
 #[island]
 pub fn EditorTextAreas() -> impl IntoView {
     let emu_ctx_signal = expect_context::<RwSignal<EmulatorCfgContext>>();
@@ -64,106 +223,231 @@ pub fn EditorTextAreas() -> impl IntoView {
     let is_current_lang = move |lang: CompileLanguage| {
         emu_ctx_signal.with(|emu_ctx| emu_ctx.editor.active_lang == lang)
     };
+    let show_generated = RwSignal::new(false);
+    let tab_class = move |generated: bool| {
+        if show_generated.get() == generated {
+            classes!(emu_style::editortab, emu_style::editortabactive)
+        } else {
+            classes!(emu_style::editortab)
+        }
+    };
     view! {
         <div class=emu_style::editorta>
             <Show
                 when=move || is_current_lang(CompileLanguage::ASM)
                 fallback=move || { "".to_string() }
             >
-                <EditorText lang=CompileLanguage::ASM />
+                <CodeEditor lang=CompileLanguage::ASM />
             </Show>
             <Show
                 when=move || is_current_lang(CompileLanguage::C)
                 fallback=move || { "".to_string() }
             >
-                <EditorText lang=CompileLanguage::C />
+                <div class=emu_style::cproject>
+                    <div class=emu_style::cprojectside>
+                        <ProjectFiles />
+                        <CompileOptionsPanel />
+                    </div>
+                    <div class=emu_style::cprojectmain>
+                        <div class=emu_style::editortabs>
+                            <span class=move || tab_class(false) on:click=move |_| show_generated.set(false)>
+                                "Source"
+                            </span>
+                            <span class=move || tab_class(true) on:click=move |_| show_generated.set(true)>
+                                "Generated assembly"
+                            </span>
+                        </div>
+                        <Show
+                            when=move || show_generated.get()
+                            fallback=|| view! { <CodeEditor lang=CompileLanguage::C /> }
+                        >
+                            <GeneratedAsm />
+                        </Show>
+                    </div>
+                </div>
             </Show>
         </div>
     }
 }
 
+/// Copies a C build into memory at the `origin` it was linked for and points
+/// PC at it, where the startup code or `main` begins.
+fn load_c_program(emu: &mut Emulator<Z80>, data: &[u8], origin: u16) -> Result<(), String> {
+    if origin as usize + data.len() > 0x10000 {
+        return Err(format!(
+            "{} bytes at {:#06X} do not fit in memory",
+            data.len(),
+            origin
+        ));
+    }
+    for (offset, byte) in data.iter().enumerate() {
+        let address = origin.wrapping_add(offset as u16);
+        emu.memory
+            .write_8_force(address, *byte)
+            .map_err(|err| format!("{:#06X}: {:?}", address, err))?;
+    }
+    emu.cpu.registers.pc = origin;
+    Ok(())
+}
+
 #[island]
 pub fn EditorTop() -> impl IntoView {
     let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
     let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    // Set while a C compile runs; the job id arrives with its first event.
+    let compiling = RwSignal::new(false);
+    let compile_job = RwSignal::new(None::<String>);
+    let log_compile_error = move |err: CompilerError| {
+        emu_cfg_ctx.update(|emu_cfg_ctx| match err {
+            CompilerError::Unauthorized => emu_cfg_ctx.logstore.log_error(
+                "Unauthenticated",
+                "C Compilation error: Unauthorized".to_string(),
+            ),
+            CompilerError::Cancelled => emu_cfg_ctx.logstore.log_warning(
+                "C Compilation cancelled",
+                "C Compilation cancelled".to_string(),
+            ),
+            err => emu_cfg_ctx.logstore.log_error(
+                "C Compilation error",
+                format!("C Compilation error: {:?}", err),
+            ),
+        });
+    };
+    let on_compile_c_done = move |res: CompileData, origin: u16| {
+        if res.rc != 0 {
+            emu_cfg_ctx.update(|emu_cfg_ctx| {
+                emu_cfg_ctx.logstore.log_error(
+                    "C Compilation error",
+                    format!("C Compilation error: compiler exited with code {}", res.rc),
+                );
+            });
+            return;
+        }
+        emu_ctx.update(|emu_ctx| {
+            if let Err(err) = load_c_program(&mut emu_ctx.emu, &res.data, origin) {
+                emu_cfg_ctx.update(|emu_cfg_ctx| {
+                    emu_cfg_ctx.logstore.log_error(
+                        "C Compilation error",
+                        format!(
+                            "C Compilation error, writting into emulator memory: {}",
+                            err
+                        ),
+                    );
+                });
+            } else {
+                emu_cfg_ctx.update(|emu_cfg_ctx| {
+                    emu_cfg_ctx.symbols = SymbolTable::default();
+                    for (name, address) in parse_map(&res.map) {
+                        emu_cfg_ctx.symbols.insert(name, address);
+                    }
+                    emu_cfg_ctx.editor.generated_asm = parse_listing(&res.listing, &res.map);
+                    let source = if res.cached { " (cached)" } else { "" };
+                    emu_cfg_ctx.logstore.log_info(
+                        "C Compilation success",
+                        format!(
+                            "C Compilation success{}, program loaded into emulator memory at {:#06X}",
+                            source, origin
+                        ),
+                    );
+                });
+            }
+        });
+    };
+    let on_compile_event = move |event: CompileEvent, origin: u16| match event {
+        CompileEvent::Started { job } => compile_job.set(Some(job)),
+        CompileEvent::Phase { phase, file } => {
+            let message = match (phase, file) {
+                (CompilePhase::Queued, _) => "Waiting for a compiler worker".to_string(),
+                (CompilePhase::Compiling, Some(file)) => format!("Compiling {}", file),
+                (CompilePhase::Compiling, None) => "Compiling".to_string(),
+                (CompilePhase::Linking, _) => "Linking".to_string(),
+            };
+            emu_cfg_ctx
+                .update(|emu_cfg_ctx| emu_cfg_ctx.logstore.log_info("C Compilation", message));
+        }
+        CompileEvent::Output { stream, line } => emu_cfg_ctx.update(|emu_cfg_ctx| match stream {
+            OutputStream::Stdout => emu_cfg_ctx.logstore.log_info("C Compiler output", line),
+            OutputStream::Stderr => emu_cfg_ctx.logstore.log_warning("C Compiler output", line),
+        }),
+        CompileEvent::Done { data } => on_compile_c_done(data, origin),
+        CompileEvent::Failed { error } => log_compile_error(error),
+    };
     let on_compile_c = move || {
-        let code = emu_cfg_ctx.with(|emu_ctx| emu_ctx.editor.c_buffer.clone());
+        if compiling.get_untracked() {
+            return;
+        }
+        let (files, options) = emu_cfg_ctx.with(|emu_ctx| {
+            (
+                emu_ctx.editor.c_files.clone(),
+                emu_ctx.editor.compile_options.clone(),
+            )
+        });
+        // The build is linked for the origin it was started with.
+        let origin = options.origin;
+        compiling.set(true);
         spawn_local(async move {
-            let res = c_compile(code).await;
-            match res {
-                Ok(res) => {
-                    if res.rc != 0 {
-                        emu_cfg_ctx.update(|emu_cfg_ctx| {
-                            emu_cfg_ctx.logstore.log_error(
-                                "C Compilation error",
-                                format!("C Compilation error: {}", res.stderr),
-                            );
-                        });
-                        return;
-                    }
-                    emu_ctx.update(|emu_ctx| {
-                        if let Err(err) = emu_ctx.emu.memory.load(&res.data, true) {
-                            emu_cfg_ctx.update(|emu_cfg_ctx| {
-                                emu_cfg_ctx.logstore.log_error(
-                                    "C Compilation error",
-                                    format!(
-                                        "C Compilation error, writting into emulator memory: {:?}",
-                                        err
-                                    ),
-                                );
-                            });
-                        } else {
-                            emu_cfg_ctx.update(|emu_cfg_ctx| {
-                                emu_cfg_ctx.logstore.log_info(
-                                    "C Compilation success",
-                                    "C Compilation success, program loaded into emulator memory"
-                                        .to_string(),
-                                );
-                            });
+            match c_compile_stream(files, options).await {
+                Ok(stream) => {
+                    let mut stream = Box::pin(stream.into_inner());
+                    let mut reader = CompileEventReader::default();
+                    while let Some(chunk) = stream.next().await {
+                        match chunk.and_then(|chunk| reader.push(&chunk)) {
+                            Ok(events) => events
+                                .into_iter()
+                                .for_each(|event| on_compile_event(event, origin)),
+                            Err(err) => {
+                                log_compile_error(err);
+                                break;
+                            }
                         }
-                    });
-                }
-                Err(CompilerError::Unauthorized) => {
-                    emu_cfg_ctx.update(|emu_cfg_ctx| {
-                        emu_cfg_ctx.logstore.log_error(
-                            "Unauthenticated",
-                            "C Compilation error: Unauthorized".to_string(),
-                        );
-                    });
-                }
-                Err(err) => {
-                    emu_cfg_ctx.update(|emu_cfg_ctx| {
-                        emu_cfg_ctx.logstore.log_error(
-                            "C Compilation error",
-                            format!("C Compilation error: {:?}", err),
-                        );
-                    });
+                    }
                 }
+                Err(err) => log_compile_error(err),
+            }
+            compiling.set(false);
+            compile_job.set(None);
+        });
+    };
+    let on_cancel_c = move |_| {
+        let Some(job) = compile_job.get_untracked() else {
+            return;
+        };
+        spawn_local(async move {
+            if let Err(err) = c_compile_cancel(job).await {
+                emu_cfg_ctx.update(|emu_cfg_ctx| {
+                    emu_cfg_ctx.logstore.log_error(
+                        "C Compilation error",
+                        format!("Could not cancel the C compilation: {:?}", err),
+                    );
+                });
             }
         });
     };
     let on_compile_asm = move || {
         emu_cfg_ctx.update(|emu_cfg_ctx| {
-            let mut compiled: Vec<u8> = vec![];
-            for line in emu_cfg_ctx
-                .editor
-                .asm_buffer
-                .lines()
-                .map(|s| s.split("//").next().unwrap_or("").trim().to_string())
-                .filter(|s| !s.is_empty())
-            {
-                if let Ok(instruction) = Z80_PARSER.ins_from_asm_string(&line) {
-                    compiled.extend(instruction.to_bytes());
-                } else {
+            let output = match assemble(&emu_cfg_ctx.editor.asm_buffer) {
+                Ok(output) => output,
+                Err(err) => {
                     emu_cfg_ctx.logstore.log_error(
                         "ASM Compilation error",
-                        format!("ASM Compilation error: Invalid instruction: \"{}\"", line),
+                        format!(
+                            "ASM Compilation error on line {}: {}",
+                            err.line_number, err.message
+                        ),
                     );
+                    emu_cfg_ctx.editor.asm_diagnostics = vec![Diagnostic {
+                        file: None,
+                        line: err.line_number,
+                        column: None,
+                        severity: Severity::Error,
+                        message: err.message,
+                    }];
                     return;
                 }
-            }
+            };
+            emu_cfg_ctx.editor.asm_diagnostics.clear();
             emu_ctx.update(|emu_ctx| {
-                if let Err(err) = emu_ctx.emu.memory.load(&compiled, true) {
+                if let Err(err) = emu_ctx.emu.memory.load(&output.bytes, true) {
                     emu_cfg_ctx.logstore.log_error(
                         "ASM Compilation error",
                         format!(
@@ -172,6 +456,8 @@ pub fn EditorTop() -> impl IntoView {
                         ),
                     );
                 } else {
+                    emu_cfg_ctx.symbols = output.symbols;
+                    emu_cfg_ctx.editor.asm_addresses = output.lines;
                     emu_cfg_ctx.logstore.log_info(
                         "ASM Compilation success",
                         "ASM Compilation success, program loaded into emulator memory".to_string(),
@@ -191,16 +477,17 @@ pub fn EditorTop() -> impl IntoView {
         }
     };
     let on_format_c = move |_| {
-        let code = emu_cfg_ctx.with(|emu_ctx| emu_ctx.editor.c_buffer.clone());
+        let Some(file) = emu_cfg_ctx.with(|emu_ctx| emu_ctx.editor.active_c_file().cloned())
+        else {
+            return;
+        };
         spawn_local(async move {
-            let res = c_format(code).await;
+            let res = c_format(file.code).await;
             match res {
                 Ok(res) => {
                     log!("Formatted C code: {:?}", res);
                     emu_cfg_ctx.update(|emu_ctx| {
-                        emu_ctx
-                            .editor
-                            .write_buffer(CompileLanguage::C, res.data.clone());
+                        emu_ctx.editor.write_c_file(&file.name, res.data.clone());
                         emu_ctx.logstore.log_info(
                             "Formatted C code",
                             format!("Formatted C code: {}", res.data),
@@ -226,11 +513,22 @@ pub fn EditorTop() -> impl IntoView {
         });
     };
     let on_syntax_check_c = move |_| {
-        let code = emu_cfg_ctx.with(|emu_ctx| emu_ctx.editor.c_buffer.clone());
+        let (files, options) = emu_cfg_ctx.with(|emu_ctx| {
+            (
+                emu_ctx.editor.c_files.clone(),
+                emu_ctx.editor.compile_options.clone(),
+            )
+        });
         spawn_local(async move {
-            let res = c_syntax_check(code).await;
+            let res = c_syntax_check(files, options).await;
             match res {
                 Ok(res) => {
+                    let diagnostics = syntax_check_diagnostics(res.rc, &res.stderr);
+                    emu_cfg_ctx.update(|emu_ctx| {
+                        emu_ctx
+                            .editor
+                            .set_diagnostics(CompileLanguage::C, diagnostics)
+                    });
                     if res.stderr.is_empty() {
                         emu_cfg_ctx.update(|emu_ctx| {
                             emu_ctx.logstore.log_info(
@@ -283,6 +581,11 @@ pub fn EditorTop() -> impl IntoView {
         <div class=emu_style::editortop>
             <div class=emu_style::editortopbtns>
                 <button on:click=on_compile>"Compile"</button>
+                <Show when=move || compiling.get()>
+                    <button on:click=on_cancel_c disabled=move || compile_job.get().is_none()>
+                        "Cancel"
+                    </button>
+                </Show>
                 <button on:click=on_format_c>"Format"</button>
                 <button on:click=on_syntax_check_c>"Syntax Check"</button>
             </div>
@@ -315,6 +618,8 @@ pub fn EditorTop() -> impl IntoView {
 }
 #[island]
 pub fn Editor() -> impl IntoView {
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    live_check(emu_cfg_ctx);
     view! {
         <div class=emu_style::editor>
             <div class=emu_style::sectop>
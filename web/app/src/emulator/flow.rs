@@ -0,0 +1,144 @@
+use emu_lib::cpu::instruction::InstructionParser;
+use emu_lib::cpu::z80::parser::Z80_PARSER;
+use emu_lib::memory::{Memory, MemoryDevice};
+
+/// How an instruction affects the program counter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// Falls through to the next instruction.
+    Next,
+    Jump { target: u16, conditional: bool },
+    Call { target: u16, conditional: bool },
+    Return { conditional: bool },
+    /// `JP (HL)`, `JP (IX)` and `JP (IY)`: the target is only known at run time.
+    IndirectJump,
+    Halt,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Decoded {
+    pub address: u16,
+    pub length: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    pub flow: Flow,
+}
+
+impl Decoded {
+    pub fn next(&self) -> u16 {
+        self.address.wrapping_add(self.length)
+    }
+}
+
+fn word(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([
+        bytes.get(1).copied().unwrap_or(0),
+        bytes.get(2).copied().unwrap_or(0),
+    ])
+}
+
+fn relative(bytes: &[u8], address: u16) -> u16 {
+    let offset = bytes.get(1).copied().unwrap_or(0) as i8;
+    address.wrapping_add(2).wrapping_add(offset as u16)
+}
+
+/// Classifies an encoded instruction by its opcode bytes.
+pub fn classify(bytes: &[u8], address: u16) -> Flow {
+    let Some(&op) = bytes.first() else {
+        return Flow::Next;
+    };
+    match op {
+        0xC3 => Flow::Jump {
+            target: word(bytes),
+            conditional: false,
+        },
+        0xC2 | 0xCA | 0xD2 | 0xDA | 0xE2 | 0xEA | 0xF2 | 0xFA => Flow::Jump {
+            target: word(bytes),
+            conditional: true,
+        },
+        0x18 => Flow::Jump {
+            target: relative(bytes, address),
+            conditional: false,
+        },
+        0x10 | 0x20 | 0x28 | 0x30 | 0x38 => Flow::Jump {
+            target: relative(bytes, address),
+            conditional: true,
+        },
+        0xCD => Flow::Call {
+            target: word(bytes),
+            conditional: false,
+        },
+        0xC4 | 0xCC | 0xD4 | 0xDC | 0xE4 | 0xEC | 0xF4 | 0xFC => Flow::Call {
+            target: word(bytes),
+            conditional: true,
+        },
+        op if op & 0xC7 == 0xC7 => Flow::Call {
+            target: (op & 0x38) as u16,
+            conditional: false,
+        },
+        0xC9 => Flow::Return { conditional: false },
+        0xC0 | 0xC8 | 0xD0 | 0xD8 | 0xE0 | 0xE8 | 0xF0 | 0xF8 => {
+            Flow::Return { conditional: true }
+        }
+        0xED => match bytes.get(1) {
+            // RETN, RETI and their undocumented mirrors
+            Some(op) if op & 0xC7 == 0x45 => Flow::Return { conditional: false },
+            _ => Flow::Next,
+        },
+        0xE9 => Flow::IndirectJump,
+        0xDD | 0xFD if bytes.get(1) == Some(&0xE9) => Flow::IndirectJump,
+        0x76 => Flow::Halt,
+        _ => Flow::Next,
+    }
+}
+
+/// `DJNZ` and the repeating block instructions (`LDIR`, `CPIR`, `INIR`, `OTIR`
+/// and their decrementing forms) loop on themselves.
+pub fn is_repeating(bytes: &[u8]) -> bool {
+    match bytes {
+        [0x10, ..] => true,
+        [0xED, op, ..] => matches!(op, 0xB0..=0xB3 | 0xB8..=0xBB),
+        _ => false,
+    }
+}
+
+pub fn decode(memory: &Memory, address: u16) -> Option<Decoded> {
+    let instruction = Z80_PARSER.ins_from_machinecode(memory, address).ok()?;
+    let bytes = instruction.to_bytes();
+    Some(Decoded {
+        address,
+        length: (bytes.len() as u16).max(1),
+        flow: classify(&bytes, address),
+        text: instruction.to_string(),
+        bytes,
+    })
+}
+
+/// Bytes taken by the instruction at `address`, without decoding its text.
+pub fn instruction_length(memory: &Memory, address: u16) -> u16 {
+    Z80_PARSER
+        .ins_from_machinecode(memory, address)
+        .map_or(1, |instruction| {
+            (instruction.to_bytes().len() as u16).max(1)
+        })
+}
+
+/// If the word `return_address` looks like it was pushed by a `CALL` or `RST`,
+/// returns the address of that call instruction and where it went.
+pub fn call_before(memory: &Memory, return_address: u16) -> Option<(u16, u16)> {
+    let call_site = return_address.wrapping_sub(3);
+    let bytes = (0..3)
+        .map(|offset| memory.read_8(call_site.wrapping_add(offset)))
+        .collect::<Result<Vec<u8>, _>>()
+        .ok()?;
+    if let Flow::Call { target, .. } = classify(&bytes, call_site) {
+        if bytes[0] == 0xCD || bytes[0] & 0xC7 == 0xC4 {
+            return Some((call_site, target));
+        }
+    }
+    let rst_site = return_address.wrapping_sub(1);
+    match memory.read_8(rst_site) {
+        Ok(op) if op & 0xC7 == 0xC7 => Some((rst_site, (op & 0x38) as u16)),
+        _ => None,
+    }
+}
@@ -0,0 +1,282 @@
+use super::{emu_style, EmulatorCfgContext};
+use leptos::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// One line of the assembly zcc generated for a C build.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneratedLine {
+    /// Where the line ended up in memory, when its section could be placed.
+    pub address: Option<u16>,
+    pub bytes: String,
+    pub text: String,
+    /// Starts the listing of the named module.
+    pub module: Option<String>,
+}
+
+/// Symbol addresses from a z88dk map file: `name = $ADDR ; ...` lines.
+pub fn parse_map(map: &str) -> BTreeMap<String, u16> {
+    map.lines()
+        .filter_map(|line| {
+            let (name, rest) = line.split_once('=')?;
+            let hex = rest.trim_start().strip_prefix('$')?;
+            let end = hex.find(|c: char| !c.is_ascii_hexdigit()).unwrap_or(hex.len());
+            let address = u32::from_str_radix(&hex[..end], 16).ok()?;
+            let name = name.trim();
+            (!name.is_empty() && !name.contains(char::is_whitespace))
+                .then(|| (name.to_string(), address as u16))
+        })
+        .collect()
+}
+
+/// A listing line split into its offset within the section, the emitted bytes
+/// and the source text.
+struct ListingRow<'a> {
+    offset: Option<u16>,
+    bytes: String,
+    text: &'a str,
+}
+
+fn is_hex_byte(token: &str) -> bool {
+    token.len() == 2 && token.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Splits `  12  0004  3E 05        ld a,5`; lines without a line number and
+/// offset are kept as plain text.
+fn parse_row(line: &str) -> ListingRow<'_> {
+    let plain = ListingRow {
+        offset: None,
+        bytes: String::new(),
+        text: line,
+    };
+    let mut rest = line.trim_start();
+    let Some(number_end) = rest.find(|c: char| !c.is_ascii_digit()) else {
+        return plain;
+    };
+    if number_end == 0 {
+        return plain;
+    }
+    rest = rest[number_end..].trim_start();
+    let offset_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let offset = match u16::from_str_radix(&rest[..offset_end], 16) {
+        Ok(offset) if offset_end == 4 => offset,
+        _ => return plain,
+    };
+    rest = &rest[offset_end..];
+    let mut bytes = vec![];
+    loop {
+        let token = rest.trim_start_matches(' ');
+        // Bytes are separated by single spaces; the source follows a wider gap.
+        if rest.len() - token.len() > 2 && !bytes.is_empty() {
+            break;
+        }
+        let end = token.find(char::is_whitespace).unwrap_or(token.len());
+        if !is_hex_byte(&token[..end]) {
+            break;
+        }
+        bytes.push(&token[..end]);
+        rest = &token[end..];
+    }
+    ListingRow {
+        offset: Some(offset),
+        bytes: bytes.join(" "),
+        text: rest.trim_start(),
+    }
+}
+
+fn label(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let name = match text.strip_prefix('.') {
+        Some(name) => name.split_whitespace().next()?,
+        None => text.split_once(':')?.0,
+    };
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '.'));
+    valid.then_some(name)
+}
+
+fn section(text: &str) -> Option<&str> {
+    let mut words = text.split_whitespace();
+    let keyword = words.next()?;
+    keyword.eq_ignore_ascii_case("section").then(|| words.next()).flatten()
+}
+
+/// Lines of the listings in `listing`, each placed at its address in memory.
+/// Offsets in a listing are relative to the section in that module, so each
+/// section's base comes from the first of its labels found in the map.
+pub fn parse_listing(listing: &str, map: &str) -> Vec<GeneratedLine> {
+    let symbols = parse_map(map);
+    let mut rows = vec![];
+    let mut bases: HashMap<(usize, String), u16> = HashMap::new();
+    let mut module = 0;
+    let mut current_section = String::new();
+    for line in listing.lines() {
+        let header = line
+            .strip_prefix("; ---- ")
+            .and_then(|line| line.strip_suffix(" ----"));
+        if header.is_some() {
+            module += 1;
+            current_section.clear();
+        }
+        let row = parse_row(line);
+        if let Some(name) = section(row.text) {
+            current_section = name.to_string();
+        }
+        let key = (module, current_section.clone());
+        if let (Some(offset), Some(address)) = (
+            row.offset,
+            label(row.text).and_then(|name| symbols.get(name)),
+        ) {
+            bases
+                .entry(key.clone())
+                .or_insert(address.wrapping_sub(offset));
+        }
+        rows.push((key, header.map(str::to_string), row));
+    }
+    rows.into_iter()
+        .map(|(key, module, row)| GeneratedLine {
+            address: row
+                .offset
+                .zip(bases.get(&key))
+                .map(|(offset, base)| base.wrapping_add(offset)),
+            bytes: row.bytes,
+            text: row.text.to_string(),
+            module,
+        })
+        .collect()
+}
+
+/// Read-only view of the generated assembly. Clicking an address shows it in
+/// the disassembler.
+#[island]
+pub fn GeneratedAsm() -> impl IntoView {
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let lines = Memo::new(move |_| emu_cfg_ctx.with(|cfg| cfg.editor.generated_asm.clone()));
+    let show = move |address: u16| {
+        emu_cfg_ctx.update(|cfg| cfg.disasm_config.start = Some(address));
+    };
+    let rows = move || {
+        lines
+            .get()
+            .into_iter()
+            .map(|line| match line.module {
+                Some(module) => view! {
+                    <tr class=emu_style::generatedmodule>
+                        <td colspan="3">{module}</td>
+                    </tr>
+                }
+                .into_any(),
+                None => {
+                    let address = line.address.map(|address| {
+                        view! {
+                            <span on:click=move |_| show(address)>
+                                {format!("{:04X}", address)}
+                            </span>
+                        }
+                    });
+                    view! {
+                        <tr>
+                            <td class=emu_style::generatedaddress>{address}</td>
+                            <td class=emu_style::generatedbytes>{line.bytes}</td>
+                            <td>{line.text}</td>
+                        </tr>
+                    }
+                    .into_any()
+                }
+            })
+            .collect_view()
+    };
+
+    view! {
+        <div class=emu_style::generatedasm>
+            <Show
+                when=move || lines.with(|lines| !lines.is_empty())
+                fallback=|| {
+                    view! {
+                        <p>"Turn on Listing in the options and compile to see the generated assembly."</p>
+                    }
+                }
+            >
+                <table>
+                    <tbody>{rows}</tbody>
+                </table>
+            </Show>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two modules as zcc lists them, both with code in `code_compiler`: one
+    /// from sccz80 with `.name` labels, one from sdcc with `name:` labels.
+    const LISTING: &str = "\
+; ---- main.c.lis ----
+1     0000              \tMODULE\tmain_c
+2     0000              \tSECTION\tcode_compiler
+3     0000              ._main
+4     0000  3E 05       \tld\ta,5
+5     0002  CD 00 00    \tcall\t_helper
+6     0005  C9          \tret
+7     0006              \tSECTION\trodata_compiler
+8     0000              i_1:
+9     0000  68 69 00    \tdefm\t\"hi\"
+; ---- util.c.lis ----
+1     0000              \tMODULE\tutil_c
+2     0000              \tSECTION\tcode_compiler
+3     0000              _helper:
+4     0000  3C          \tinc\ta
+5     0001  C9          \tret
+";
+
+    const MAP: &str = "\
+__head                          = $0000 ; const, public, , , , 
+_main                           = $0100 ; addr, public, , main_c, code_compiler, main.c:3
+_helper                         = $0106 ; addr, public, , util_c, code_compiler, util.c:1
+i_1                             = $0200 ; addr, local, , main_c, rodata_compiler, main.c:9
+";
+
+    #[test]
+    fn map_symbols() {
+        let symbols = parse_map(MAP);
+        assert_eq!(symbols.get("_main"), Some(&0x0100));
+        assert_eq!(symbols.get("_helper"), Some(&0x0106));
+        assert_eq!(symbols.get("__head"), Some(&0x0000));
+        assert_eq!(symbols.len(), 4);
+    }
+
+    #[test]
+    fn each_module_section_is_placed_at_its_own_base() {
+        let lines = parse_listing(LISTING, MAP);
+        let code = lines
+            .iter()
+            .filter(|line| !line.bytes.is_empty())
+            .map(|line| (line.address, line.bytes.as_str(), line.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            code,
+            vec![
+                (Some(0x0100), "3E 05", "ld\ta,5"),
+                (Some(0x0102), "CD 00 00", "call\t_helper"),
+                (Some(0x0105), "C9", "ret"),
+                (Some(0x0200), "68 69 00", "defm\t\"hi\""),
+                (Some(0x0106), "3C", "inc\ta"),
+                (Some(0x0107), "C9", "ret"),
+            ]
+        );
+        let modules = lines
+            .iter()
+            .filter_map(|line| line.module.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(modules, vec!["main.c.lis", "util.c.lis"]);
+    }
+
+    #[test]
+    fn sections_without_a_mapped_label_have_no_address() {
+        let lines = parse_listing(LISTING, "_main = $0100 ; addr, public");
+        let helper = lines.iter().find(|line| line.text == "inc\ta").unwrap();
+        assert_eq!(helper.address, None);
+    }
+}
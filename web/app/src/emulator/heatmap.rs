@@ -0,0 +1,286 @@
+use super::flow::instruction_length;
+use super::{emu_style, EmulatorCfgContext};
+use emu_lib::memory::errors::{MemoryReadError, MemoryWriteError};
+use emu_lib::memory::{Memory, MemoryDevice};
+use leptos::html::Canvas;
+use leptos::prelude::*;
+use leptos::wasm_bindgen::{Clamped, JsCast};
+use leptos::ev::MouseEvent;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+const ADDRESS_SPACE: usize = 0x10000;
+/// Addresses per heatmap row; the map is a 256 by 256 grid of bytes.
+const MAP_WIDTH: usize = 0x100;
+/// Fraction of every count kept after each run chunk.
+const DECAY: f32 = 0.97;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccessCounts {
+    reads: Vec<f32>,
+    writes: Vec<f32>,
+    executes: Vec<f32>,
+}
+
+impl Default for AccessCounts {
+    fn default() -> Self {
+        AccessCounts {
+            reads: vec![0.0; ADDRESS_SPACE],
+            writes: vec![0.0; ADDRESS_SPACE],
+            executes: vec![0.0; ADDRESS_SPACE],
+        }
+    }
+}
+
+impl AccessCounts {
+    fn decay(&mut self, factor: f32) {
+        for counts in [&mut self.reads, &mut self.writes, &mut self.executes] {
+            counts.iter_mut().for_each(|count| *count *= factor);
+        }
+    }
+
+    fn max(counts: &[f32]) -> f32 {
+        counts.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// RGBA pixels with writes in red, reads in green and executes in blue, each
+    /// on a log scale relative to its busiest address.
+    fn pixels(&self) -> Vec<u8> {
+        let scale = |counts: &[f32]| {
+            let max = Self::max(counts).ln_1p();
+            move |count: f32| {
+                if max > 0.0 {
+                    (count.ln_1p() / max * 255.0) as u8
+                } else {
+                    0
+                }
+            }
+        };
+        let (writes, reads, executes) = (
+            scale(&self.writes),
+            scale(&self.reads),
+            scale(&self.executes),
+        );
+        (0..ADDRESS_SPACE)
+            .flat_map(|address| {
+                [
+                    writes(self.writes[address]),
+                    reads(self.reads[address]),
+                    executes(self.executes[address]),
+                    255,
+                ]
+            })
+            .collect()
+    }
+}
+
+/// Shared access counters. Counting only happens while `recording` is set, so
+/// reads made by the panels themselves are not counted as program accesses.
+/// `recording` is a plain flag rather than a signal as every emulated memory
+/// access checks it.
+#[derive(Clone, Debug)]
+pub struct AccessTracker {
+    pub enabled: RwSignal<bool>,
+    pub counts: RwSignal<AccessCounts>,
+    recording: Arc<AtomicBool>,
+}
+
+impl Default for AccessTracker {
+    fn default() -> Self {
+        AccessTracker {
+            enabled: RwSignal::new(false),
+            counts: RwSignal::new(AccessCounts::default()),
+            recording: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl AccessTracker {
+    fn recording(&self) -> bool {
+        self.recording.load(Ordering::Relaxed)
+    }
+
+    /// Starts counting if the heatmap is enabled; returns whether it did.
+    pub fn start(&self) -> bool {
+        let enabled = self.enabled.get_untracked();
+        self.recording.store(enabled, Ordering::Relaxed);
+        enabled
+    }
+
+    /// Stops counting, fades older accesses and redraws the heatmap once.
+    pub fn finish(&self) {
+        if self.recording.swap(false, Ordering::Relaxed) {
+            self.counts.update(|counts| counts.decay(DECAY));
+        }
+    }
+
+    /// Runs `f` without counting its memory reads, for tools that look at
+    /// memory in the middle of a run.
+    pub fn paused<T>(&self, f: impl FnOnce() -> T) -> T {
+        let recording = self.recording.swap(false, Ordering::Relaxed);
+        let result = f();
+        self.recording.store(recording, Ordering::Relaxed);
+        result
+    }
+
+    /// Counts the instruction just executed at `pc`. Its opcode and operand
+    /// fetches were seen as reads, so they are taken back off the read counts.
+    pub fn record_execute(&self, memory: &Memory, pc: u16) {
+        if !self.recording() {
+            return;
+        }
+        let length = self.paused(|| instruction_length(memory, pc));
+        self.counts.update_untracked(|counts| {
+            counts.executes[pc as usize] += 1.0;
+            for offset in 0..length {
+                let read = &mut counts.reads[pc.wrapping_add(offset) as usize];
+                *read = (*read - 1.0).max(0.0);
+            }
+        });
+    }
+
+    fn record_read(&self, address: u16) {
+        if self.recording() {
+            self.counts
+                .update_untracked(|counts| counts.reads[address as usize] += 1.0);
+        }
+    }
+
+    fn record_write(&self, address: u16) {
+        if self.recording() {
+            self.counts
+                .update_untracked(|counts| counts.writes[address as usize] += 1.0);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.counts.set(AccessCounts::default());
+    }
+}
+
+/// Wraps a device mapped at `base` and reports its accesses to the tracker.
+pub struct TracingDevice {
+    inner: Box<dyn MemoryDevice>,
+    base: u16,
+    tracker: AccessTracker,
+}
+
+impl TracingDevice {
+    pub fn new(inner: Box<dyn MemoryDevice>, base: u16, tracker: AccessTracker) -> Self {
+        TracingDevice {
+            inner,
+            base,
+            tracker,
+        }
+    }
+}
+
+impl MemoryDevice for TracingDevice {
+    fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    fn read_8(&self, addr: u16) -> Result<u8, MemoryReadError> {
+        self.tracker.record_read(self.base.wrapping_add(addr));
+        self.inner.read_8(addr)
+    }
+
+    fn write_8(&mut self, addr: u16, value: u8) -> Result<(), MemoryWriteError> {
+        self.tracker.record_write(self.base.wrapping_add(addr));
+        self.inner.write_8(addr, value)
+    }
+
+    fn write_8_force(&mut self, addr: u16, value: u8) -> Result<(), MemoryWriteError> {
+        self.inner.write_8_force(addr, value)
+    }
+}
+
+#[island]
+pub fn Heatmap() -> impl IntoView {
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let (enabled, counts) =
+        emu_cfg_ctx.with_untracked(|cfg| (cfg.tracker.enabled, cfg.tracker.counts));
+    let canvas_ref: NodeRef<Canvas> = NodeRef::new();
+    let hovered = RwSignal::new(None::<u16>);
+    Effect::new(move |_| {
+        let pixels = counts.with(|counts| counts.pixels());
+        let Some(canvas) = canvas_ref.get() else {
+            return;
+        };
+        let ctx = canvas
+            .get_context("2d")
+            .expect("should not error")
+            .unwrap()
+            .dyn_into::<CanvasRenderingContext2d>()
+            .expect("context should be 2d");
+        let image_data = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&pixels),
+            MAP_WIDTH as u32,
+            (ADDRESS_SPACE / MAP_WIDTH) as u32,
+        )
+        .expect("should create image data");
+        ctx.put_image_data(&image_data, 0.0, 0.0)
+            .expect("put image data");
+    });
+    let address_at = move |ev: &MouseEvent| {
+        let canvas = canvas_ref.get_untracked()?;
+        let rect = canvas.get_bounding_client_rect();
+        let x = (ev.client_x() as f64 - rect.left()) / rect.width() * MAP_WIDTH as f64;
+        let y = (ev.client_y() as f64 - rect.top()) / rect.height()
+            * (ADDRESS_SPACE / MAP_WIDTH) as f64;
+        let (x, y) = (x.floor() as usize, y.floor() as usize);
+        (x < MAP_WIDTH && y < ADDRESS_SPACE / MAP_WIDTH).then(|| (y * MAP_WIDTH + x) as u16)
+    };
+    let jump = move |ev: MouseEvent| {
+        if let Some(address) = address_at(&ev) {
+            emu_cfg_ctx.update(|cfg| {
+                cfg.mem_config.goto(address);
+                cfg.disasm_config.start = Some(address);
+            });
+        }
+    };
+    let hover_text = move || {
+        hovered.get().map(|address| {
+            counts.with(|counts| {
+                let index = address as usize;
+                format!(
+                    "{:04X}: R {:.0} W {:.0} X {:.0}",
+                    address, counts.reads[index], counts.writes[index], counts.executes[index]
+                )
+            })
+        })
+    };
+    view! {
+        <div class=emu_style::heatmap>
+            <div class=emu_style::sectop>
+                <span>Memory heatmap</span>
+            </div>
+            <div class=emu_style::heatmaptools>
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=enabled
+                        on:change=move |ev| enabled.set(event_target_checked(&ev))
+                    />
+                    Track accesses
+                </label>
+                <input type="button" value="Clear" on:click=move |_| counts.set(AccessCounts::default()) />
+                <span class=emu_style::heatlegend>
+                    <span class=emu_style::heatwrite>W</span>
+                    <span class=emu_style::heatread>R</span>
+                    <span class=emu_style::heatexec>X</span>
+                </span>
+            </div>
+            <canvas
+                node_ref=canvas_ref
+                width=MAP_WIDTH
+                height=ADDRESS_SPACE / MAP_WIDTH
+                on:click=jump
+                on:mousemove=move |ev| hovered.set(address_at(&ev))
+                on:mouseleave=move |_| hovered.set(None)
+            ></canvas>
+            <div class=emu_style::heatinfo>{hover_text}</div>
+        </div>
+    }
+}
@@ -0,0 +1,310 @@
+use super::editor::CompileLanguage;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    Type,
+    Preprocessor,
+    Mnemonic,
+    Register,
+    Directive,
+    Label,
+    Number,
+    String,
+    Comment,
+}
+
+pub type Token = (TokenKind, String);
+
+const C_KEYWORDS: [&str; 24] = [
+    "auto", "break", "case", "const", "continue", "default", "do", "else", "enum", "extern",
+    "for", "goto", "if", "inline", "register", "restrict", "return", "sizeof", "static",
+    "struct", "switch", "typedef", "union", "while",
+];
+
+const C_TYPES: [&str; 20] = [
+    "bool", "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void",
+    "volatile", "int8_t", "int16_t", "int32_t", "uint8_t", "uint16_t", "uint32_t", "size_t",
+    "true", "false",
+];
+
+const Z80_MNEMONICS: [&str; 68] = [
+    "adc", "add", "and", "bit", "call", "ccf", "cp", "cpd", "cpdr", "cpi", "cpir", "cpl", "daa",
+    "dec", "di", "djnz", "ei", "ex", "exx", "halt", "im", "in", "inc", "ind", "indr", "ini",
+    "inir", "jp", "jr", "ld", "ldd", "lddr", "ldi", "ldir", "neg", "nop", "or", "otdr", "otir",
+    "out", "outd", "outi", "pop", "push", "res", "ret", "reti", "retn", "rl", "rla", "rlc",
+    "rlca", "rld", "rr", "rra", "rrc", "rrca", "rrd", "rst", "sbc", "scf", "set", "sla", "sll",
+    "sra", "srl", "sub", "xor",
+];
+
+/// Registers and condition codes; `c` is both and reads as a register.
+const Z80_REGISTERS: [&str; 29] = [
+    "a", "b", "c", "d", "e", "h", "l", "i", "r", "f", "af", "bc", "de", "hl", "sp", "ix", "iy",
+    "ixh", "ixl", "iyh", "iyl", "af'", "nz", "z", "nc", "po", "pe", "p", "m",
+];
+
+const ASM_DIRECTIVES: [&str; 2] = ["org", "db"];
+
+/// Splits each line of `source` into highlighted tokens. Concatenating a line's
+/// tokens gives back the line exactly, so the overlay lines up with the text.
+pub fn highlight(lang: CompileLanguage, source: &str) -> Vec<Vec<Token>> {
+    let mut in_block_comment = false;
+    source
+        .split('\n')
+        .map(|line| match lang {
+            CompileLanguage::C => highlight_c_line(line, &mut in_block_comment),
+            CompileLanguage::ASM => highlight_asm_line(line),
+        })
+        .collect()
+}
+
+struct Scanner<'a> {
+    line: &'a str,
+    position: usize,
+    tokens: Vec<Token>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(line: &'a str) -> Self {
+        Scanner {
+            line,
+            position: 0,
+            tokens: vec![],
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.line[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// Emits the next `len` bytes as one token, merging with the previous token
+    /// of the same kind.
+    fn emit(&mut self, kind: TokenKind, len: usize) {
+        let text = &self.line[self.position..self.position + len];
+        self.position += len;
+        match self.tokens.last_mut() {
+            Some((last, previous)) if *last == kind => previous.push_str(text),
+            _ => self.tokens.push((kind, text.to_string())),
+        }
+    }
+
+    fn emit_rest(&mut self, kind: TokenKind) {
+        let len = self.rest().len();
+        if len > 0 {
+            self.emit(kind, len);
+        }
+    }
+
+    fn word_len(&self, extra: impl Fn(char) -> bool) -> usize {
+        self.rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || extra(c)))
+            .unwrap_or(self.rest().len())
+    }
+
+    /// Length of a quoted string starting at the cursor, honouring backslash
+    /// escapes; an unterminated string runs to the end of the line.
+    fn string_len(&self, quote: char) -> usize {
+        let mut escaped = false;
+        for (index, c) in self.rest().char_indices().skip(1) {
+            match c {
+                '\\' if !escaped => escaped = true,
+                c if c == quote && !escaped => return index + c.len_utf8(),
+                _ => escaped = false,
+            }
+        }
+        self.rest().len()
+    }
+}
+
+fn highlight_c_line(line: &str, in_block_comment: &mut bool) -> Vec<Token> {
+    let mut scanner = Scanner::new(line);
+    if line.trim_start().starts_with('#') && !*in_block_comment {
+        scanner.emit_rest(TokenKind::Preprocessor);
+        return scanner.tokens;
+    }
+    while let Some(c) = scanner.peek() {
+        if *in_block_comment {
+            match scanner.rest().find("*/") {
+                Some(end) => {
+                    scanner.emit(TokenKind::Comment, end + 2);
+                    *in_block_comment = false;
+                }
+                None => scanner.emit_rest(TokenKind::Comment),
+            }
+            continue;
+        }
+        let rest = scanner.rest();
+        if rest.starts_with("//") {
+            scanner.emit_rest(TokenKind::Comment);
+        } else if rest.starts_with("/*") {
+            *in_block_comment = true;
+            scanner.emit(TokenKind::Comment, 2);
+        } else if c == '"' || c == '\'' {
+            let len = scanner.string_len(c);
+            scanner.emit(TokenKind::String, len);
+        } else if c.is_ascii_digit() {
+            let len = scanner.word_len(|c| c == '.');
+            scanner.emit(TokenKind::Number, len);
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = scanner.word_len(|_| false);
+            let word = &rest[..len];
+            let kind = if C_KEYWORDS.contains(&word) {
+                TokenKind::Keyword
+            } else if C_TYPES.contains(&word) {
+                TokenKind::Type
+            } else {
+                TokenKind::Plain
+            };
+            scanner.emit(kind, len);
+        } else {
+            scanner.emit(TokenKind::Plain, c.len_utf8());
+        }
+    }
+    scanner.tokens
+}
+
+fn highlight_asm_line(line: &str) -> Vec<Token> {
+    let mut scanner = Scanner::new(line);
+    let mut first_word = true;
+    while let Some(c) = scanner.peek() {
+        let rest = scanner.rest();
+        if rest.starts_with("//") || c == ';' {
+            scanner.emit_rest(TokenKind::Comment);
+        } else if c == '"' || c == '\'' {
+            let len = scanner.string_len(c);
+            scanner.emit(TokenKind::String, len);
+        } else if c.is_ascii_digit() || (c == '$' && rest.len() > 1) {
+            let len = 1 + rest[1..]
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len() - 1);
+            scanner.emit(TokenKind::Number, len);
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = scanner.word_len(|c| c == '\'');
+            let word = rest[..len].to_ascii_lowercase();
+            let kind = if rest[len..].starts_with(':') {
+                TokenKind::Label
+            } else if first_word && Z80_MNEMONICS.contains(&word.as_str()) {
+                TokenKind::Mnemonic
+            } else if first_word && ASM_DIRECTIVES.contains(&word.as_str()) {
+                TokenKind::Directive
+            } else if Z80_REGISTERS.contains(&word.as_str()) {
+                TokenKind::Register
+            } else {
+                TokenKind::Plain
+            };
+            first_word = kind == TokenKind::Label;
+            scanner.emit(kind, len);
+        } else {
+            scanner.emit(TokenKind::Plain, c.len_utf8());
+        }
+    }
+    scanner.tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TokenKind::*;
+
+    fn tokens(kinds: &[(TokenKind, &str)]) -> Vec<Token> {
+        kinds
+            .iter()
+            .map(|(kind, text)| (*kind, text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_concatenate_back_to_each_line() {
+        let c = "#include \"emu.h\"\nint main() { /* a\n  b */ char *s = \"a\\\"b\"; // x\n\treturn '\\''; }\n";
+        let asm = "start: ld a, $FF ; load\n  ex af, af'\n  db \"hi;there\", 0x10 // data\n\tjr nz, start";
+        for (lang, source) in [(CompileLanguage::C, c), (CompileLanguage::ASM, asm)] {
+            let lines = highlight(lang, source);
+            assert_eq!(lines.len(), source.split('\n').count());
+            for (line, tokens) in source.split('\n').zip(lines) {
+                let joined = tokens.into_iter().map(|(_, text)| text).collect::<Vec<_>>();
+                assert_eq!(joined.concat(), line);
+            }
+        }
+    }
+
+    #[test]
+    fn c_block_comments_carry_across_lines() {
+        let lines = highlight(CompileLanguage::C, "int a; /* one\ntwo */ return 0;");
+        assert_eq!(
+            lines[0],
+            tokens(&[(Type, "int"), (Plain, " a; "), (Comment, "/* one")])
+        );
+        assert_eq!(
+            lines[1],
+            tokens(&[
+                (Comment, "two */"),
+                (Plain, " "),
+                (Keyword, "return"),
+                (Plain, " "),
+                (Number, "0"),
+                (Plain, ";"),
+            ])
+        );
+    }
+
+    #[test]
+    fn c_strings_keep_escaped_quotes() {
+        let lines = highlight(CompileLanguage::C, r#"s = "a\"b"; // done"#);
+        assert_eq!(
+            lines[0],
+            tokens(&[
+                (Plain, "s = "),
+                (String, r#""a\"b""#),
+                (Plain, "; "),
+                (Comment, "// done"),
+            ])
+        );
+    }
+
+    #[test]
+    fn asm_shadow_registers_hex_numbers_and_strings() {
+        let lines = highlight(
+            CompileLanguage::ASM,
+            "loop: ld a, $FF ; load\nex af, af'\ndb \"a;b\", 0",
+        );
+        assert_eq!(
+            lines[0],
+            tokens(&[
+                (Label, "loop"),
+                (Plain, ": "),
+                (Mnemonic, "ld"),
+                (Plain, " "),
+                (Register, "a"),
+                (Plain, ", "),
+                (Number, "$FF"),
+                (Plain, " "),
+                (Comment, "; load"),
+            ])
+        );
+        assert_eq!(
+            lines[1],
+            tokens(&[
+                (Mnemonic, "ex"),
+                (Plain, " "),
+                (Register, "af"),
+                (Plain, ", "),
+                (Register, "af'"),
+            ])
+        );
+        assert_eq!(
+            lines[2],
+            tokens(&[
+                (Directive, "db"),
+                (Plain, " "),
+                (String, "\"a;b\""),
+                (Plain, ", "),
+                (Number, "0"),
+            ])
+        );
+    }
+}
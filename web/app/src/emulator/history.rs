@@ -0,0 +1,129 @@
+use emu_lib::memory::{Memory, MemoryDevice};
+
+const MAX_HISTORY: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryChange {
+    pub address: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub description: String,
+    pub changes: Vec<MemoryChange>,
+}
+
+#[derive(Default)]
+pub struct ChangeHistory {
+    entries: Vec<HistoryEntry>,
+}
+
+impl ChangeHistory {
+    pub fn record(&mut self, entry: HistoryEntry) {
+        if entry.changes.is_empty() {
+            return;
+        }
+        if self.entries.len() >= MAX_HISTORY {
+            self.entries.remove(0);
+        }
+        self.entries.push(entry);
+    }
+
+    pub fn last(&self) -> Option<&HistoryEntry> {
+        self.entries.last()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Reverts the most recent entry, restoring bytes in reverse write order.
+    /// The entry is only dropped once every byte is restored, so a failed undo
+    /// can be retried.
+    pub fn undo(&mut self, memory: &mut Memory) -> Option<Result<HistoryEntry, String>> {
+        let entry = self.entries.last()?;
+        for change in entry.changes.iter().rev() {
+            if let Err(err) = memory.write_8(change.address, change.old) {
+                return Some(Err(format!(
+                    "Undo of \"{}\" failed at {:#06X}: {}",
+                    entry.description, change.address, err
+                )));
+            }
+            memory.clear_change(change.address);
+        }
+        self.entries.pop().map(Ok)
+    }
+}
+
+/// Writes `writes` into memory and returns the entry needed to undo them.
+/// Stops at the first failing write; the bytes written so far are still returned
+/// so that they can be recorded and undone.
+pub fn write_bytes(
+    memory: &mut Memory,
+    description: String,
+    writes: impl IntoIterator<Item = (u16, u8)>,
+) -> (HistoryEntry, Result<(), String>) {
+    let mut entry = HistoryEntry {
+        description,
+        changes: vec![],
+    };
+    for (address, new) in writes {
+        let old = match memory.read_8(address) {
+            Ok(old) => old,
+            Err(err) => return (entry, Err(format!("Read error at {:#06X}: {}", address, err))),
+        };
+        if let Err(err) = memory.write_8(address, new) {
+            return (entry, Err(format!("Write error at {:#06X}: {}", address, err)));
+        }
+        memory.clear_change(address);
+        entry.changes.push(MemoryChange { address, old, new });
+    }
+    (entry, Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emu_lib::memory::memdevices::RAM;
+
+    fn entry(changes: &[(u16, u8, u8)]) -> HistoryEntry {
+        HistoryEntry {
+            description: "Patch".to_string(),
+            changes: changes
+                .iter()
+                .map(|&(address, old, new)| MemoryChange { address, old, new })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn undo_restores_the_old_bytes() {
+        let mut memory = Memory::new();
+        memory.add_device(Box::new(RAM::new(0x100)));
+        let (recorded, result) = write_bytes(&mut memory, "Patch".to_string(), [(0x10, 0xAA)]);
+        assert!(result.is_ok());
+        let mut history = ChangeHistory::default();
+        history.record(recorded);
+
+        assert!(history.undo(&mut memory).unwrap().is_ok());
+        assert_eq!(memory.read_8(0x10), Ok(0));
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn a_failed_undo_keeps_the_entry() {
+        let mut memory = Memory::new();
+        memory.add_device(Box::new(RAM::new(0x100)));
+        let mut history = ChangeHistory::default();
+        // 0x0200 is past the end of the only device, so its write fails.
+        history.record(entry(&[(0x0200, 0x00, 0x11), (0x0010, 0x00, 0x22)]));
+
+        assert!(history.undo(&mut memory).unwrap().is_err());
+        assert_eq!(
+            history.last(),
+            Some(&entry(&[(0x0200, 0x00, 0x11), (0x0010, 0x00, 0x22)]))
+        );
+    }
+}
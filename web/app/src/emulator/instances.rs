@@ -0,0 +1,555 @@
+use super::display::DisplayMemoryDevice;
+use super::editor::EditorContext;
+use super::history::ChangeHistory;
+use super::registers::RegisterSnapshot;
+use super::symbols::SymbolTable;
+use super::{emu_style, EmulatorCfgContext, EmulatorContext, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use emu_lib::cpu::z80::Z80;
+use emu_lib::cpu::Cpu;
+use emu_lib::emulator::Emulator;
+use emu_lib::memory::MemoryDevice;
+use leptos::prelude::*;
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+/// Upper bound on lockstep instructions per comparison.
+const MAX_LOCKSTEP_STEPS: usize = 1_000_000;
+/// Lockstep instructions run before yielding to the browser.
+const LOCKSTEP_CHUNK: usize = 10_000;
+
+/// Everything that belongs to one machine while another one is on screen.
+pub struct ParkedInstance {
+    emu: EmulatorContext,
+    display: DisplayMemoryDevice,
+    editor: EditorContext,
+    symbols: SymbolTable,
+    history: ChangeHistory,
+}
+
+/// Named emulator instances. Only the active one lives in the page's
+/// `EmulatorContext`; the others are parked here and swapped in on selection.
+pub struct InstanceStore {
+    active: usize,
+    names: Vec<String>,
+    parked: Vec<Option<ParkedInstance>>,
+}
+
+impl Default for InstanceStore {
+    fn default() -> Self {
+        InstanceStore {
+            active: 0,
+            names: vec!["Main".to_string()],
+            parked: vec![None],
+        }
+    }
+}
+
+impl InstanceStore {
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    fn parked_mut(&mut self, index: usize) -> Option<&mut ParkedInstance> {
+        self.parked.get_mut(index).and_then(Option::as_mut)
+    }
+}
+
+/// The first instance's editor, whether it is active or parked. It holds the
+/// buffers the workspace keeps across reloads.
+pub fn main_editor(cfg: &EmulatorCfgContext) -> &EditorContext {
+    match cfg.instances.parked.first().and_then(Option::as_ref) {
+        Some(parked) => &parked.editor,
+        None => &cfg.editor,
+    }
+}
+
+pub fn main_editor_mut(cfg: &mut EmulatorCfgContext) -> &mut EditorContext {
+    match cfg.instances.parked_mut(0) {
+        Some(parked) => &mut parked.editor,
+        None => &mut cfg.editor,
+    }
+}
+
+/// Creates a blank machine with its own display and makes it the active one.
+pub(super) fn add_instance(emu: &mut EmulatorContext, cfg: &mut EmulatorCfgContext, name: String) {
+    let display = DisplayMemoryDevice::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    cfg.instances.names.push(name);
+    cfg.instances.parked.push(Some(ParkedInstance {
+        emu: EmulatorContext::new(display, cfg.tracker.clone()),
+        display,
+        editor: EditorContext::default(),
+        symbols: SymbolTable::default(),
+        history: ChangeHistory::default(),
+    }));
+    switch_instance(emu, cfg, cfg.instances.names.len() - 1);
+}
+
+pub(super) fn switch_instance(
+    emu: &mut EmulatorContext,
+    cfg: &mut EmulatorCfgContext,
+    index: usize,
+) {
+    let previous = cfg.instances.active;
+    let Some(mut parked) = cfg.instances.parked.get_mut(index).and_then(Option::take) else {
+        return;
+    };
+    std::mem::swap(emu, &mut parked.emu);
+    std::mem::swap(&mut cfg.display, &mut parked.display);
+    std::mem::swap(&mut cfg.editor, &mut parked.editor);
+    std::mem::swap(&mut cfg.symbols, &mut parked.symbols);
+    std::mem::swap(&mut cfg.history, &mut parked.history);
+    cfg.instances.parked[previous] = Some(parked);
+    cfg.instances.active = index;
+    cfg.tracker.clear();
+}
+
+fn remove_instance(cfg: &mut EmulatorCfgContext, index: usize) -> Option<String> {
+    let instances = &mut cfg.instances;
+    if index == instances.active || index >= instances.names.len() {
+        return None;
+    }
+    instances.parked.remove(index);
+    if instances.active > index {
+        instances.active -= 1;
+    }
+    Some(instances.names.remove(index))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Divergence {
+    step: usize,
+    pcs: (u16, u16),
+    registers: Vec<(&'static str, u16, u16)>,
+    memory: Option<(u16, u8, u8)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum LockstepOutcome {
+    Diverged(Divergence),
+    Stopped { steps: usize, reason: String },
+    Matched { steps: usize },
+}
+
+fn written_addresses(emu: &Emulator<Z80>, into: &mut BTreeSet<u16>) {
+    if let Some(changes) = emu.memory.get_changes() {
+        into.extend(changes.iter().copied());
+    }
+}
+
+/// Steps both machines one instruction at a time and stops at the first step
+/// after which their registers, or any byte either of them wrote, differ.
+/// Returns `None` when every step in `steps` matched.
+fn lockstep(
+    ours: &mut Emulator<Z80>,
+    theirs: &mut Emulator<Z80>,
+    steps: RangeInclusive<usize>,
+    ignore_pc: bool,
+) -> Option<LockstepOutcome> {
+    for step in steps {
+        let pcs = (ours.cpu.registers.pc, theirs.cpu.registers.pc);
+        for (name, emu) in [("this", &mut *ours), ("the other", &mut *theirs)] {
+            // Only this step's writes are compared; earlier ones already matched.
+            emu.memory.clear_changes();
+            if emu.cpu.halted() {
+                return Some(LockstepOutcome::Stopped {
+                    steps: step - 1,
+                    reason: format!("{} instance halted", name),
+                });
+            }
+            if let Err(err) = emu.step() {
+                return Some(LockstepOutcome::Stopped {
+                    steps: step - 1,
+                    reason: format!(
+                        "{} instance failed at {:#06X}: {}",
+                        name, emu.cpu.registers.pc, err
+                    ),
+                });
+            }
+        }
+        let registers = RegisterSnapshot::capture(ours)
+            .differences(&RegisterSnapshot::capture(theirs), ignore_pc);
+        let mut written = BTreeSet::new();
+        written_addresses(ours, &mut written);
+        written_addresses(theirs, &mut written);
+        let memory = written.into_iter().find_map(|address| {
+            let values = (ours.memory.read_8(address), theirs.memory.read_8(address));
+            match values {
+                (Ok(a), Ok(b)) if a != b => Some((address, a, b)),
+                _ => None,
+            }
+        });
+        if !registers.is_empty() || memory.is_some() {
+            return Some(LockstepOutcome::Diverged(Divergence {
+                step,
+                pcs,
+                registers,
+                memory,
+            }));
+        }
+    }
+    None
+}
+
+/// A lockstep comparison in progress, run a chunk at a time.
+#[derive(Clone, Copy)]
+struct LockstepRun {
+    emu_ctx: RwSignal<EmulatorContext>,
+    emu_cfg_ctx: RwSignal<EmulatorCfgContext>,
+    /// Instance active when the comparison started.
+    active: usize,
+    other: usize,
+    steps: usize,
+    ignore_pc: bool,
+    busy: RwSignal<bool>,
+    outcome: RwSignal<Option<LockstepOutcome>>,
+}
+
+/// Runs the next chunk of `run` from step `first`, then schedules the rest.
+fn lockstep_chunk(run: LockstepRun, first: usize) {
+    if !run.busy.get_untracked() {
+        return;
+    }
+    let last = (first + LOCKSTEP_CHUNK - 1).min(run.steps);
+    let mut result = None;
+    let mut interrupted = false;
+    run.emu_ctx.update(|emu| {
+        run.emu_cfg_ctx.update_untracked(|cfg| {
+            let active = cfg.instances.active();
+            let running = cfg.control.running.get_untracked();
+            match cfg.instances.parked_mut(run.other) {
+                Some(parked) if active == run.active && !running => {
+                    result = lockstep(
+                        &mut emu.emu,
+                        &mut parked.emu.emu,
+                        first..=last,
+                        run.ignore_pc,
+                    );
+                }
+                _ => interrupted = true,
+            }
+        })
+    });
+    if interrupted {
+        run.busy.set(false);
+        return;
+    }
+    let result = match result {
+        Some(result) => result,
+        None if last < run.steps => {
+            set_timeout(move || lockstep_chunk(run, last + 1), Duration::ZERO);
+            return;
+        }
+        None => LockstepOutcome::Matched { steps: run.steps },
+    };
+    run.emu_cfg_ctx.update(|cfg| match &result {
+        LockstepOutcome::Diverged(divergence) => {
+            cfg.logstore.log_warning(
+                "Lockstep diverged",
+                format!(
+                    "Instances diverged after {} steps at {:#06X} / {:#06X}",
+                    divergence.step, divergence.pcs.0, divergence.pcs.1
+                ),
+            );
+            if let Some((address, _, _)) = divergence.memory {
+                cfg.mem_config.goto(address);
+            }
+            cfg.disasm_config.start = Some(divergence.pcs.0);
+        }
+        LockstepOutcome::Stopped { steps, reason } => cfg.logstore.log_info(
+            "Lockstep stopped",
+            format!(
+                "Lockstep stopped after {} matching steps: {}",
+                steps, reason
+            ),
+        ),
+        LockstepOutcome::Matched { steps } => cfg.logstore.log_info(
+            "Lockstep matched",
+            format!("Instances matched for {} steps", steps),
+        ),
+    });
+    run.outcome.set(Some(result));
+    run.busy.set(false);
+}
+
+#[island]
+pub fn InstanceTabs() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let new_name = RwSignal::new(String::new());
+    let tabs = Memo::new(move |_| {
+        emu_cfg_ctx.with(|cfg| (cfg.instances.names().to_vec(), cfg.instances.active()))
+    });
+    let stop_running = move || {
+        emu_cfg_ctx.with_untracked(|cfg| cfg.control.running.set(false));
+    };
+    let select = move |index: usize| {
+        stop_running();
+        emu_ctx.update(|emu| {
+            emu_cfg_ctx.update(|cfg| {
+                switch_instance(emu, cfg, index);
+                let name = cfg.instances.names()[cfg.instances.active()].clone();
+                cfg.logstore.log_info(
+                    "Instance selected",
+                    format!("Switched to instance {}", name),
+                );
+            })
+        });
+    };
+    let add = move || {
+        let name = new_name.get_untracked().trim().to_string();
+        stop_running();
+        emu_ctx.update(|emu| {
+            emu_cfg_ctx.update(|cfg| {
+                let name = if name.is_empty() {
+                    format!("Instance {}", cfg.instances.names().len() + 1)
+                } else {
+                    name
+                };
+                cfg.logstore
+                    .log_info("Instance created", format!("Created instance {}", name));
+                add_instance(emu, cfg, name);
+            })
+        });
+        new_name.set(String::new());
+    };
+    let remove = move |index: usize| {
+        emu_cfg_ctx.update(|cfg| {
+            if let Some(name) = remove_instance(cfg, index) {
+                cfg.logstore
+                    .log_info("Instance removed", format!("Removed instance {}", name));
+            }
+        });
+    };
+    let tab_views = move || {
+        let (names, active) = tabs.get();
+        names
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let is_active = index == active;
+                view! {
+                    <div
+                        class=if is_active { emu_style::activetab } else { "" }
+                        on:click=move |_| {
+                            if !is_active {
+                                select(index)
+                            }
+                        }
+                    >
+                        <span>{name}</span>
+                        <Show when=move || !is_active>
+                            <span
+                                class=emu_style::tabclose
+                                on:click=move |ev| {
+                                    ev.stop_propagation();
+                                    remove(index);
+                                }
+                            >
+                                "×"
+                            </span>
+                        </Show>
+                    </div>
+                }
+            })
+            .collect_view()
+    };
+    view! {
+        <div class=emu_style::instancetabs>
+            {tab_views}
+            <div class=emu_style::newtab>
+                <input
+                    placeholder="New instance"
+                    prop:value=new_name
+                    on:input=move |ev| new_name.set(event_target_value(&ev))
+                    on:keydown=move |ev| {
+                        if ev.key() == "Enter" {
+                            add();
+                        }
+                    }
+                />
+                <input type="button" value="+" on:click=move |_| add() />
+            </div>
+        </div>
+    }
+}
+
+#[island]
+pub fn Lockstep() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let other = RwSignal::new(None::<usize>);
+    let steps = RwSignal::new("10000".to_string());
+    let ignore_pc = RwSignal::new(true);
+    let outcome = RwSignal::new(None::<LockstepOutcome>);
+    let busy = RwSignal::new(false);
+    let candidates = Memo::new(move |_| {
+        emu_cfg_ctx.with(|cfg| {
+            let active = cfg.instances.active();
+            cfg.instances
+                .names()
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != active)
+                .map(|(index, name)| (index, name.clone()))
+                .collect::<Vec<_>>()
+        })
+    });
+    // Indices shift when instances are added or removed, which also ends a
+    // comparison in progress.
+    Effect::new(move |_| {
+        candidates.track();
+        other.set(None);
+        busy.set(false);
+    });
+    let run = move |_| {
+        if busy.get_untracked() {
+            busy.set(false);
+            return;
+        }
+        let Some(index) = other.get_untracked() else {
+            emu_cfg_ctx.update(|cfg| {
+                cfg.logstore.log_error(
+                    "Lockstep error",
+                    "Lockstep error: choose an instance to compare with".to_string(),
+                )
+            });
+            return;
+        };
+        let steps = match steps.get_untracked().trim().parse::<usize>() {
+            Ok(steps) if steps > 0 => steps.min(MAX_LOCKSTEP_STEPS),
+            _ => {
+                emu_cfg_ctx.update(|cfg| {
+                    cfg.logstore.log_error(
+                        "Lockstep error",
+                        "Lockstep error: invalid step count".to_string(),
+                    )
+                });
+                return;
+            }
+        };
+        emu_cfg_ctx.with_untracked(|cfg| cfg.control.running.set(false));
+        let active = emu_cfg_ctx.with_untracked(|cfg| cfg.instances.active());
+        emu_ctx.update(|emu| emu.registers_before = RegisterSnapshot::capture(&emu.emu));
+        outcome.set(None);
+        busy.set(true);
+        lockstep_chunk(
+            LockstepRun {
+                emu_ctx,
+                emu_cfg_ctx,
+                active,
+                other: index,
+                steps,
+                ignore_pc: ignore_pc.get_untracked(),
+                busy,
+                outcome,
+            },
+            1,
+        );
+    };
+    let options = move || {
+        candidates
+            .get()
+            .into_iter()
+            .map(|(index, name)| view! { <option value=index.to_string()>{name}</option> })
+            .collect_view()
+    };
+    let result_view = move || {
+        if busy.get() {
+            return Some(view! { <div>"Comparing…"</div> }.into_any());
+        }
+        outcome.get().map(|outcome| match outcome {
+            LockstepOutcome::Diverged(divergence) => {
+                let registers = divergence
+                    .registers
+                    .into_iter()
+                    .map(|(name, ours, theirs)| {
+                        view! {
+                            <tr>
+                                <th>{name}</th>
+                                <td>{format!("{:04X}", ours)}</td>
+                                <td>{format!("{:04X}", theirs)}</td>
+                            </tr>
+                        }
+                    })
+                    .collect_view();
+                let memory = divergence.memory.map(|(address, ours, theirs)| {
+                    view! {
+                        <tr>
+                            <th>{format!("[{:04X}]", address)}</th>
+                            <td>{format!("{:02X}", ours)}</td>
+                            <td>{format!("{:02X}", theirs)}</td>
+                        </tr>
+                    }
+                });
+                view! {
+                    <div class=emu_style::divergence>
+                        {format!(
+                            "First divergence after step {} (PC {:04X} / {:04X})",
+                            divergence.step,
+                            divergence.pcs.0,
+                            divergence.pcs.1,
+                        )}
+                    </div>
+                    <table>
+                        <thead>
+                            <tr>
+                                <th></th>
+                                <th>This</th>
+                                <th>Other</th>
+                            </tr>
+                        </thead>
+                        <tbody>{registers}{memory}</tbody>
+                    </table>
+                }
+                .into_any()
+            }
+            LockstepOutcome::Stopped { steps, reason } => {
+                view! { <div>{format!("Stopped after {} matching steps: {}", steps, reason)}</div> }
+                    .into_any()
+            }
+            LockstepOutcome::Matched { steps } => {
+                view! { <div>{format!("No divergence in {} steps", steps)}</div> }.into_any()
+            }
+        })
+    };
+    view! {
+        <div class=emu_style::lockstep>
+            <div class=emu_style::sectop>
+                <span>Lockstep diff</span>
+            </div>
+            <div class=emu_style::locksteptools>
+                <select
+                    prop:value=move || other.get().map(|index| index.to_string()).unwrap_or_default()
+                    on:change=move |ev| other.set(event_target_value(&ev).parse().ok())
+                >
+                    <option value="">"Compare with…"</option>
+                    {options}
+                </select>
+                <input
+                    placeholder="Steps"
+                    prop:value=steps
+                    on:input=move |ev| steps.set(event_target_value(&ev))
+                />
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=ignore_pc
+                        on:change=move |ev| ignore_pc.set(event_target_checked(&ev))
+                    />
+                    Ignore PC
+                </label>
+                <input
+                    type="button"
+                    value=move || if busy.get() { "Stop" } else { "Run" }
+                    on:click=run
+                />
+            </div>
+            {result_view}
+        </div>
+    }
+}
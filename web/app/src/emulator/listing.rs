@@ -0,0 +1,228 @@
+use super::disassembler::{region_at, DataRegion};
+use super::flow::{decode, Decoded, Flow};
+use super::symbols::SymbolTable;
+use emu_lib::cpu::instruction::InstructionParser;
+use emu_lib::cpu::z80::parser::Z80_PARSER;
+use emu_lib::memory::{Memory, MemoryDevice};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Bytes per `DB` line.
+const DB_ROW_BYTES: u32 = 8;
+const TEXT_WIDTH: usize = 24;
+
+/// Decodes an instruction only if the assembler turns its text back into the same
+/// bytes and it fits in the range, so the listing always reassembles identically.
+fn listable(memory: &Memory, address: u16, end: u32) -> Option<Decoded> {
+    let decoded = decode(memory, address)?;
+    if address as u32 + decoded.bytes.len() as u32 > end + 1 || decoded.bytes.is_empty() {
+        return None;
+    }
+    let reassembled = Z80_PARSER.ins_from_asm_string(&decoded.text).ok()?;
+    (reassembled.to_bytes() == decoded.bytes).then_some(decoded)
+}
+
+fn branch_target(flow: Flow) -> Option<u16> {
+    match flow {
+        Flow::Jump { target, .. } | Flow::Call { target, .. } => Some(target),
+        _ => None,
+    }
+}
+
+/// Names every symbol and branch target inside the range; targets without a
+/// symbol get an `L_XXXX` label.
+fn collect_labels(
+    memory: &Memory,
+    start: u16,
+    end: u16,
+    regions: &[DataRegion],
+    symbols: &SymbolTable,
+) -> BTreeMap<u16, String> {
+    let in_range = |address: u16| (start..=end).contains(&address);
+    let mut labels: BTreeMap<u16, String> = symbols
+        .iter()
+        .filter(|(_, address)| in_range(**address))
+        .map(|(name, address)| (*address, name.clone()))
+        .collect();
+    let mut address = start as u32;
+    while address <= end as u32 {
+        let current = address as u16;
+        let decoded = match region_at(regions, current) {
+            Some(_) => None,
+            None => listable(memory, current, end as u32),
+        };
+        let Some(decoded) = decoded else {
+            address += 1;
+            continue;
+        };
+        if let Some(target) = branch_target(decoded.flow).filter(|target| in_range(*target)) {
+            labels
+                .entry(target)
+                .or_insert_with(|| format!("L_{:04X}", target));
+        }
+        address += decoded.bytes.len() as u32;
+    }
+    labels
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Writes `start..=end` as assembler source: one instruction per line with its
+/// address and bytes in a comment, `DB` for data regions and anything that does
+/// not decode, and a label on every branch target. Assembling the result with
+/// [`super::assembler::assemble`] reproduces the same bytes at the same addresses.
+pub fn export_listing(
+    memory: &Memory,
+    start: u16,
+    end: u16,
+    regions: &[DataRegion],
+    symbols: &SymbolTable,
+) -> String {
+    let labels = collect_labels(memory, start, end, regions, symbols);
+    let mut listing = String::new();
+    let _ = writeln!(listing, "// Disassembly of {:#06X}-{:#06X}", start, end);
+    if start != 0 {
+        let _ = writeln!(listing, "ORG {:#06X}", start);
+    }
+    let mut address = start as u32;
+    let mut data: Vec<u8> = vec![];
+    let mut data_start = address;
+    let flush = |listing: &mut String, data: &mut Vec<u8>, data_start: u32| {
+        if data.is_empty() {
+            return;
+        }
+        let values = data
+            .iter()
+            .map(|byte| format!("{:#04X}", byte))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let line = format!("DB {}", values);
+        let _ = writeln!(listing, "    {:<w$} // {:04X}", line, data_start, w = TEXT_WIDTH);
+        data.clear();
+    };
+    while address <= end as u32 {
+        let current = address as u16;
+        if let Some(label) = labels.get(&current) {
+            flush(&mut listing, &mut data, data_start);
+            let _ = writeln!(listing, "{}:", label);
+        }
+        // A label inside an instruction means overlapping code; fall back to bytes.
+        let decoded = match region_at(regions, current) {
+            Some(_) => None,
+            None => listable(memory, current, end as u32).filter(|decoded| {
+                (1..decoded.bytes.len() as u16)
+                    .all(|offset| !labels.contains_key(&current.wrapping_add(offset)))
+            }),
+        };
+        match decoded {
+            Some(decoded) => {
+                flush(&mut listing, &mut data, data_start);
+                let mut comment = format!("{:04X}: {}", current, hex_bytes(&decoded.bytes));
+                let target = branch_target(decoded.flow);
+                if let Some(label) = target.and_then(|target| labels.get(&target)) {
+                    let _ = write!(comment, " -> {}", label);
+                }
+                let text = &decoded.text;
+                let _ = writeln!(listing, "    {:<w$} // {}", text, comment, w = TEXT_WIDTH);
+                address += decoded.bytes.len() as u32;
+            }
+            None => {
+                if data.is_empty() {
+                    data_start = address;
+                }
+                data.push(memory.read_8(current).unwrap_or(0));
+                address += 1;
+                if data.len() as u32 >= DB_ROW_BYTES {
+                    flush(&mut listing, &mut data, data_start);
+                }
+            }
+        }
+    }
+    flush(&mut listing, &mut data, data_start);
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::assembler::assemble;
+    use emu_lib::memory::memdevices::RAM;
+
+    fn memory_with(start: u16, bytes: &[u8]) -> Memory {
+        let mut memory = Memory::new();
+        memory.add_device(Box::new(RAM::new(0x10000)));
+        for (offset, byte) in bytes.iter().enumerate() {
+            memory.write_8_force(start + offset as u16, *byte).unwrap();
+        }
+        memory
+    }
+
+    /// Exports `start..=end`, assembles the listing and checks it reproduces
+    /// the bytes in memory; returns the listing.
+    fn round_trip(memory: &Memory, start: u16, end: u16, regions: &[DataRegion]) -> String {
+        let listing = export_listing(memory, start, end, regions, &SymbolTable::default());
+        let output = assemble(&listing).unwrap_or_else(|err| panic!("{:?}\n{}", err, listing));
+        let expected = (start..=end)
+            .map(|address| memory.read_8(address).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            output.bytes.get(start as usize..=end as usize),
+            Some(&expected[..]),
+            "{}",
+            listing
+        );
+        listing
+    }
+
+    #[test]
+    fn code_data_and_undecodable_bytes_reassemble_identically() {
+        let program = [
+            0x3E, 0x05, // LD A, 5
+            0x3D, // loop: DEC A
+            0x20, 0xFD, // JR NZ, loop
+            0xCD, 0x10, 0x00, // CALL sub
+            0x76, // HALT
+            0x48, 0x69, 0x00, 0xFF, // marked data
+            0xED, 0x00, // no such instruction
+            0x00, // NOP
+            0x47, // sub: LD B, A
+            0xC9, // RET
+        ];
+        let memory = memory_with(0, &program);
+        let regions = [DataRegion {
+            start: 0x09,
+            end: 0x0C,
+        }];
+        let listing = round_trip(&memory, 0, program.len() as u16 - 1, &regions);
+        assert!(listing.contains("L_0002:"), "{}", listing);
+        assert!(listing.contains("L_0010:"), "{}", listing);
+        assert!(listing.contains("DB 0x48, 0x69, 0x00, 0xFF"), "{}", listing);
+    }
+
+    #[test]
+    fn a_range_away_from_zero_reassembles_at_its_origin() {
+        let program = [
+            0x21, 0x0A, 0x80, // LD HL, data
+            0xCD, 0x09, 0x80, // CALL sub
+            0xC3, 0x00, 0x80, // JP start
+            0xC9, // sub: RET
+            0x01, 0x02, // marked data
+            0xC3, // JP cut off by the end of the range
+        ];
+        let memory = memory_with(0x8000, &program);
+        let regions = [DataRegion {
+            start: 0x800A,
+            end: 0x800B,
+        }];
+        let listing = round_trip(&memory, 0x8000, 0x8000 + program.len() as u16 - 1, &regions);
+        assert!(listing.contains("ORG 0x8000"), "{}", listing);
+        assert!(listing.contains("L_8000:"), "{}", listing);
+        assert!(listing.contains("L_8009:"), "{}", listing);
+    }
+}
@@ -0,0 +1,150 @@
+use super::control::{download_file, fmt_timestamp};
+use super::{emu_style, EmulatorCfgContext, EmulatorContext};
+use crate::utils::logger::{Log, LogFilter, LogLevel};
+use leptos::ev::Event;
+use leptos::prelude::*;
+
+fn level_class(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Info => emu_style::info,
+        LogLevel::Warning => emu_style::warning,
+        LogLevel::Error => emu_style::error,
+    }
+}
+
+#[island]
+pub fn LogPanel() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let filter = RwSignal::new(LogFilter::default());
+    // Entries logged outside a step or run chunk get the latest cycle count.
+    Effect::new(move |_| {
+        let cycles = emu_ctx.with(|emu| emu.emu.cycles as u64);
+        emu_cfg_ctx.update_untracked(|cfg| cfg.logstore.set_clock(cycles));
+    });
+    let revision = emu_cfg_ctx.with_untracked(|cfg| cfg.logstore.revision());
+    // Rows are keyed by id and repeat count, as a merged repeat changes its
+    // entry in place.
+    let logs = Memo::new(move |_| {
+        revision.track();
+        filter.with(|filter| {
+            emu_cfg_ctx.with_untracked(|cfg| {
+                cfg.logstore.filtered(filter).rev().cloned().collect::<Vec<Log>>()
+            })
+        })
+    });
+    let total = move || {
+        revision.track();
+        emu_cfg_ctx.with_untracked(|cfg| cfg.logstore.get_logs().len())
+    };
+    let limit = move || {
+        revision.track();
+        emu_cfg_ctx.with_untracked(|cfg| cfg.logstore.limit().to_string())
+    };
+    let set_limit = move |ev: Event| {
+        if let Ok(limit) = event_target_value(&ev).trim().parse::<usize>() {
+            emu_cfg_ctx.update(|cfg| cfg.logstore.set_limit(limit));
+        }
+    };
+    let toggle_level = move |level: LogLevel, shown: bool| {
+        filter.update(|filter| {
+            filter.levels.retain(|other| *other != level);
+            if shown {
+                filter.levels.push(level);
+            }
+        });
+    };
+    let export = move |json: bool| {
+        let data = filter.with_untracked(|filter| {
+            emu_cfg_ctx.with_untracked(|cfg| {
+                if json {
+                    cfg.logstore.export_json(filter)
+                } else {
+                    cfg.logstore.export_text(filter)
+                }
+            })
+        });
+        if json {
+            download_file("emu_log.json", "application/json", data.as_bytes());
+        } else {
+            download_file("emu_log.txt", "text/plain", data.as_bytes());
+        }
+    };
+    let level_toggles = LogLevel::ALL
+        .into_iter()
+        .map(|level| {
+            view! {
+                <label class=level_class(level)>
+                    <input
+                        type="checkbox"
+                        prop:checked=move || filter.with(|filter| filter.levels.contains(&level))
+                        on:change=move |ev| toggle_level(level, event_target_checked(&ev))
+                    />
+                    {level.name()}
+                </label>
+            }
+        })
+        .collect_view();
+    view! {
+        <div class=emu_style::logpanel>
+            <div class=emu_style::sectop>
+                <span>Log</span>
+            </div>
+            <div class=emu_style::logtools>
+                {level_toggles}
+                <input
+                    type="text"
+                    placeholder="Search"
+                    prop:value=move || filter.with(|filter| filter.text.clone())
+                    on:input=move |ev| {
+                        let text = event_target_value(&ev);
+                        filter.update(|filter| filter.text = text);
+                    }
+                />
+                <label>
+                    Keep
+                    <input
+                        type="text"
+                        class=emu_style::loglimit
+                        prop:value=limit
+                        on:change=set_limit
+                    />
+                </label>
+                <span>{move || format!("{} / {}", logs.with(Vec::len), total())}</span>
+                <input
+                    type="button"
+                    value="Clear"
+                    on:click=move |_| emu_cfg_ctx.update(|cfg| cfg.logstore.clear())
+                />
+                <input type="button" value=".txt" on:click=move |_| export(false) />
+                <input type="button" value=".json" on:click=move |_| export(true) />
+            </div>
+            <div class=emu_style::logtable>
+                <table>
+                    <thead>
+                        <tr>
+                            <th>Time</th>
+                            <th>Cycle</th>
+                            <th>Level</th>
+                            <th>Event</th>
+                            <th>Message</th>
+                            <th></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        <For each=move || logs.get() key=|log| (log.id, log.repeats) let:log>
+                            <tr class=level_class(log.level)>
+                                <td>{fmt_timestamp(&log.timestamp)}</td>
+                                <td>{log.cycles}</td>
+                                <td>{log.level.name()}</td>
+                                <td>{log.short_message}</td>
+                                <td>{log.message}</td>
+                                <td>{(log.repeats > 1).then(|| format!("x{}", log.repeats))}</td>
+                            </tr>
+                        </For>
+                    </tbody>
+                </table>
+            </div>
+        </div>
+    }
+}
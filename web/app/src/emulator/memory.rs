@@ -1,27 +1,52 @@
+use super::history::write_bytes;
+use super::symbols::SymbolTable;
 use super::{emu_style, EmulatorCfgContext, EmulatorContext};
 use crate::utils::icons::Icon;
-use emu_lib::memory::MemoryDevice;
+use emu_lib::cpu::instruction::InstructionParser;
+use emu_lib::cpu::z80::parser::Z80_PARSER;
+use emu_lib::cpu::z80::Z80;
+use emu_lib::emulator::Emulator;
+use emu_lib::memory::{Memory as EmuMemory, MemoryDevice};
 use leptos::ev::Event;
 use leptos::prelude::*;
 use leptos::web_sys::HtmlInputElement;
 use leptos::{html, IntoView};
 use leptos_use::{on_click_outside_with_options, OnClickOutsideOptions};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::ops::Div;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub enum MemDisplay {
     Hex,
     Dec,
+    SignedDec,
+    Bin,
     Ascii,
+    Word,
+    Disasm,
 }
 
 impl MemDisplay {
+    pub const ALL: [MemDisplay; 7] = [
+        MemDisplay::Hex,
+        MemDisplay::Dec,
+        MemDisplay::SignedDec,
+        MemDisplay::Bin,
+        MemDisplay::Ascii,
+        MemDisplay::Word,
+        MemDisplay::Disasm,
+    ];
+
     pub fn from_str(value: &str) -> Option<Self> {
         match value {
             "hex" => Some(MemDisplay::Hex),
             "dec" => Some(MemDisplay::Dec),
+            "sdec" => Some(MemDisplay::SignedDec),
+            "bin" => Some(MemDisplay::Bin),
             "ascii" => Some(MemDisplay::Ascii),
+            "word" => Some(MemDisplay::Word),
+            "disasm" => Some(MemDisplay::Disasm),
             _ => None,
         }
     }
@@ -30,7 +55,94 @@ impl MemDisplay {
         match self {
             MemDisplay::Hex => "hex",
             MemDisplay::Dec => "dec",
+            MemDisplay::SignedDec => "sdec",
+            MemDisplay::Bin => "bin",
             MemDisplay::Ascii => "ascii",
+            MemDisplay::Word => "word",
+            MemDisplay::Disasm => "disasm",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MemDisplay::Hex => "Hex",
+            MemDisplay::Dec => "Dec",
+            MemDisplay::SignedDec => "Signed",
+            MemDisplay::Bin => "Binary",
+            MemDisplay::Ascii => "ASCII",
+            MemDisplay::Word => "Word (LE)",
+            MemDisplay::Disasm => "Disassembly",
+        }
+    }
+
+    /// Number of bytes shown by a single cell.
+    pub fn cell_bytes(&self) -> u16 {
+        match self {
+            MemDisplay::Word => 2,
+            _ => 1,
+        }
+    }
+
+    fn max_length(&self) -> usize {
+        match self {
+            MemDisplay::Hex => 2,
+            MemDisplay::Dec => 3,
+            MemDisplay::SignedDec => 4,
+            MemDisplay::Bin => 8,
+            MemDisplay::Ascii => 1,
+            MemDisplay::Word => 4,
+            MemDisplay::Disasm => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum FollowRegister {
+    PC,
+    SP,
+    HL,
+    IX,
+    IY,
+}
+
+impl FollowRegister {
+    pub const ALL: [FollowRegister; 5] = [
+        FollowRegister::PC,
+        FollowRegister::SP,
+        FollowRegister::HL,
+        FollowRegister::IX,
+        FollowRegister::IY,
+    ];
+
+    pub fn from_str(value: &str) -> Option<Self> {
+        match value {
+            "PC" => Some(FollowRegister::PC),
+            "SP" => Some(FollowRegister::SP),
+            "HL" => Some(FollowRegister::HL),
+            "IX" => Some(FollowRegister::IX),
+            "IY" => Some(FollowRegister::IY),
+            _ => None,
+        }
+    }
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            FollowRegister::PC => "PC",
+            FollowRegister::SP => "SP",
+            FollowRegister::HL => "HL",
+            FollowRegister::IX => "IX",
+            FollowRegister::IY => "IY",
+        }
+    }
+
+    pub fn read(&self, emu: &Emulator<Z80>) -> u16 {
+        let registers = &emu.cpu.registers;
+        match self {
+            FollowRegister::PC => registers.pc,
+            FollowRegister::SP => registers.sp,
+            FollowRegister::HL => registers.gp.hl,
+            FollowRegister::IX => registers.ix,
+            FollowRegister::IY => registers.iy,
         }
     }
 }
@@ -41,6 +153,10 @@ pub struct MemoryContext {
     pub height: u16,
     pub start: u16,
     pub display: MemDisplay,
+    /// Highlighted range as (start, length), set by the last successful search.
+    pub selection: Option<(u16, u16)>,
+    /// When set, the view is pinned to the row holding this register's value.
+    pub follow: Option<FollowRegister>,
 }
 
 impl Default for MemoryContext {
@@ -50,20 +166,67 @@ impl Default for MemoryContext {
             height: 0x10,
             start: 0x0,
             display: MemDisplay::Hex,
+            selection: None,
+            follow: None,
         }
     }
 }
 
+impl MemoryContext {
+    fn row_start(&self, address: u16) -> u16 {
+        address - address % self.width.max(1)
+    }
+
+    pub fn goto(&mut self, address: u16) {
+        self.follow = None;
+        self.start = self.row_start(address);
+    }
+
+    /// First address shown, taking the followed register into account.
+    pub fn view_start(&self, emu: &Emulator<Z80>) -> u16 {
+        match self.follow {
+            Some(register) => self.row_start(register.read(emu)),
+            None => self.start,
+        }
+    }
+
+    pub fn columns(&self) -> u16 {
+        (self.width / self.display.cell_bytes()).max(1)
+    }
+
+    pub fn is_selected(&self, address: u16) -> bool {
+        match self.selection {
+            Some((start, len)) => address.wrapping_sub(start) < len,
+            None => false,
+        }
+    }
+}
+
+/// Memory shape with `start` resolved against the followed register.
+fn use_view_shape() -> Memo<MemoryContext> {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    Memo::new(move |_| {
+        let mut shape = emu_cfg_ctx.with(|ctx| ctx.mem_config);
+        if shape.follow.is_some() {
+            shape.start = emu_ctx.with(|emu| shape.view_start(&emu.emu));
+        }
+        shape
+    })
+}
+
 #[island]
 fn MemoryTHead() -> impl IntoView {
     let emu_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
-    let width = Memo::new(move |_| emu_ctx.with(|emu_ctx| emu_ctx.mem_config.width));
+    let shape = Memo::new(move |_| emu_ctx.with(|emu_ctx| emu_ctx.mem_config));
     view! {
         <thead>
             <tr>
                 <th></th>
-                <For each=move || 0..width() key=|n| *n let:data>
-                    <th>{format!("{:X}", data)}</th>
+                <For each=move || 0..shape.with(|shape| shape.columns()) key=|n| *n let:data>
+                    <th>
+                        {move || format!("{:X}", data * shape.with(|shape| shape.display.cell_bytes()))}
+                    </th>
                 </For>
             </tr>
         </thead>
@@ -92,6 +255,22 @@ fn format_dec(value: u8) -> String {
     format!("{}", value)
 }
 
+fn parse_signed_dec(value: &str) -> Option<u8> {
+    value.parse::<i8>().ok().map(|value| value as u8)
+}
+
+fn format_signed_dec(value: u8) -> String {
+    format!("{}", value as i8)
+}
+
+fn parse_bin(value: &str) -> Option<u8> {
+    u8::from_str_radix(value, 2).ok()
+}
+
+fn format_bin(value: u8) -> String {
+    format!("{:08b}", value)
+}
+
 fn parse_ascii(value: &str) -> Option<u8> {
     value.chars().next().map(|c| c as u8)
 }
@@ -105,19 +284,73 @@ fn format_ascii(value: u8) -> String {
     }
 }
 
-fn parse_value(value: &str, display: MemDisplay) -> Option<u8> {
-    match display {
+fn parse_word(value: &str) -> Option<u16> {
+    u16::from_str_radix(value, 16).ok()
+}
+
+fn format_word(value: u16) -> String {
+    format!("{:04X}", value)
+}
+
+/// Parses a cell edit into the bytes it covers, little-endian for words.
+fn parse_value(value: &str, display: MemDisplay) -> Option<Vec<u8>> {
+    let byte = match display {
         MemDisplay::Hex => parse_hex(value),
         MemDisplay::Dec => parse_dec(value),
+        MemDisplay::SignedDec => parse_signed_dec(value),
+        MemDisplay::Bin => parse_bin(value),
         MemDisplay::Ascii => parse_ascii(value),
-    }
+        MemDisplay::Word => return parse_word(value).map(|word| word.to_le_bytes().to_vec()),
+        MemDisplay::Disasm => return None,
+    };
+    byte.map(|byte| vec![byte])
 }
 
 fn format_value(value: u8, display: MemDisplay) -> String {
     match display {
-        MemDisplay::Hex => format_hex(value),
+        MemDisplay::Hex | MemDisplay::Disasm => format_hex(value),
         MemDisplay::Dec => format_dec(value),
+        MemDisplay::SignedDec => format_signed_dec(value),
+        MemDisplay::Bin => format_bin(value),
         MemDisplay::Ascii => format_ascii(value),
+        MemDisplay::Word => format_word(value as u16),
+    }
+}
+
+/// Decodes `len` bytes linearly from `start` and maps the address of every
+/// instruction that begins in the range to its mnemonic. Addresses inside an
+/// instruction, or of undecodable bytes, are left out.
+fn inline_mnemonics(memory: &EmuMemory, start: u16, len: u32) -> HashMap<u16, String> {
+    let mut mnemonics = HashMap::new();
+    let mut offset = 0;
+    while offset < len {
+        let address = start.wrapping_add(offset as u16);
+        let length = match Z80_PARSER.ins_from_machinecode(memory, address) {
+            Ok(instruction) => {
+                mnemonics.insert(address, instruction.to_string());
+                instruction.common().length.max(1) as u32
+            }
+            Err(_) => 1,
+        };
+        offset += length;
+    }
+    mnemonics
+}
+
+/// Mnemonics of the visible range in disassembly display, shared by every cell.
+#[derive(Clone, Copy)]
+struct VisibleMnemonics(Memo<HashMap<u16, String>>);
+
+fn read_cell(emu: &Emulator<Z80>, address: u16, display: MemDisplay) -> String {
+    match display {
+        MemDisplay::Word => match emu.memory.read_16(address) {
+            Ok(val) => format_word(val),
+            _ => "N/A".to_string(),
+        },
+        _ => match emu.memory.read_8(address) {
+            Ok(val) => format_value(val, display),
+            _ => "N/A".to_string(),
+        },
     }
 }
 
@@ -126,43 +359,69 @@ fn MemoryMemCell(column: u16, row: u16) -> impl IntoView {
     let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
     let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
     let display = Memo::new(move |_| emu_cfg_ctx.with(|ctx| ctx.mem_config.display));
-    let max_length = Memo::new(move |_| match display.get() {
-        MemDisplay::Hex => 2,
-        MemDisplay::Dec => 3,
-        MemDisplay::Ascii => 1,
-    });
-    let shape = Memo::new(move |_| emu_cfg_ctx.with(|ctx| ctx.mem_config));
+    let max_length = Memo::new(move |_| display.get().max_length());
+    let mnemonics = expect_context::<VisibleMnemonics>().0;
+    let shape = use_view_shape();
     let vw = move || {
-        let address = shape.with(|shape| get_mem_address(shape.start, shape.width, column, row));
+        let address = shape.with(|shape| {
+            get_mem_address(
+                shape.start,
+                shape.width,
+                column * shape.display.cell_bytes(),
+                row,
+            )
+        });
+        let cell_bytes = display.get_untracked().cell_bytes();
         let changed = Memo::new(move |_| {
             emu_ctx.with(|emu| {
                 if let Some(addresses) = emu.emu.memory.get_changes() {
-                    addresses.contains(&address)
+                    (0..cell_bytes).any(|offset| addresses.contains(&address.wrapping_add(offset)))
                 } else {
                     false
                 }
             })
         });
+        let selected = Memo::new(move |_| shape.with(|shape| shape.is_selected(address)));
+        let mnemonic = Memo::new(move |_| {
+            if display.get() != MemDisplay::Disasm {
+                return None;
+            }
+            mnemonics.with(|mnemonics| mnemonics.get(&address).cloned())
+        });
         let changed_class = Memo::new(move |_| {
             if changed.get() {
                 emu_style::changed
+            } else if selected.get() {
+                emu_style::found
+            } else if display.get() == MemDisplay::Disasm && mnemonic.with(|m| m.is_none()) {
+                emu_style::operand
             } else {
                 ""
             }
         });
         let read_mem = Memo::new(move |_| {
-            emu_ctx.with(|emu| match emu.emu.memory.read_8(address) {
-                Ok(val) => format_value(val, display()),
-                _ => "N/A".to_string(),
-            })
+            if let Some(mnemonic) = mnemonic.get() {
+                return mnemonic.split_whitespace().next().unwrap_or("").to_string();
+            }
+            emu_ctx.with(|emu| read_cell(&emu.emu, address, display()))
         });
+        let title = move || mnemonic.get().unwrap_or_default();
         let write_mem = move |ev: Event| {
             let value = event_target_value(&ev);
             match parse_value(&value, display()) {
-                Some(val) => {
+                Some(bytes) => {
                     emu_ctx.update(|emu| {
                         emu_cfg_ctx.update(|cfg| {
-                            if let Err(err) = emu.emu.memory.write_8(address, val) {
+                            let (entry, result) = write_bytes(
+                                &mut emu.emu.memory,
+                                format!("Edit {:#06X}", address),
+                                bytes
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(offset, val)| (address.wrapping_add(offset as u16), *val)),
+                            );
+                            cfg.history.record(entry);
+                            if let Err(err) = result {
                                 cfg.logstore.log_error(
                                     "Memory write error",
                                     format!("Memory write error: {}", err),
@@ -170,11 +429,10 @@ fn MemoryMemCell(column: u16, row: u16) -> impl IntoView {
                             } else {
                                 cfg.logstore.log_info(
                                     "Memory written",
-                                    format!("Memory write: ({:#04X}) = {:#04X}", address, val),
+                                    format!("Memory write: ({:#04X}) = {}", address, value),
                                 );
                             }
                         });
-                        emu.emu.memory.clear_change(address);
                     });
                 }
                 None => {
@@ -193,15 +451,40 @@ fn MemoryMemCell(column: u16, row: u16) -> impl IntoView {
                 }
             }
         };
-        view! { <input class=changed_class maxlength=max_length on:change=write_mem prop:value=read_mem /> }.into_any()
+        view! {
+            <input
+                class=changed_class
+                maxlength=max_length
+                style:width=move || format!("{}ch", max_length.get().max(2) + 3)
+                title=title
+                readonly=move || display.get() == MemDisplay::Disasm
+                on:change=write_mem
+                prop:value=read_mem
+            />
+        }
+        .into_any()
     };
     vw.into_view()
 }
 
 #[island]
 fn MemoryTBody() -> impl IntoView {
-    let ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
-    let shape = Memo::new(move |_| ctx.with(|ctx| ctx.mem_config));
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let shape = use_view_shape();
+    let mnemonics = Memo::new(move |_| {
+        let (start, len, display) = shape.with(|shape| {
+            (
+                shape.start,
+                shape.width as u32 * shape.height as u32,
+                shape.display,
+            )
+        });
+        if display != MemDisplay::Disasm {
+            return HashMap::new();
+        }
+        emu_ctx.with(|emu| inline_mnemonics(&emu.emu.memory, start, len))
+    });
+    provide_context(VisibleMnemonics(mnemonics));
     view! {
         <tbody>
             <For each=move || 0..shape.with(|shape| shape.height) key=|n| *n let:row>
@@ -217,7 +500,7 @@ fn MemoryTBody() -> impl IntoView {
                             format!("{:04X}", val)
                         }}
                     </th>
-                    <For each=move || 0..shape.with(|shape| shape.width) key=move |n| *n let:column>
+                    <For each=move || 0..shape.with(|shape| shape.columns()) key=move |n| *n let:column>
                         <td>
                             <MemoryMemCell column row />
                         </td>
@@ -228,6 +511,351 @@ fn MemoryTBody() -> impl IntoView {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SearchKind {
+    Bytes,
+    Word,
+    Text,
+}
+
+impl SearchKind {
+    fn from_str(value: &str) -> Option<Self> {
+        match value {
+            "bytes" => Some(SearchKind::Bytes),
+            "word" => Some(SearchKind::Word),
+            "text" => Some(SearchKind::Text),
+            _ => None,
+        }
+    }
+
+    fn placeholder(&self) -> &'static str {
+        match self {
+            SearchKind::Bytes => "3E 05 ..",
+            SearchKind::Word => "0x1234",
+            SearchKind::Text => "text",
+        }
+    }
+
+    fn pattern(&self, value: &str, symbols: &SymbolTable) -> Result<Vec<u8>, String> {
+        match self {
+            SearchKind::Bytes => parse_bytes(value),
+            SearchKind::Word => symbols
+                .resolve(value)
+                .map(|word| word.to_le_bytes().to_vec())
+                .ok_or_else(|| format!("invalid word \"{}\"", value)),
+            SearchKind::Text if value.is_empty() => Err("empty pattern".to_string()),
+            SearchKind::Text => Ok(value.as_bytes().to_vec()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RangeOperation {
+    Fill,
+    Copy,
+    Move,
+}
+
+impl RangeOperation {
+    fn from_str(value: &str) -> Option<Self> {
+        match value {
+            "fill" => Some(RangeOperation::Fill),
+            "copy" => Some(RangeOperation::Copy),
+            "move" => Some(RangeOperation::Move),
+            _ => None,
+        }
+    }
+
+    fn to_str(&self) -> &'static str {
+        match self {
+            RangeOperation::Fill => "Fill",
+            RangeOperation::Copy => "Copy",
+            RangeOperation::Move => "Move",
+        }
+    }
+
+    fn placeholder(&self) -> &'static str {
+        match self {
+            RangeOperation::Fill => "Bytes",
+            RangeOperation::Copy | RangeOperation::Move => "Destination",
+        }
+    }
+
+    /// Computes the writes for the inclusive range `start..=end`. Sources are read
+    /// up front so overlapping copies and moves behave like `memmove`.
+    fn writes(
+        &self,
+        memory: &EmuMemory,
+        start: u16,
+        end: u16,
+        arg: &str,
+        symbols: &SymbolTable,
+    ) -> Result<Vec<(u16, u8)>, String> {
+        if let RangeOperation::Fill = self {
+            let pattern = parse_bytes(arg)?;
+            return Ok((start..=end)
+                .zip(pattern.iter().cycle())
+                .map(|(address, value)| (address, *value))
+                .collect());
+        }
+        let dest = symbols
+            .resolve(arg)
+            .ok_or_else(|| format!("invalid destination \"{}\"", arg))?;
+        let source = (start..=end)
+            .map(|address| {
+                memory
+                    .read_8(address)
+                    .map_err(|err| format!("read error at {:#06X}: {}", address, err))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        let len = source.len() as u16;
+        let mut writes = source
+            .into_iter()
+            .enumerate()
+            .map(|(offset, value)| (dest.wrapping_add(offset as u16), value))
+            .collect::<Vec<_>>();
+        if let RangeOperation::Move = self {
+            writes.extend(
+                (start..=end)
+                    .filter(|address| address.wrapping_sub(dest) >= len)
+                    .map(|address| (address, 0)),
+            );
+        }
+        Ok(writes)
+    }
+}
+
+fn parse_bytes(value: &str) -> Result<Vec<u8>, String> {
+    let bytes = value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|byte| !byte.is_empty())
+        .map(|byte| {
+            parse_hex(byte.trim_start_matches("0x"))
+                .ok_or_else(|| format!("invalid byte \"{}\"", byte))
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    if bytes.is_empty() {
+        return Err("empty pattern".to_string());
+    }
+    Ok(bytes)
+}
+
+fn find_pattern(memory: &EmuMemory, pattern: &[u8], from: u16, forward: bool) -> Option<u16> {
+    (0..=u16::MAX)
+        .map(|offset| {
+            if forward {
+                from.wrapping_add(offset)
+            } else {
+                from.wrapping_sub(offset)
+            }
+        })
+        .find(|address| {
+            pattern.iter().enumerate().all(|(offset, byte)| {
+                memory
+                    .read_8(address.wrapping_add(offset as u16))
+                    .map_or(false, |value| value == *byte)
+            })
+        })
+}
+
+#[island]
+fn MemoryTools() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let goto_value = RwSignal::new(String::new());
+    let search_kind = RwSignal::new(SearchKind::Bytes);
+    let search_value = RwSignal::new(String::new());
+    let operation = RwSignal::new(RangeOperation::Fill);
+    let range_start = RwSignal::new(String::new());
+    let range_end = RwSignal::new(String::new());
+    let range_arg = RwSignal::new(String::new());
+
+    let goto = move || {
+        let value = goto_value.get_untracked();
+        emu_cfg_ctx.update(|cfg| match cfg.symbols.resolve(&value) {
+            Some(address) => {
+                cfg.mem_config.goto(address);
+                cfg.logstore
+                    .log_info("Memory goto", format!("Memory view moved to {:#06X}", address));
+            }
+            None => {
+                cfg.logstore
+                    .log_error("Invalid address", format!("Invalid address: {}", value));
+            }
+        });
+    };
+
+    let search = move |forward: bool| {
+        let value = search_value.get_untracked();
+        emu_cfg_ctx.update(|cfg| {
+            let pattern = match search_kind.get_untracked().pattern(&value, &cfg.symbols) {
+                Ok(pattern) => pattern,
+                Err(err) => {
+                    cfg.logstore.log_error(
+                        "Invalid search pattern",
+                        format!("Invalid search pattern: {}", err),
+                    );
+                    return;
+                }
+            };
+            let from = match cfg.mem_config.selection {
+                Some((start, _)) if forward => start.wrapping_add(1),
+                Some((start, _)) => start.wrapping_sub(1),
+                None => cfg.mem_config.start,
+            };
+            let found = emu_ctx
+                .with_untracked(|emu| find_pattern(&emu.emu.memory, &pattern, from, forward));
+            match found {
+                Some(address) => {
+                    cfg.mem_config.selection = Some((address, pattern.len() as u16));
+                    cfg.mem_config.goto(address);
+                    cfg.logstore
+                        .log_info("Pattern found", format!("Pattern found at {:#06X}", address));
+                }
+                None => {
+                    cfg.mem_config.selection = None;
+                    cfg.logstore
+                        .log_warning("Pattern not found", format!("Pattern not found: {}", value));
+                }
+            }
+        });
+    };
+
+    let apply = move || {
+        let op = operation.get_untracked();
+        emu_ctx.update(|emu| {
+            emu_cfg_ctx.update(|cfg| {
+                let start = cfg.symbols.resolve(&range_start.get_untracked());
+                let end = cfg.symbols.resolve(&range_end.get_untracked());
+                let (start, end) = match (start, end) {
+                    (Some(start), Some(end)) if start <= end => (start, end),
+                    _ => {
+                        cfg.logstore.log_error(
+                            "Memory operation error",
+                            format!("{} error: invalid address range", op.to_str()),
+                        );
+                        return;
+                    }
+                };
+                let writes = match op.writes(
+                    &emu.emu.memory,
+                    start,
+                    end,
+                    &range_arg.get_untracked(),
+                    &cfg.symbols,
+                ) {
+                    Ok(writes) => writes,
+                    Err(err) => {
+                        cfg.logstore.log_error(
+                            "Memory operation error",
+                            format!("{} error: {}", op.to_str(), err),
+                        );
+                        return;
+                    }
+                };
+                let description = format!("{} {:#06X}-{:#06X}", op.to_str(), start, end);
+                let (entry, result) = write_bytes(&mut emu.emu.memory, description.clone(), writes);
+                cfg.history.record(entry);
+                match result {
+                    Ok(()) => cfg.logstore.log_info("Memory operation", description),
+                    Err(err) => cfg.logstore.log_error(
+                        "Memory operation error",
+                        format!("{} error: {}", description, err),
+                    ),
+                }
+            });
+        });
+    };
+
+    let undo = move || {
+        emu_ctx.update(|emu| {
+            emu_cfg_ctx.update(|cfg| match cfg.history.undo(&mut emu.emu.memory) {
+                Some(Ok(entry)) => cfg
+                    .logstore
+                    .log_info("Undo", format!("Undone: {}", entry.description)),
+                Some(Err(err)) => cfg.logstore.log_error("Undo error", err),
+                None => cfg
+                    .logstore
+                    .log_warning("Nothing to undo", "Nothing to undo".to_string()),
+            });
+        });
+    };
+
+    view! {
+        <div class=emu_style::memtools>
+            <div>
+                <input
+                    placeholder="Goto: 0x1234, 4660, label"
+                    prop:value=goto_value
+                    on:input=move |ev| goto_value.set(event_target_value(&ev))
+                    on:keydown=move |ev| {
+                        if ev.key() == "Enter" {
+                            goto();
+                        }
+                    }
+                />
+                <input type="button" value="Go" on:click=move |_| goto() />
+            </div>
+            <div>
+                <select on:change=move |ev| {
+                    if let Some(kind) = SearchKind::from_str(&event_target_value(&ev)) {
+                        search_kind.set(kind);
+                    }
+                }>
+                    <option value="bytes">Bytes</option>
+                    <option value="word">Word</option>
+                    <option value="text">Text</option>
+                </select>
+                <input
+                    placeholder=move || search_kind.get().placeholder()
+                    prop:value=search_value
+                    on:input=move |ev| search_value.set(event_target_value(&ev))
+                    on:keydown=move |ev| {
+                        if ev.key() == "Enter" {
+                            search(!ev.shift_key());
+                        }
+                    }
+                />
+                <input type="button" value="Prev" on:click=move |_| search(false) />
+                <input type="button" value="Next" on:click=move |_| search(true) />
+            </div>
+            <div>
+                <select on:change=move |ev| {
+                    if let Some(op) = RangeOperation::from_str(&event_target_value(&ev)) {
+                        operation.set(op);
+                    }
+                }>
+                    <option value="fill">Fill</option>
+                    <option value="copy">Copy</option>
+                    <option value="move">Move</option>
+                </select>
+                <input
+                    placeholder="Start"
+                    prop:value=range_start
+                    on:input=move |ev| range_start.set(event_target_value(&ev))
+                />
+                <input
+                    placeholder="End"
+                    prop:value=range_end
+                    on:input=move |ev| range_end.set(event_target_value(&ev))
+                />
+                <input
+                    placeholder=move || operation.get().placeholder()
+                    prop:value=range_arg
+                    on:input=move |ev| range_arg.set(event_target_value(&ev))
+                />
+                <input type="button" value="Apply" on:click=move |_| apply() />
+                <input
+                    type="button"
+                    value="Undo"
+                    prop:disabled=move || emu_cfg_ctx.with(|cfg| cfg.history.is_empty())
+                    on:click=move |_| undo()
+                />
+            </div>
+        </div>
+    }
+}
+
 #[derive(Clone)]
 struct DisplayMemorySettings {
     signal: RwSignal<bool>,
@@ -250,52 +878,57 @@ pub fn SettingsInner() -> impl IntoView {
         OnClickOutsideOptions::default().ignore(["div", ".memsetbtn"]),
     );
 
+    let follow = Memo::new(move |_| emu_cfg_ctx.with(|ctx| ctx.mem_config.follow));
+    let change_follow = move |value: String| {
+        emu_cfg_ctx.update(|ctx| {
+            ctx.mem_config.follow = FollowRegister::from_str(&value);
+        });
+    };
+
     view! {
         <div node_ref=noderef class=emu_style::secsettingsinner>
             <div>
                 <span>Display mode:</span>
             </div>
-            <div>
-                <input
-                    type="radio"
-                    id="hexdsp"
-                    name="displaymode"
-                    value="Hex"
-                    on:click=move |_| change_display(MemDisplay::Hex)
-                    prop:checked=move || {
-                        if let MemDisplay::Hex = display.get() { true } else { false }
-                    }
-                />
-                <label for="hexdsp">Hex</label>
-            </div>
-            <div>
-                <input
-                    type="radio"
-                    id="decdsp"
-                    name="displaymode"
-                    value="Dec"
-                    on:click=move |_| change_display(MemDisplay::Dec)
-                    prop:checked=move || {
-                        if let MemDisplay::Dec = display.get() { true } else { false }
+            {MemDisplay::ALL
+                .into_iter()
+                .map(|mode| {
+                    let id = format!("{}dsp", mode.to_str());
+                    view! {
+                        <div>
+                            <input
+                                type="radio"
+                                id=id.clone()
+                                name="displaymode"
+                                value=mode.label()
+                                on:click=move |_| change_display(mode)
+                                prop:checked=move || display.get() == mode
+                            />
+                            <label for=id>{mode.label()}</label>
+                        </div>
                     }
-                />
-                <label for="decdsp">Dec</label>
-            </div>
+                })
+                .collect_view()}
             <div>
-                <input
-                    type="radio"
-                    id="asciidsp"
-                    name="displaymode"
-                    value="Ascii"
-                    on:click=move |_| change_display(MemDisplay::Ascii)
-                    prop:checked=move || {
-                        emu_cfg_ctx
-                            .with(|ctx| {
-                                if let MemDisplay::Ascii = display.get() { true } else { false }
-                            })
-                    }
-                />
-                <label for="asciidsp">ASCII</label>
+                <span>Follow:</span>
+                <select on:change=move |ev| change_follow(event_target_value(&ev))>
+                    <option value="none" selected=move || follow.get().is_none()>
+                        None
+                    </option>
+                    {FollowRegister::ALL
+                        .into_iter()
+                        .map(|register| {
+                            view! {
+                                <option
+                                    value=register.to_str()
+                                    selected=move || follow.get() == Some(register)
+                                >
+                                    {register.to_str()}
+                                </option>
+                            }
+                        })
+                        .collect_view()}
+                </select>
             </div>
         </div>
     }
@@ -326,10 +959,12 @@ pub fn Settings() -> impl IntoView {
 }
 #[island]
 pub fn Memory() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
     let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
     view! {
         <div class=emu_style::memorymap>
             <Settings />
+            <MemoryTools />
             <table
                 class=emu_style::memorymaptable
                 on:wheel=move |ev| {
@@ -342,7 +977,10 @@ pub fn Memory() -> impl IntoView {
                         .update(|emu_cfg| {
                             let offset = delta_direction * delta_magnitude
                                 * emu_cfg.mem_config.width as i16;
-                            emu_cfg.mem_config.start = (emu_cfg.mem_config.start as i32)
+                            let start = emu_ctx
+                                .with_untracked(|emu| emu_cfg.mem_config.view_start(&emu.emu));
+                            emu_cfg.mem_config.follow = None;
+                            emu_cfg.mem_config.start = (start as i32)
                                 .wrapping_add(offset as i32) as u16;
                         });
                 }
@@ -353,3 +991,24 @@ pub fn Memory() -> impl IntoView {
         </div>
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emu_lib::memory::memdevices::RAM;
+
+    #[test]
+    fn mnemonics_stay_aligned_across_rows() {
+        let mut memory = EmuMemory::new();
+        memory.add_device(Box::new(RAM::new(0x10000)));
+        // NOP x3, then LD BC,0x1234 straddling the 4-byte row boundary, then NOP.
+        let bytes = [0x00, 0x00, 0x00, 0x01, 0x34, 0x12, 0x00];
+        for (address, byte) in bytes.iter().enumerate() {
+            memory.write_8_force(address as u16, *byte).unwrap();
+        }
+        let mnemonics = inline_mnemonics(&memory, 0x0000, 8);
+        let mut starts = mnemonics.keys().copied().collect::<Vec<_>>();
+        starts.sort();
+        assert_eq!(starts, vec![0, 1, 2, 3, 6, 7]);
+    }
+}
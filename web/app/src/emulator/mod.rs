@@ -1,19 +1,43 @@
 mod account;
+mod analysis;
+mod assembler;
+mod callgraph;
+mod code_editor;
+mod compile_options;
 mod control;
+mod diagnostics;
 mod disassembler;
 mod editor;
+mod flow;
+mod generated;
+mod heatmap;
+mod highlight;
+mod history;
 mod info;
+mod instances;
+mod listing;
+mod logs;
 mod memory;
+mod profiler;
+mod project;
 mod registers;
+mod script;
 mod display;
+mod stack;
+mod symbols;
+mod workspace;
 
 
 use crate::emulator::account::Account;
+use crate::emulator::callgraph::CallGraph;
 use crate::emulator::display::Display;
 use crate::emulator::disassembler::DisassemblerContext;
 use crate::emulator::editor::{Editor, EditorContext};
 use crate::emulator::memory::MemoryContext;
-use crate::emulator::registers::Registers;
+use crate::emulator::profiler::{Profiler, ProfilerContext};
+use crate::emulator::registers::{RegisterSnapshot, Registers};
+use crate::emulator::script::ScriptConsole;
+use crate::emulator::stack::Stack;
 use crate::utils::logger::LogStore;
 use control::Control;
 use disassembler::Disassembler;
@@ -25,19 +49,42 @@ use leptos_meta::{Meta, Title};
 use memory::Memory;
 use crate::emulator::control::ControlContext;
 use crate::emulator::display::DisplayMemoryDevice;
+use crate::emulator::logs::LogPanel;
+use crate::emulator::heatmap::{AccessTracker, Heatmap, TracingDevice};
+use crate::emulator::history::ChangeHistory;
+use crate::emulator::instances::{InstanceStore, InstanceTabs, Lockstep};
+use crate::emulator::symbols::SymbolTable;
+use crate::emulator::workspace::persist_workspace;
 
 stylance::import_style!(emu_style, "./emulator.module.scss");
 
-fn build_z80_emu(display: DisplayMemoryDevice) -> Emulator<Z80> {
+const DISPLAY_WIDTH: usize = 192;
+const DISPLAY_HEIGHT: usize = 128;
+
+fn build_z80_emu(display: DisplayMemoryDevice, tracker: AccessTracker) -> Emulator<Z80> {
     use emu_lib::memory;
     use emu_lib::memory::MemoryDevice;
     let mut memory = memory::Memory::new();
     let initial_ram_size = 0x4000;
+    let display_size = display.size();
     let initial_ram = memory::memdevices::RAM::new(initial_ram_size);
-    let post_ram = memory::memdevices::RAM::new(0x10000 - initial_ram_size - display.size());
-    memory.add_device(Box::new(initial_ram));
-    memory.add_device(Box::new(display));
-    memory.add_device(Box::new(post_ram));
+    let post_ram = memory::memdevices::RAM::new(0x10000 - initial_ram_size - display_size);
+    let post_ram_start = (initial_ram_size + display_size) as u16;
+    memory.add_device(Box::new(TracingDevice::new(
+        Box::new(initial_ram),
+        0,
+        tracker.clone(),
+    )));
+    memory.add_device(Box::new(TracingDevice::new(
+        Box::new(display),
+        initial_ram_size as u16,
+        tracker.clone(),
+    )));
+    memory.add_device(Box::new(TracingDevice::new(
+        Box::new(post_ram),
+        post_ram_start,
+        tracker,
+    )));
     let mut emu = Emulator::<Z80>::new_w_mem(memory);
     emu.memory.record_changes(true);
     emu
@@ -45,11 +92,16 @@ fn build_z80_emu(display: DisplayMemoryDevice) -> Emulator<Z80> {
 
 pub struct EmulatorContext {
     pub emu: Emulator<Z80>,
+    pub registers_before: RegisterSnapshot,
 }
 
 impl EmulatorContext {
-    fn new(display: DisplayMemoryDevice) -> Self {
-        EmulatorContext { emu: build_z80_emu(display) }
+    fn new(display: DisplayMemoryDevice, tracker: AccessTracker) -> Self {
+        let emu = build_z80_emu(display, tracker);
+        EmulatorContext {
+            registers_before: RegisterSnapshot::capture(&emu),
+            emu,
+        }
     }
 }
 
@@ -60,6 +112,11 @@ pub struct EmulatorCfgContext {
     pub editor: EditorContext,
     pub display: DisplayMemoryDevice,
     pub control: ControlContext,
+    pub symbols: SymbolTable,
+    pub history: ChangeHistory,
+    pub profiler: ProfilerContext,
+    pub tracker: AccessTracker,
+    pub instances: InstanceStore,
 }
 
 impl EmulatorCfgContext {
@@ -71,6 +128,11 @@ impl EmulatorCfgContext {
             editor: EditorContext::default(),
             display,
             control: ControlContext::default(),
+            symbols: SymbolTable::default(),
+            history: ChangeHistory::default(),
+            profiler: ProfilerContext::default(),
+            tracker: AccessTracker::default(),
+            instances: InstanceStore::default(),
         }
     }
 }
@@ -84,6 +146,7 @@ pub fn EmulatorNoTitle() -> impl IntoView {
                 <Disassembler />
                 <div class=emu_style::regsinfo>
                     <Registers />
+                    <Stack />
                     <Info />
                 </div>
             </div>
@@ -97,8 +160,9 @@ pub fn EmulatorInner() -> impl IntoView {
         provide_context(RwSignal::new(cfg));
     }
     if use_context::<RwSignal<EmulatorContext>>().is_none() {
-        let display = DisplayMemoryDevice::new(192, 128);
-        provide_context(RwSignal::new(EmulatorContext::new(display)));
+        let display = DisplayMemoryDevice::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        let tracker = AccessTracker::default();
+        provide_context(RwSignal::new(EmulatorContext::new(display, tracker.clone())));
         let cfg = expect_context::<RwSignal<EmulatorCfgContext>>();
         cfg.update(|cfg| {
             cfg.logstore.log_info(
@@ -106,10 +170,13 @@ pub fn EmulatorInner() -> impl IntoView {
                 "Emulator initialized with default settings".to_string(),
             );
             cfg.display = display;
+            cfg.tracker = tracker;
         })
     }
+    persist_workspace(expect_context::<RwSignal<EmulatorCfgContext>>());
     view! {
         <div class=emu_style::emumain>
+        <InstanceTabs />
         <Control />
         <div class=emu_style::emulator>
             <EmulatorNoTitle />
@@ -119,6 +186,14 @@ pub fn EmulatorInner() -> impl IntoView {
             </div>
             <Account />
         </div>
+        <div class=emu_style::toolpanels>
+            <Heatmap />
+            <Profiler />
+            <CallGraph />
+            <Lockstep />
+            <ScriptConsole />
+            <LogPanel />
+        </div>
         </div>
     }
 }
@@ -0,0 +1,248 @@
+use super::symbols::SymbolTable;
+use super::{emu_style, EmulatorCfgContext};
+use leptos::prelude::*;
+use std::collections::HashMap;
+
+/// Rows shown in the hot-spot table.
+const PROFILE_ROWS: usize = 40;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HotSpot {
+    pub hits: u64,
+    pub cycles: u64,
+}
+
+impl HotSpot {
+    fn add(&mut self, other: HotSpot) {
+        self.hits += other.hits;
+        self.cycles += other.cycles;
+    }
+}
+
+/// Executed cycles and hit counts per instruction address.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    spots: HashMap<u16, HotSpot>,
+    total_cycles: u64,
+    max_cycles: u64,
+}
+
+impl Profile {
+    pub fn record(&mut self, pc: u16, cycles: u64) {
+        let spot = self.spots.entry(pc).or_default();
+        spot.add(HotSpot { hits: 1, cycles });
+        self.total_cycles += cycles;
+        self.max_cycles = self.max_cycles.max(spot.cycles);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spots.is_empty()
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    /// Share of the hottest address's cycles spent at `pc`, from 0.0 to 1.0.
+    pub fn heat(&self, pc: u16) -> f64 {
+        match self.spots.get(&pc) {
+            Some(spot) if self.max_cycles > 0 => spot.cycles as f64 / self.max_cycles as f64,
+            _ => 0.0,
+        }
+    }
+
+    fn by_address(&self, symbols: &SymbolTable) -> Vec<ProfileRow> {
+        self.spots
+            .iter()
+            .map(|(pc, spot)| ProfileRow {
+                label: symbols.describe(*pc),
+                address: *pc,
+                spot: *spot,
+            })
+            .collect()
+    }
+
+    /// Sums every address into the routine whose symbol precedes it.
+    fn by_routine(&self, symbols: &SymbolTable) -> Vec<ProfileRow> {
+        let mut routines: HashMap<Option<(&str, u16)>, HotSpot> = HashMap::new();
+        for (pc, spot) in &self.spots {
+            routines.entry(symbols.nearest(*pc)).or_default().add(*spot);
+        }
+        routines
+            .into_iter()
+            .map(|(routine, spot)| match routine {
+                Some((name, address)) => ProfileRow {
+                    label: name.to_string(),
+                    address,
+                    spot,
+                },
+                None => ProfileRow {
+                    label: "(no symbol)".to_string(),
+                    address: 0,
+                    spot,
+                },
+            })
+            .collect()
+    }
+}
+
+pub struct ProfilerContext {
+    pub enabled: RwSignal<bool>,
+    pub profile: RwSignal<Profile>,
+}
+
+impl Default for ProfilerContext {
+    fn default() -> Self {
+        Self {
+            enabled: RwSignal::new(false),
+            profile: RwSignal::new(Profile::default()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct ProfileRow {
+    label: String,
+    address: u16,
+    spot: HotSpot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SortColumn {
+    Location,
+    Hits,
+    Cycles,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Grouping {
+    Address,
+    Routine,
+}
+
+#[island]
+pub fn Profiler() -> impl IntoView {
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let enabled = emu_cfg_ctx.with_untracked(|cfg| cfg.profiler.enabled);
+    let profile = emu_cfg_ctx.with_untracked(|cfg| cfg.profiler.profile);
+    let grouping = RwSignal::new(Grouping::Address);
+    let sort = RwSignal::new((SortColumn::Cycles, true));
+    let sort_by = move |column: SortColumn| {
+        sort.update(|(current, descending)| {
+            if *current == column {
+                *descending = !*descending;
+            } else {
+                *current = column;
+                *descending = column != SortColumn::Location;
+            }
+        });
+    };
+    let sort_marker = move |column: SortColumn| {
+        move || match sort.get() {
+            (current, true) if current == column => " ▼",
+            (current, false) if current == column => " ▲",
+            _ => "",
+        }
+    };
+    let rows = Memo::new(move |_| {
+        let mut rows = emu_cfg_ctx.with(|cfg| {
+            profile.with(|profile| match grouping.get() {
+                Grouping::Address => profile.by_address(&cfg.symbols),
+                Grouping::Routine => profile.by_routine(&cfg.symbols),
+            })
+        });
+        let (column, descending) = sort.get();
+        rows.sort_by(|a, b| {
+            let ordering = match column {
+                SortColumn::Location => a.address.cmp(&b.address),
+                SortColumn::Hits => a.spot.hits.cmp(&b.spot.hits),
+                SortColumn::Cycles => a.spot.cycles.cmp(&b.spot.cycles),
+            };
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        rows.truncate(PROFILE_ROWS);
+        rows
+    });
+    let total_cycles = Memo::new(move |_| profile.with(|profile| profile.total_cycles()));
+    let show_disasm = move |address: u16| {
+        emu_cfg_ctx.update(|cfg| cfg.disasm_config.start = Some(address));
+    };
+    let clear = move |_| {
+        profile.set(Profile::default());
+        emu_cfg_ctx.update(|cfg| {
+            cfg.logstore
+                .log_info("Profile cleared", "Profile cleared".to_string())
+        });
+    };
+    let table_rows = move || {
+        let total = total_cycles.get().max(1) as f64;
+        rows.get()
+            .into_iter()
+            .map(|row| {
+                let address = row.address;
+                view! {
+                    <tr on:click=move |_| show_disasm(address)>
+                        <td class=emu_style::link>{row.label}</td>
+                        <td>{format!("{:04X}", row.address)}</td>
+                        <td>{row.spot.hits}</td>
+                        <td>{row.spot.cycles}</td>
+                        <td>{format!("{:.1}%", row.spot.cycles as f64 * 100.0 / total)}</td>
+                    </tr>
+                }
+            })
+            .collect_view()
+    };
+    view! {
+        <div class=emu_style::profiler>
+            <div class=emu_style::sectop>
+                <span>Profiler</span>
+            </div>
+            <div class=emu_style::profilertools>
+                <label>
+                    <input
+                        type="checkbox"
+                        prop:checked=enabled
+                        on:change=move |ev| enabled.set(event_target_checked(&ev))
+                    />
+                    Profile runs
+                </label>
+                <select on:change=move |ev| {
+                    grouping
+                        .set(
+                            match event_target_value(&ev).as_str() {
+                                "routine" => Grouping::Routine,
+                                _ => Grouping::Address,
+                            },
+                        )
+                }>
+                    <option value="address">By address</option>
+                    <option value="routine">By routine</option>
+                </select>
+                <input type="button" value="Clear" on:click=clear />
+                <span>{move || format!("{} cycles", total_cycles.get())}</span>
+            </div>
+            <table>
+                <thead>
+                    <tr>
+                        <th on:click=move |_| sort_by(SortColumn::Location)>
+                            "Location"{sort_marker(SortColumn::Location)}
+                        </th>
+                        <th>Address</th>
+                        <th on:click=move |_| sort_by(SortColumn::Hits)>
+                            "Hits"{sort_marker(SortColumn::Hits)}
+                        </th>
+                        <th on:click=move |_| sort_by(SortColumn::Cycles)>
+                            "Cycles"{sort_marker(SortColumn::Cycles)}
+                        </th>
+                        <th>Share</th>
+                    </tr>
+                </thead>
+                <tbody>{table_rows}</tbody>
+            </table>
+        </div>
+    }
+}
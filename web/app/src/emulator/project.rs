@@ -0,0 +1,143 @@
+use super::code_editor::{severity_class, worst_severity};
+use super::editor::CompileLanguage;
+use super::{emu_style, EmulatorCfgContext};
+use crate::utils::ccompiler::BUNDLED_HEADERS;
+use leptos::prelude::*;
+use stylance::classes;
+
+fn report(cfg: &mut EmulatorCfgContext, result: Result<(), String>) {
+    if let Err(err) = result {
+        cfg.logstore
+            .log_warning("Project file error", format!("Project file error: {}", err));
+    }
+}
+
+/// File tree of the C project: sources, headers and the bundled library headers,
+/// with actions to add, rename and delete files.
+#[island]
+pub fn ProjectFiles() -> impl IntoView {
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let files = Memo::new(move |_| {
+        emu_cfg_ctx.with(|cfg| {
+            let diagnostics = cfg.editor.diagnostics(CompileLanguage::C);
+            cfg.editor
+                .c_files
+                .iter()
+                .enumerate()
+                .map(|(index, file)| {
+                    let severity = worst_severity(
+                        diagnostics
+                            .iter()
+                            .filter(|diagnostic| diagnostic.file.as_deref() == Some(&file.name))
+                            .map(|diagnostic| diagnostic.severity),
+                    );
+                    (index, file.name.clone(), file.is_header(), severity)
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    let active = Memo::new(move |_| emu_cfg_ctx.with(|cfg| cfg.editor.c_active));
+
+    let ask_name = move |message: &str, default: &str| {
+        window()
+            .prompt_with_message_and_default(message, default)
+            .ok()
+            .flatten()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+    };
+    let on_new = move |_| {
+        if let Some(name) = ask_name("New file name (.c or .h)", "") {
+            emu_cfg_ctx.update(|cfg| {
+                let result = cfg.editor.add_c_file(&name);
+                report(cfg, result);
+            });
+        }
+    };
+    let on_rename = move |_| {
+        let index = active.get_untracked();
+        let Some(current) = emu_cfg_ctx
+            .with_untracked(|cfg| cfg.editor.active_c_file().map(|file| file.name.clone()))
+        else {
+            return;
+        };
+        if let Some(name) = ask_name("Rename file to", &current) {
+            if name != current {
+                emu_cfg_ctx.update(|cfg| {
+                    let result = cfg.editor.rename_c_file(index, &name);
+                    report(cfg, result);
+                });
+            }
+        }
+    };
+    let on_delete = move |_| {
+        let index = active.get_untracked();
+        let Some(name) = emu_cfg_ctx
+            .with_untracked(|cfg| cfg.editor.active_c_file().map(|file| file.name.clone()))
+        else {
+            return;
+        };
+        let confirmed = window()
+            .confirm_with_message(&format!("Delete {}?", name))
+            .unwrap_or(false);
+        if confirmed {
+            emu_cfg_ctx.update(|cfg| {
+                let result = cfg.editor.remove_c_file(index);
+                report(cfg, result);
+            });
+        }
+    };
+
+    let file_group = move |headers: bool| {
+        files
+            .get()
+            .into_iter()
+            .filter(|(_, _, is_header, _)| *is_header == headers)
+            .map(|(index, name, _, severity)| {
+                let class = move || {
+                    classes!(
+                        emu_style::projectfile,
+                        if active.get() == index { emu_style::projectfileactive } else { "" },
+                        severity_class(severity)
+                    )
+                };
+                view! {
+                    <div
+                        class=class
+                        on:click=move |_| emu_cfg_ctx.update(|cfg| cfg.editor.select_c_file(index))
+                    >
+                        {name}
+                    </div>
+                }
+            })
+            .collect_view()
+    };
+
+    view! {
+        <div class=emu_style::projectfiles>
+            <div class=emu_style::projecttools>
+                <input type="button" value="New" on:click=on_new />
+                <input type="button" value="Rename" on:click=on_rename />
+                <input type="button" value="Delete" on:click=on_delete />
+            </div>
+            <div class=emu_style::projectgroup>"Sources"</div>
+            {move || file_group(false)}
+            <div class=emu_style::projectgroup>"Headers"</div>
+            {move || file_group(true)}
+            <div class=emu_style::projectgroup>"Library"</div>
+            {BUNDLED_HEADERS
+                .iter()
+                .map(|name| {
+                    view! {
+                        <div
+                            class=classes!(emu_style::projectfile, emu_style::projectbundled)
+                            title="Bundled with the compiler, always on the include path"
+                        >
+                            {*name}
+                        </div>
+                    }
+                })
+                .collect_view()}
+        </div>
+    }
+}
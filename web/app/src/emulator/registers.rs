@@ -6,6 +6,123 @@ use leptos::prelude::*;
 use leptos::web_sys::HtmlInputElement;
 use std::collections::HashMap;
 
+/// Register values captured before the last step or run chunk, used to highlight
+/// what it changed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RegisterSnapshot {
+    af: u16,
+    bc: u16,
+    de: u16,
+    hl: u16,
+    af_alt: u16,
+    bc_alt: u16,
+    de_alt: u16,
+    hl_alt: u16,
+    pc: u16,
+    sp: u16,
+    ix: u16,
+    iy: u16,
+    i: u8,
+    r: u8,
+    im: u8,
+    iff1: bool,
+    iff2: bool,
+}
+
+impl RegisterSnapshot {
+    pub fn capture(emu: &Emulator<Z80>) -> Self {
+        let registers = &emu.cpu.registers;
+        RegisterSnapshot {
+            af: registers.gp.af,
+            bc: registers.gp.bc,
+            de: registers.gp.de,
+            hl: registers.gp.hl,
+            af_alt: registers.gp_alt.af,
+            bc_alt: registers.gp_alt.bc,
+            de_alt: registers.gp_alt.de,
+            hl_alt: registers.gp_alt.hl,
+            pc: registers.pc,
+            sp: registers.sp,
+            ix: registers.ix,
+            iy: registers.iy,
+            i: registers.i,
+            r: registers.r,
+            im: registers.im,
+            iff1: registers.iff1,
+            iff2: registers.iff2,
+        }
+    }
+
+    /// Registers that differ from `other` as (name, ours, theirs). The refresh
+    /// register is skipped since it counts instruction fetches.
+    pub fn differences(&self, other: &Self, ignore_pc: bool) -> Vec<(&'static str, u16, u16)> {
+        SNAPSHOT_REGISTERS
+            .iter()
+            .filter(|name| !(ignore_pc && **name == "PC") && **name != "R")
+            .filter_map(|name| {
+                let (ours, theirs) = (self.value(name)?, other.value(name)?);
+                (ours != theirs).then_some((*name, ours, theirs))
+            })
+            .collect()
+    }
+
+    /// Looks a register up by the name shown in the panel.
+    fn value(&self, name: &str) -> Option<u16> {
+        Some(match name {
+            "AF" => self.af,
+            "BC" => self.bc,
+            "DE" => self.de,
+            "HL" => self.hl,
+            "AF'" => self.af_alt,
+            "BC'" => self.bc_alt,
+            "DE'" => self.de_alt,
+            "HL'" => self.hl_alt,
+            "PC" => self.pc,
+            "SP" => self.sp,
+            "IX" => self.ix,
+            "IY" => self.iy,
+            "I" => self.i as u16,
+            "R" => self.r as u16,
+            "IM" => self.im as u16,
+            "IFF1" => self.iff1 as u16,
+            "IFF2" => self.iff2 as u16,
+            _ => return None,
+        })
+    }
+}
+
+const SNAPSHOT_REGISTERS: [&str; 17] = [
+    "AF", "BC", "DE", "HL", "AF'", "BC'", "DE'", "HL'", "PC", "SP", "IX", "IY", "I", "R", "IM",
+    "IFF1", "IFF2",
+];
+
+/// `emu_style::changed` when any bit in `mask` of register `name` differs from
+/// its value before the last step or run chunk, like changed memory cells.
+fn changed_class(
+    emu_ctx: RwSignal<EmulatorContext>,
+    name: &str,
+    mask: u16,
+    current: u16,
+) -> &'static str {
+    match emu_ctx.with(|emu| emu.registers_before.value(name)) {
+        Some(before) if (before ^ current) & mask != 0 => emu_style::changed,
+        _ => "",
+    }
+}
+
+/// F register bits, most significant first. X and Y are the undocumented copies
+/// of bits 3 and 5 of the result.
+const FLAGS: [(&str, u8); 8] = [
+    ("S", 7),
+    ("Z", 6),
+    ("Y", 5),
+    ("H", 4),
+    ("X", 3),
+    ("P/V", 2),
+    ("N", 1),
+    ("C", 0),
+];
+
 #[derive(Clone, Debug)]
 struct GPRegisterSignals16 {
     read: Signal<u16>,
@@ -46,6 +163,7 @@ struct GPRegistersAllSignals {
 pub fn Register16Bit(name: String) -> impl IntoView {
     let signals = use_context::<RwSignal<GPRegistersAllSignals>>()
         .expect("No GPRegistersSignals context found");
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
     let emu_cfg_ctx =
         use_context::<RwSignal<EmulatorCfgContext>>().expect("No EmulatorCfgContext found");
     let name_clone = name.clone();
@@ -58,6 +176,9 @@ pub fn Register16Bit(name: String) -> impl IntoView {
             .clone()
     };
     let full_val_clone = full_val.clone();
+    let name_clone = name.clone();
+    let class_full = move || changed_class(emu_ctx, &name_clone, 0xFFFF, full_val_clone().read.get());
+    let full_val_clone = full_val.clone();
     let read_full = move || format!("{:04X}", full_val().read.get());
     let name_clone = name.clone();
     let write_full = move |ev: Event| {
@@ -87,6 +208,7 @@ pub fn Register16Bit(name: String) -> impl IntoView {
                     <td>
                         <input
                             style:width="6ch"
+                            class=class_full
                             on:change=write_full
                             prop:value=read_full
                             maxlength=4
@@ -98,29 +220,73 @@ pub fn Register16Bit(name: String) -> impl IntoView {
     }
 }
 
-// #[island]
-// pub fn Register8Bit(name:String) -> impl IntoView{
-//     let signals = use_context::<RwSignal<GPRegistersAllSignals>>().expect("No GPRegistersSignals context found");
-//     let sigclone = signals.clone();
-//     let read_full = move || { format!("{:02X}", sigclone.get().signals8.get(&name).expect("No signal found for this register").read.get()) };
-//     let write_full = |ev: Event| {
-//         let value = event_target_value(&ev);
-//         if let Ok(val) = u8::from_str_radix(&value, 16) {
-//             signals.get().signals8.get(&name).expect("No signal found for this register").write.set(val);
-//         }
-//     };
-//     view! {
-//         <table>
-//             <tr><th>{name.clone()}</th></tr>
-//             <tr><td><input style:width="4ch" on:change=write_full prop:value=read_full maxlength=2/></td></tr>
-//         </table>
-//     }
-// }
+#[island]
+pub fn Register8Bit(name: String) -> impl IntoView {
+    let signals = use_context::<RwSignal<GPRegistersAllSignals>>()
+        .expect("No GPRegistersSignals context found");
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx =
+        use_context::<RwSignal<EmulatorCfgContext>>().expect("No EmulatorCfgContext found");
+    let name_clone = name.clone();
+    let full_val = move || {
+        signals
+            .get()
+            .signals8
+            .get(&name_clone)
+            .expect("No signal found for this register")
+            .clone()
+    };
+    let full_val_clone = full_val.clone();
+    let read_full = move || format!("{:02X}", full_val_clone().read.get());
+    let full_val_clone = full_val.clone();
+    let name_clone = name.clone();
+    let class_full =
+        move || changed_class(emu_ctx, &name_clone, 0xFF, full_val_clone().read.get() as u16);
+    let name_clone = name.clone();
+    let write_full = move |ev: Event| {
+        let value = event_target_value(&ev);
+        if let Ok(val) = u8::from_str_radix(&value, 16) {
+            full_val().write.set(val);
+            emu_cfg_ctx.update(|emu_cfg| {
+                emu_cfg.logstore.log_info(
+                    "Register changed",
+                    format!("Register {} set to {:02X}", name_clone, val),
+                );
+            });
+        } else {
+            let input: HtmlInputElement = event_target(&ev);
+            input.set_value(&format!("{:02X}", full_val().read.get()));
+        }
+    };
+    view! {
+        <table>
+            <thead>
+                <tr>
+                    <th>{name}</th>
+                </tr>
+            </thead>
+            <tbody>
+                <tr>
+                    <td>
+                        <input
+                            style:width="4ch"
+                            class=class_full
+                            on:change=write_full
+                            prop:value=read_full
+                            maxlength=2
+                        />
+                    </td>
+                </tr>
+            </tbody>
+        </table>
+    }
+}
 
 #[island]
 pub fn GPRegister(name: String) -> impl IntoView {
     let signals = use_context::<RwSignal<GPRegistersAllSignals>>()
         .expect("No GPRegistersSignals context found");
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
     let emu_cfg_ctx =
         use_context::<RwSignal<EmulatorCfgContext>>().expect("No EmulatorCfgContext found");
     let name_clone = name.clone();
@@ -136,6 +302,13 @@ pub fn GPRegister(name: String) -> impl IntoView {
     let read_full = move || format!("{:04X}", full_val_clone().read.get());
     let full_val_clone = full_val.clone();
     let name_clone = name.clone();
+    let class_of = move |mask: u16| {
+        let full_val_clone = full_val_clone.clone();
+        let name_clone = name_clone.clone();
+        move || changed_class(emu_ctx, &name_clone, mask, full_val_clone().read.get())
+    };
+    let full_val_clone = full_val.clone();
+    let name_clone = name.clone();
     let write_full = move |ev: Event| {
         let value = event_target_value(&ev);
         if let Ok(val) = u16::from_str_radix(&value, 16) {
@@ -226,6 +399,7 @@ pub fn GPRegister(name: String) -> impl IntoView {
                         <input
                             maxlength=4
                             style:width="6ch"
+                            class=class_of(0xFFFF)
                             on:change=write_full
                             prop:value=read_full
                         />
@@ -240,6 +414,7 @@ pub fn GPRegister(name: String) -> impl IntoView {
                         <input
                             maxlength=2
                             style:width="4ch"
+                            class=class_of(0xFF00)
                             on:change=write_higher
                             prop:value=read_higher
                         />
@@ -248,6 +423,7 @@ pub fn GPRegister(name: String) -> impl IntoView {
                         <input
                             maxlength=2
                             style:width="4ch"
+                            class=class_of(0x00FF)
                             on:change=write_lower
                             prop:value=read_lower
                         />
@@ -258,6 +434,131 @@ pub fn GPRegister(name: String) -> impl IntoView {
     }
 }
 
+#[island]
+pub fn FlagRegister() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let (af, set_af) = create_slice(
+        emu_ctx,
+        |emu| emu.emu.cpu.registers.gp.af,
+        |emu, val| emu.emu.cpu.registers.gp.af = val,
+    );
+    let names = FLAGS
+        .iter()
+        .map(|&(name, _)| view! { <th>{name}</th> })
+        .collect_view();
+    let toggles = FLAGS
+        .iter()
+        .map(|&(name, bit)| {
+            let mask = 1u16 << bit;
+            let toggle = move |ev: Event| {
+                let set = event_target_checked(&ev);
+                let value = af.get_untracked();
+                set_af.set(if set { value | mask } else { value & !mask });
+                emu_cfg_ctx.update(|emu_cfg| {
+                    emu_cfg.logstore.log_info(
+                        "Flag changed",
+                        format!("Flag {} set to {}", name, set as u8),
+                    );
+                });
+            };
+            view! {
+                <td class=move || changed_class(emu_ctx, "AF", mask, af.get())>
+                    <input type="checkbox" prop:checked=move || af.get() & mask != 0 on:change=toggle />
+                </td>
+            }
+        })
+        .collect_view();
+    view! {
+        <table class=emu_style::flags>
+            <thead>
+                <tr>{names}</tr>
+            </thead>
+            <tbody>
+                <tr>{toggles}</tr>
+            </tbody>
+        </table>
+    }
+}
+
+#[island]
+pub fn InterruptState() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let (im, set_im) = create_slice(
+        emu_ctx,
+        |emu| emu.emu.cpu.registers.im,
+        |emu, val| emu.emu.cpu.registers.im = val,
+    );
+    let (iff1, set_iff1) = create_slice(
+        emu_ctx,
+        |emu| emu.emu.cpu.registers.iff1,
+        |emu, val| emu.emu.cpu.registers.iff1 = val,
+    );
+    let (iff2, set_iff2) = create_slice(
+        emu_ctx,
+        |emu| emu.emu.cpu.registers.iff2,
+        |emu, val| emu.emu.cpu.registers.iff2 = val,
+    );
+    let log_change = move |name: &str, value: u8| {
+        emu_cfg_ctx.update(|emu_cfg| {
+            emu_cfg.logstore.log_info(
+                "Register changed",
+                format!("Register {} set to {}", name, value),
+            );
+        });
+    };
+    let on_im = move |ev: Event| {
+        if let Ok(mode @ 0..=2) = event_target_value(&ev).parse::<u8>() {
+            set_im.set(mode);
+            log_change("IM", mode);
+        }
+    };
+    let iff_toggle = move |name: &'static str, setter: SignalSetter<bool>| {
+        move |ev: Event| {
+            let enabled = event_target_checked(&ev);
+            setter.set(enabled);
+            log_change(name, enabled as u8);
+        }
+    };
+    view! {
+        <table>
+            <thead>
+                <tr>
+                    <th>IM</th>
+                    <th>IFF1</th>
+                    <th>IFF2</th>
+                </tr>
+            </thead>
+            <tbody>
+                <tr>
+                    <td class=move || changed_class(emu_ctx, "IM", 0xFF, im.get() as u16)>
+                        <select prop:value=move || im.get().to_string() on:change=on_im>
+                            <option value="0">0</option>
+                            <option value="1">1</option>
+                            <option value="2">2</option>
+                        </select>
+                    </td>
+                    <td class=move || changed_class(emu_ctx, "IFF1", 1, iff1.get() as u16)>
+                        <input
+                            type="checkbox"
+                            prop:checked=iff1
+                            on:change=iff_toggle("IFF1", set_iff1)
+                        />
+                    </td>
+                    <td class=move || changed_class(emu_ctx, "IFF2", 1, iff2.get() as u16)>
+                        <input
+                            type="checkbox"
+                            prop:checked=iff2
+                            on:change=iff_toggle("IFF2", set_iff2)
+                        />
+                    </td>
+                </tr>
+            </tbody>
+        </table>
+    }
+}
+
 #[island]
 pub fn GPRegisters() -> impl IntoView {
     let emu = expect_context::<RwSignal<EmulatorContext>>();
@@ -358,6 +659,9 @@ pub fn GPRegisters() -> impl IntoView {
             <GPRegister name="DE".to_string() />
             <GPRegister name="HL".to_string() />
         </div>
+        <div class=emu_style::registersflex>
+            <FlagRegister />
+        </div>
         <div style:display="none" class=emu_style::registersflex>
             <GPRegister name="AF'".to_string() />
             <GPRegister name="BC'".to_string() />
@@ -370,6 +674,11 @@ pub fn GPRegisters() -> impl IntoView {
             <Register16Bit name="IX".to_string() />
             <Register16Bit name="IY".to_string() />
         </div>
+        <div class=emu_style::registersflex>
+            <Register8Bit name="I".to_string() />
+            <Register8Bit name="R".to_string() />
+            <InterruptState />
+        </div>
     }
 }
 
@@ -0,0 +1,371 @@
+use super::control::{run_stepped, RunObservers};
+use super::registers::RegisterSnapshot;
+use super::{emu_style, EmulatorCfgContext, EmulatorContext};
+use emu_lib::cpu::z80::Z80;
+use emu_lib::emulator::{Emulator, StopReason};
+use emu_lib::memory::MemoryDevice;
+use leptos::prelude::*;
+use leptos::web_sys::js_sys::Date;
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext, INT};
+
+/// Rhai operations allowed per script.
+const MAX_OPERATIONS: u64 = 5_000_000;
+/// Wall-clock time a script may take before it is stopped. Checked between
+/// Rhai operations, so single calls are capped by the limits below.
+const MAX_SCRIPT_MILLIS: f64 = 10_000.0;
+/// Cycle limit for `run()` and `run_until(cond)` when the script gives none.
+const DEFAULT_RUN_CYCLES: INT = 1_000_000;
+/// Most cycles one `run()` or `run_until()` call may take.
+const MAX_RUN_CYCLES: INT = 50_000_000;
+/// Most instructions one `step()` call may take.
+const MAX_STEPS: INT = 1_000_000;
+/// Console lines kept from the last script.
+const MAX_OUTPUT_LINES: usize = 200;
+
+const HELP: &str = "reg(name) set_reg(name, v) peek(addr) peek16(addr) poke(addr, v) \
+step([n]) run([cycles]) run_until(|| cond, [cycles]) add_breakpoint(addr) \
+remove_breakpoint(addr) clear_breakpoints() print(text)";
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// A register, or half of a register pair, reachable by name from scripts.
+enum RegisterSlot<'a> {
+    Word(&'a mut u16),
+    High(&'a mut u16),
+    Low(&'a mut u16),
+    Byte(&'a mut u8),
+}
+
+impl RegisterSlot<'_> {
+    fn get(&self) -> u16 {
+        match self {
+            RegisterSlot::Word(value) => **value,
+            RegisterSlot::High(value) => **value >> 8,
+            RegisterSlot::Low(value) => **value & 0xFF,
+            RegisterSlot::Byte(value) => **value as u16,
+        }
+    }
+
+    fn set(self, new: u16) {
+        match self {
+            RegisterSlot::Word(value) => *value = new,
+            RegisterSlot::High(value) => *value = (*value & 0x00FF) | ((new & 0xFF) << 8),
+            RegisterSlot::Low(value) => *value = (*value & 0xFF00) | (new & 0xFF),
+            RegisterSlot::Byte(value) => *value = new as u8,
+        }
+    }
+}
+
+fn register_slot<'a>(emu: &'a mut Emulator<Z80>, name: &str) -> Option<RegisterSlot<'a>> {
+    let registers = &mut emu.cpu.registers;
+    Some(match name.to_ascii_uppercase().as_str() {
+        "AF" => RegisterSlot::Word(&mut registers.gp.af),
+        "BC" => RegisterSlot::Word(&mut registers.gp.bc),
+        "DE" => RegisterSlot::Word(&mut registers.gp.de),
+        "HL" => RegisterSlot::Word(&mut registers.gp.hl),
+        "AF'" => RegisterSlot::Word(&mut registers.gp_alt.af),
+        "BC'" => RegisterSlot::Word(&mut registers.gp_alt.bc),
+        "DE'" => RegisterSlot::Word(&mut registers.gp_alt.de),
+        "HL'" => RegisterSlot::Word(&mut registers.gp_alt.hl),
+        "A" => RegisterSlot::High(&mut registers.gp.af),
+        "F" => RegisterSlot::Low(&mut registers.gp.af),
+        "B" => RegisterSlot::High(&mut registers.gp.bc),
+        "C" => RegisterSlot::Low(&mut registers.gp.bc),
+        "D" => RegisterSlot::High(&mut registers.gp.de),
+        "E" => RegisterSlot::Low(&mut registers.gp.de),
+        "H" => RegisterSlot::High(&mut registers.gp.hl),
+        "L" => RegisterSlot::Low(&mut registers.gp.hl),
+        "PC" => RegisterSlot::Word(&mut registers.pc),
+        "SP" => RegisterSlot::Word(&mut registers.sp),
+        "IX" => RegisterSlot::Word(&mut registers.ix),
+        "IY" => RegisterSlot::Word(&mut registers.iy),
+        "I" => RegisterSlot::Byte(&mut registers.i),
+        "R" => RegisterSlot::Byte(&mut registers.r),
+        _ => return None,
+    })
+}
+
+fn address(value: INT) -> ScriptResult<u16> {
+    u16::try_from(value).map_err(|_| format!("address out of range: {}", value).into())
+}
+
+fn stop_name(reason: StopReason) -> ScriptResult<String> {
+    match reason {
+        StopReason::Halt => Ok("halt".to_string()),
+        StopReason::Breakpoint => Ok("breakpoint".to_string()),
+        StopReason::Error(err) => Err(format!("emulator error: {}", err).into()),
+    }
+}
+
+/// Runs `f` on the active machine without notifying the panels; the console
+/// refreshes them once the script ends.
+fn with_emu<T>(
+    emu_ctx: RwSignal<EmulatorContext>,
+    f: impl FnOnce(&mut Emulator<Z80>) -> ScriptResult<T>,
+) -> ScriptResult<T> {
+    emu_ctx
+        .try_update_untracked(|emu| f(&mut emu.emu))
+        .unwrap_or_else(|| Err("emulator is not available".into()))
+}
+
+/// Steps up to `count` instructions, stopping early at a halt or breakpoint;
+/// returns the new PC.
+fn step(
+    emu_ctx: RwSignal<EmulatorContext>,
+    observers: &RunObservers,
+    count: INT,
+) -> ScriptResult<INT> {
+    let count = count.clamp(0, MAX_STEPS);
+    with_emu(emu_ctx, |emu| {
+        observers.start();
+        let mut stopped = None;
+        for _ in 0..count {
+            let result = run_stepped(emu, 1.0, |emu, pc, cycles| {
+                observers.observe(emu, pc, cycles);
+                false
+            });
+            if let Err(reason) = result {
+                stopped = Some(reason);
+                break;
+            }
+        }
+        observers.finish();
+        if let Some(reason) = stopped {
+            stop_name(reason)?;
+        }
+        Ok(emu.cpu.registers.pc as INT)
+    })
+}
+
+fn run(
+    emu_ctx: RwSignal<EmulatorContext>,
+    observers: &RunObservers,
+    cycles: INT,
+) -> ScriptResult<String> {
+    let cycles = cycles.clamp(0, MAX_RUN_CYCLES);
+    let result = with_emu(emu_ctx, |emu| {
+        observers.start();
+        let result = run_stepped(emu, cycles as f64, |emu, pc, spent| {
+            observers.observe(emu, pc, spent);
+            false
+        });
+        observers.finish();
+        Ok(result)
+    })?;
+    match result {
+        Ok(_) => Ok("limit".to_string()),
+        Err(reason) => stop_name(reason),
+    }
+}
+
+/// Steps one instruction at a time until `condition` returns true. The
+/// emulator is released between steps so the condition can call `reg` and `peek`.
+fn run_until(
+    emu_ctx: RwSignal<EmulatorContext>,
+    observers: &RunObservers,
+    ctx: &NativeCallContext,
+    condition: &FnPtr,
+    cycles: INT,
+) -> ScriptResult<String> {
+    let cycles = cycles.clamp(0, MAX_RUN_CYCLES);
+    observers.start();
+    let result = (|| {
+        let mut elapsed = 0u64;
+        while (elapsed as INT) < cycles {
+            let result = with_emu(emu_ctx, |emu| {
+                Ok(run_stepped(emu, 1.0, |emu, pc, spent| {
+                    observers.observe(emu, pc, spent);
+                    elapsed += spent.max(1);
+                    false
+                }))
+            })?;
+            if let Err(reason) = result {
+                return stop_name(reason);
+            }
+            // The condition's own peeks are not program accesses.
+            let reached = observers
+                .tracker
+                .paused(|| condition.call_within_context::<bool>(ctx, ()))?;
+            if reached {
+                return Ok("condition".to_string());
+            }
+        }
+        Ok("limit".to_string())
+    })();
+    observers.finish();
+    result
+}
+
+/// Builds an engine whose functions act on the active machine and whose
+/// `print` goes to the console and the log.
+fn build_engine(
+    emu_ctx: RwSignal<EmulatorContext>,
+    output: RwSignal<Vec<String>>,
+    emu_cfg_ctx: RwSignal<EmulatorCfgContext>,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let deadline = Date::now() + MAX_SCRIPT_MILLIS;
+    engine.on_progress(move |_| {
+        (Date::now() > deadline).then(|| Dynamic::from("script took too long".to_string()))
+    });
+    let observers = emu_cfg_ctx.with_untracked(RunObservers::new);
+    let print = move |text: &str| {
+        output.update(|lines| {
+            lines.push(text.to_string());
+            let excess = lines.len().saturating_sub(MAX_OUTPUT_LINES);
+            lines.drain(..excess);
+        });
+        emu_cfg_ctx.update(|cfg| cfg.logstore.log_info("Script", text.to_string()));
+    };
+    engine.on_print(print);
+    engine.on_debug(move |text, _, position| print(&format!("{:?}: {}", position, text)));
+
+    engine.register_fn("reg", move |name: &str| -> ScriptResult<INT> {
+        with_emu(emu_ctx, |emu| match register_slot(emu, name) {
+            Some(slot) => Ok(slot.get() as INT),
+            None => Err(format!("unknown register: {}", name).into()),
+        })
+    });
+    engine.register_fn("set_reg", move |name: &str, value: INT| -> ScriptResult<()> {
+        with_emu(emu_ctx, |emu| match register_slot(emu, name) {
+            Some(slot) => {
+                slot.set(value as u16);
+                Ok(())
+            }
+            None => Err(format!("unknown register: {}", name).into()),
+        })
+    });
+    engine.register_fn("peek", move |addr: INT| -> ScriptResult<INT> {
+        let addr = address(addr)?;
+        with_emu(emu_ctx, |emu| {
+            emu.memory
+                .read_8(addr)
+                .map(INT::from)
+                .map_err(|err| format!("read {:#06X}: {:?}", addr, err).into())
+        })
+    });
+    engine.register_fn("peek16", move |addr: INT| -> ScriptResult<INT> {
+        let addr = address(addr)?;
+        with_emu(emu_ctx, |emu| {
+            let low = emu.memory.read_8(addr);
+            let high = emu.memory.read_8(addr.wrapping_add(1));
+            match (low, high) {
+                (Ok(low), Ok(high)) => Ok(INT::from(u16::from_le_bytes([low, high]))),
+                (Err(err), _) | (_, Err(err)) => {
+                    Err(format!("read {:#06X}: {:?}", addr, err).into())
+                }
+            }
+        })
+    });
+    engine.register_fn("poke", move |addr: INT, value: INT| -> ScriptResult<()> {
+        let addr = address(addr)?;
+        with_emu(emu_ctx, |emu| {
+            emu.memory
+                .write_8(addr, value as u8)
+                .map_err(|err| format!("write {:#06X}: {:?}", addr, err).into())
+        })
+    });
+
+    let observer = observers.clone();
+    engine.register_fn("step", move || step(emu_ctx, &observer, 1));
+    let observer = observers.clone();
+    engine.register_fn("step", move |count: INT| step(emu_ctx, &observer, count));
+
+    let observer = observers.clone();
+    engine.register_fn("run", move || run(emu_ctx, &observer, DEFAULT_RUN_CYCLES));
+    let observer = observers.clone();
+    engine.register_fn("run", move |cycles: INT| run(emu_ctx, &observer, cycles));
+    let observer = observers.clone();
+    engine.register_fn(
+        "run_until",
+        move |ctx: NativeCallContext, condition: FnPtr| {
+            run_until(emu_ctx, &observer, &ctx, &condition, DEFAULT_RUN_CYCLES)
+        },
+    );
+    engine.register_fn(
+        "run_until",
+        move |ctx: NativeCallContext, condition: FnPtr, cycles: INT| {
+            run_until(emu_ctx, &observers, &ctx, &condition, cycles)
+        },
+    );
+
+    engine.register_fn("add_breakpoint", move |addr: INT| -> ScriptResult<()> {
+        let addr = address(addr)?;
+        with_emu(emu_ctx, |emu| {
+            if !emu.breakpoints.contains(&addr) {
+                emu.breakpoints.push(addr);
+            }
+            Ok(())
+        })
+    });
+    engine.register_fn("remove_breakpoint", move |addr: INT| -> ScriptResult<()> {
+        let addr = address(addr)?;
+        with_emu(emu_ctx, |emu| {
+            emu.breakpoints.retain(|breakpoint| *breakpoint != addr);
+            Ok(())
+        })
+    });
+    engine.register_fn("clear_breakpoints", move || -> ScriptResult<()> {
+        with_emu(emu_ctx, |emu| {
+            emu.breakpoints.clear();
+            Ok(())
+        })
+    });
+    engine
+}
+
+#[island]
+pub fn ScriptConsole() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let output = RwSignal::new(Vec::<String>::new());
+    let script = move || emu_cfg_ctx.with(|cfg| cfg.editor.script.clone());
+    let run_script = move |_| {
+        if emu_cfg_ctx.with_untracked(|cfg| cfg.control.running.get_untracked()) {
+            emu_cfg_ctx.update(|cfg| {
+                cfg.logstore.log_warning(
+                    "Script not run",
+                    "Halt the emulator before running a script".to_string(),
+                )
+            });
+            return;
+        }
+        output.set(vec![]);
+        emu_ctx.update_untracked(|emu| emu.registers_before = RegisterSnapshot::capture(&emu.emu));
+        let source = emu_cfg_ctx.with_untracked(|cfg| cfg.editor.script.clone());
+        let engine = build_engine(emu_ctx, output, emu_cfg_ctx);
+        let result = engine.run(&source);
+        emu_ctx.notify();
+        match result {
+            Ok(()) => output.update(|lines| lines.push("Script finished".to_string())),
+            Err(err) => {
+                let message = err.to_string();
+                output.update(|lines| lines.push(format!("Error: {}", message)));
+                emu_cfg_ctx.update(|cfg| cfg.logstore.log_error("Script error", message));
+            }
+        }
+    };
+    view! {
+        <div class=emu_style::scriptconsole>
+            <div class=emu_style::sectop>
+                <span>Script</span>
+            </div>
+            <div class=emu_style::scripttools>
+                <input type="button" value="Run script" on:click=run_script />
+                <input type="button" value="Clear output" on:click=move |_| output.set(vec![]) />
+            </div>
+            <textarea
+                spellcheck="false"
+                placeholder=HELP
+                prop:value=script
+                on:input=move |ev| {
+                    let text = event_target_value(&ev);
+                    emu_cfg_ctx.update(|cfg| cfg.editor.script = text);
+                }
+            ></textarea>
+            <div class=emu_style::scripthelp>{HELP}</div>
+            <pre class=emu_style::scriptoutput>{move || output.get().join("\n")}</pre>
+        </div>
+    }
+}
@@ -0,0 +1,135 @@
+use super::flow::call_before;
+use super::{emu_style, EmulatorCfgContext, EmulatorContext};
+use emu_lib::cpu::z80::Z80;
+use emu_lib::emulator::Emulator;
+use emu_lib::memory::MemoryDevice;
+use leptos::prelude::*;
+
+/// Words shown in the stack table.
+const STACK_ROWS: usize = 12;
+/// Words scanned for return addresses when rebuilding the call stack.
+const CALL_SCAN_DEPTH: u16 = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+struct StackEntry {
+    address: u16,
+    value: Option<u16>,
+    /// (call site, call target) when the value looks like a return address.
+    call: Option<(u16, u16)>,
+}
+
+fn read_stack(emu: &Emulator<Z80>, depth: u16) -> Vec<StackEntry> {
+    let sp = emu.cpu.registers.sp;
+    (0..depth)
+        .map_while(|index| sp.checked_add(index * 2))
+        .map(|address| {
+            let value = emu.memory.read_16(address).ok();
+            StackEntry {
+                address,
+                value,
+                call: value.and_then(|value| call_before(&emu.memory, value)),
+            }
+        })
+        .collect()
+}
+
+#[island]
+pub fn Stack() -> impl IntoView {
+    let emu_ctx = expect_context::<RwSignal<EmulatorContext>>();
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let entries = Memo::new(move |_| emu_ctx.with(|emu| read_stack(&emu.emu, CALL_SCAN_DEPTH)));
+    let pc = Memo::new(move |_| emu_ctx.with(|emu| emu.emu.cpu.registers.pc));
+    // Best effort: the current routine, then every return address found on the stack.
+    let frames = Memo::new(move |_| {
+        emu_cfg_ctx.with(|cfg| {
+            let mut frames = vec![(cfg.symbols.describe(pc.get()), pc.get())];
+            entries.with(|entries| {
+                frames.extend(
+                    entries
+                        .iter()
+                        .filter_map(|entry| entry.call)
+                        .map(|(site, _)| (cfg.symbols.describe(site), site)),
+                );
+            });
+            frames
+        })
+    });
+    let show_disasm = move |address: u16| {
+        emu_cfg_ctx.update(|cfg| cfg.disasm_config.start = Some(address));
+    };
+    let rows = move || {
+        entries.with(|entries| {
+            entries
+                .iter()
+                .take(STACK_ROWS)
+                .cloned()
+                .map(|entry| {
+                    let caller = entry.call.map(|(site, target)| {
+                        emu_cfg_ctx.with(|cfg| {
+                            format!(
+                                "{} → {}",
+                                cfg.symbols.describe(site),
+                                cfg.symbols.describe(target)
+                            )
+                        })
+                    });
+                    let value = match entry.value {
+                        Some(value) => format!("{:04X}", value),
+                        None => "N/A".to_string(),
+                    };
+                    view! {
+                        <tr class=if entry.call.is_some() { emu_style::retaddr } else { "" }>
+                            <th>{format!("{:04X}", entry.address)}</th>
+                            <td>{value}</td>
+                            <td
+                                class=emu_style::link
+                                on:click=move |_| {
+                                    if let Some((site, _)) = entry.call {
+                                        show_disasm(site);
+                                    }
+                                }
+                            >
+                                {caller.unwrap_or_default()}
+                            </td>
+                        </tr>
+                    }
+                })
+                .collect_view()
+        })
+    };
+    let call_stack = move || {
+        frames
+            .get()
+            .into_iter()
+            .map(|(label, address)| {
+                view! {
+                    <li class=emu_style::link on:click=move |_| show_disasm(address)>
+                        {label}
+                        <span>{format!(" ({:04X})", address)}</span>
+                    </li>
+                }
+            })
+            .collect_view()
+    };
+    view! {
+        <div class=emu_style::stack>
+            <div class=emu_style::sectop>
+                <span>Stack</span>
+            </div>
+            <table>
+                <thead>
+                    <tr>
+                        <th>Address</th>
+                        <th>Value</th>
+                        <th>Call</th>
+                    </tr>
+                </thead>
+                <tbody>{rows}</tbody>
+            </table>
+            <div class=emu_style::callstack>
+                <span>Call stack</span>
+                <ol>{call_stack}</ol>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SymbolTable {
+    symbols: BTreeMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn insert(&mut self, name: String, address: u16) {
+        self.symbols.insert(name, address);
+    }
+
+    pub fn get(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &u16)> {
+        self.symbols.iter()
+    }
+
+    /// Closest symbol at or below `address`, used to name the routine it belongs to.
+    pub fn nearest(&self, address: u16) -> Option<(&str, u16)> {
+        self.symbols
+            .iter()
+            .filter(|(_, value)| **value <= address)
+            .max_by_key(|(_, value)| **value)
+            .map(|(name, value)| (name.as_str(), *value))
+    }
+
+    /// Formats an address as `symbol+offset`, falling back to plain hex.
+    pub fn describe(&self, address: u16) -> String {
+        match self.nearest(address) {
+            Some((name, value)) if value == address => name.to_string(),
+            Some((name, value)) => format!("{}+{:#X}", name, address - value),
+            None => format!("{:#06X}", address),
+        }
+    }
+
+    /// Resolves an address typed by the user: `0x1234`, `$1234` and `1234h` are hex,
+    /// plain digits are decimal. Known symbols take precedence so a label like `beach`
+    /// is not read as hex.
+    pub fn resolve(&self, expr: &str) -> Option<u16> {
+        self.get(expr.trim()).or_else(|| parse_number(expr))
+    }
+}
+
+pub fn parse_number(value: &str) -> Option<u16> {
+    let value = value.trim();
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .or_else(|| value.strip_prefix('$'))
+    {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+    if let Some(hex) = value
+        .strip_suffix('h')
+        .or_else(|| value.strip_suffix('H'))
+    {
+        if hex.chars().all(|c| c.is_ascii_hexdigit()) && !hex.is_empty() {
+            return u16::from_str_radix(hex, 16).ok();
+        }
+    }
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+        return value.parse::<u16>().ok();
+    }
+    None
+}
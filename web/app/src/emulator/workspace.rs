@@ -0,0 +1,279 @@
+use super::control::DEFAULT_FREQUENCY;
+use super::disassembler::DisassemblerContext;
+use super::editor::{EditorContext, MAIN_FILE};
+use super::instances::{main_editor, main_editor_mut};
+use super::memory::{MemDisplay, MemoryContext};
+use super::EmulatorCfgContext;
+use crate::utils::logger::DEFAULT_LOG_LIMIT;
+use leptos::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const STORAGE_KEY: &str = "emu_workspace";
+/// Bumped when a saved workspace can no longer be read back as-is.
+const WORKSPACE_VERSION: u32 = 2;
+/// Changes are gathered for this long before the workspace is written.
+const SAVE_DELAY: Duration = Duration::from_millis(1000);
+
+/// Memory panel settings that are kept; the scroll position, search
+/// selection and followed register are not.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+struct MemoryLayout {
+    width: u16,
+    height: u16,
+    display: MemDisplay,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        MemoryLayout::capture(&MemoryContext::default())
+    }
+}
+
+impl MemoryLayout {
+    fn capture(memory: &MemoryContext) -> Self {
+        MemoryLayout {
+            width: memory.width,
+            height: memory.height,
+            display: memory.display,
+        }
+    }
+}
+
+/// Disassembler panel settings that are kept; the scroll position is not.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+struct DisassemblerLayout {
+    rows: u16,
+}
+
+impl Default for DisassemblerLayout {
+    fn default() -> Self {
+        DisassemblerLayout {
+            rows: DisassemblerContext::default().rows,
+        }
+    }
+}
+
+/// The parts of the emulator page that survive a reload: the main instance's
+/// editor buffers and the panel settings. Machine state is not included; use
+/// Save for memory.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Workspace {
+    version: u32,
+    editor: EditorContext,
+    memory: MemoryLayout,
+    disassembler: DisassemblerLayout,
+    target_frequency: usize,
+    log_limit: usize,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Workspace {
+            version: WORKSPACE_VERSION,
+            editor: EditorContext::default(),
+            memory: MemoryLayout::default(),
+            disassembler: DisassemblerLayout::default(),
+            target_frequency: DEFAULT_FREQUENCY,
+            log_limit: DEFAULT_LOG_LIMIT,
+        }
+    }
+}
+
+impl Workspace {
+    fn capture(cfg: &EmulatorCfgContext) -> Self {
+        Workspace {
+            version: WORKSPACE_VERSION,
+            editor: main_editor(cfg).clone(),
+            memory: MemoryLayout::capture(&cfg.mem_config),
+            disassembler: DisassemblerLayout {
+                rows: cfg.disasm_config.rows,
+            },
+            target_frequency: cfg.control.target_frequency.get_untracked(),
+            log_limit: cfg.logstore.limit(),
+        }
+    }
+
+    fn apply(self, cfg: &mut EmulatorCfgContext) {
+        *main_editor_mut(cfg) = self.editor;
+        cfg.mem_config.width = self.memory.width;
+        cfg.mem_config.height = self.memory.height;
+        cfg.mem_config.display = self.memory.display;
+        cfg.disasm_config.rows = self.disassembler.rows;
+        cfg.control.target_frequency.set(self.target_frequency);
+        cfg.logstore.set_limit(self.log_limit);
+    }
+}
+
+/// Brings a workspace saved by an older version up to the current layout.
+fn migrate(value: &mut serde_json::Value) {
+    let version = value.get("version").and_then(|version| version.as_u64());
+    // Version 1 had a single C buffer instead of a project.
+    if version == Some(1) {
+        if let Some(editor) = value.get_mut("editor").and_then(|editor| editor.as_object_mut()) {
+            let code = editor.remove("c_buffer").unwrap_or_else(|| serde_json::json!(""));
+            editor.insert(
+                "c_files".to_string(),
+                serde_json::json!([{ "name": MAIN_FILE, "code": code }]),
+            );
+            editor.insert("c_active".to_string(), serde_json::json!(0));
+        }
+        value["version"] = serde_json::json!(2);
+    }
+}
+
+fn load_local() -> Result<Option<Workspace>, String> {
+    let Some(storage) = window().local_storage().ok().flatten() else {
+        return Ok(None);
+    };
+    let Some(json) = storage.get_item(STORAGE_KEY).ok().flatten() else {
+        return Ok(None);
+    };
+    let mut value: serde_json::Value =
+        serde_json::from_str(&json).map_err(|err| err.to_string())?;
+    migrate(&mut value);
+    let workspace: Workspace = serde_json::from_value(value).map_err(|err| err.to_string())?;
+    if workspace.version != WORKSPACE_VERSION {
+        return Err(format!("unsupported workspace version {}", workspace.version));
+    }
+    Ok(Some(workspace))
+}
+
+fn save_local(json: &str) {
+    if let Some(storage) = window().local_storage().ok().flatten() {
+        let _ = storage.set_item(STORAGE_KEY, json);
+    }
+}
+
+fn clear_local() {
+    if let Some(storage) = window().local_storage().ok().flatten() {
+        let _ = storage.remove_item(STORAGE_KEY);
+    }
+}
+
+/// Restores the saved workspace once the page is interactive, then saves it
+/// again shortly after the page state changes, when a persisted part of it
+/// differs from what was last written.
+pub fn persist_workspace(emu_cfg_ctx: RwSignal<EmulatorCfgContext>) {
+    let pending = StoredValue::new(false);
+    let written = StoredValue::new(None::<String>);
+    let save = move || {
+        pending.set_value(false);
+        let json =
+            emu_cfg_ctx.with_untracked(|cfg| serde_json::to_string(&Workspace::capture(cfg)));
+        let Ok(json) = json else {
+            return;
+        };
+        if written.with_value(|written| written.as_deref() != Some(json.as_str())) {
+            save_local(&json);
+            written.set_value(Some(json));
+        }
+    };
+    Effect::new(move |restored: Option<()>| {
+        if restored.is_some() {
+            emu_cfg_ctx.with(|cfg| cfg.control.target_frequency.track());
+            // The page state changes many times a second while running, so
+            // saves are spaced out rather than pushed back by each change.
+            if !pending.get_value() {
+                pending.set_value(true);
+                set_timeout(save, SAVE_DELAY);
+            }
+            return;
+        }
+        match load_local() {
+            Ok(Some(saved)) => emu_cfg_ctx.update(|cfg| {
+                saved.apply(cfg);
+                cfg.logstore
+                    .log_info("Workspace restored", "Workspace restored".to_string());
+            }),
+            Ok(None) => {}
+            Err(err) => emu_cfg_ctx.update(|cfg| {
+                cfg.logstore.log_warning(
+                    "Workspace not restored",
+                    format!("Saved workspace could not be read: {}", err),
+                )
+            }),
+        }
+        emu_cfg_ctx.with(|cfg| cfg.control.target_frequency.track());
+    });
+}
+
+#[island]
+pub fn ResetWorkspaceButton() -> impl IntoView {
+    let emu_cfg_ctx = expect_context::<RwSignal<EmulatorCfgContext>>();
+    let on_reset = move |_| {
+        let confirmed = window()
+            .confirm_with_message("Discard the editor buffers and panel settings?")
+            .unwrap_or(false);
+        if !confirmed {
+            return;
+        }
+        clear_local();
+        emu_cfg_ctx.update(|cfg| {
+            Workspace::default().apply(cfg);
+            cfg.logstore
+                .log_info("Workspace reset", "Workspace reset to defaults".to_string());
+        });
+    };
+    view! { <input type="button" value="Reset workspace" on:click=on_reset /> }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::display::DisplayMemoryDevice;
+    use crate::emulator::heatmap::AccessTracker;
+    use crate::emulator::instances::{add_instance, switch_instance};
+    use crate::emulator::{EmulatorContext, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+    fn stored(cfg: &EmulatorCfgContext) -> Workspace {
+        let json = serde_json::to_string(&Workspace::capture(cfg)).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn keeps_the_main_instance_buffers_while_another_is_active() {
+        let display = DisplayMemoryDevice::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        let mut emu = EmulatorContext::new(display, AccessTracker::default());
+        let mut cfg = EmulatorCfgContext::new(display);
+        cfg.editor.asm_buffer = "ld a, 1".to_string();
+
+        add_instance(&mut emu, &mut cfg, "Other".to_string());
+        cfg.editor.asm_buffer = "ld a, 2".to_string();
+        assert_eq!(stored(&cfg).editor.asm_buffer, "ld a, 1");
+
+        switch_instance(&mut emu, &mut cfg, 0);
+        assert_eq!(stored(&cfg).editor.asm_buffer, "ld a, 1");
+        switch_instance(&mut emu, &mut cfg, 1);
+        assert_eq!(cfg.editor.asm_buffer, "ld a, 2");
+    }
+
+    #[test]
+    fn restoring_while_another_instance_is_active_fills_the_main_one() {
+        let display = DisplayMemoryDevice::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        let mut emu = EmulatorContext::new(display, AccessTracker::default());
+        let mut cfg = EmulatorCfgContext::new(display);
+        add_instance(&mut emu, &mut cfg, "Other".to_string());
+        let mut workspace = Workspace::default();
+        workspace.editor.asm_buffer = "halt".to_string();
+
+        workspace.apply(&mut cfg);
+        assert_eq!(cfg.editor.asm_buffer, "");
+        switch_instance(&mut emu, &mut cfg, 0);
+        assert_eq!(cfg.editor.asm_buffer, "halt");
+    }
+
+    #[test]
+    fn scrolling_does_not_change_the_stored_workspace() {
+        let display = DisplayMemoryDevice::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        let mut cfg = EmulatorCfgContext::new(display);
+        let before = stored(&cfg);
+        cfg.mem_config.goto(0x1234);
+        cfg.mem_config.selection = Some((0x1234, 2));
+        cfg.disasm_config.start = Some(0x100);
+        assert_eq!(stored(&cfg), before);
+    }
+}
@@ -1,8 +1,6 @@
-use std::sync::LazyLock;
-use base64::Engine;
 use leptos::prelude::*;
 use serde::{Deserialize, Serialize};
-use server_fn::codec::JsonEncoding;
+use server_fn::codec::{JsonEncoding, StreamingText, TextStream};
 use server_fn::error::ServerFnErrorEncoding;
 use thiserror::Error;
 
@@ -10,12 +8,30 @@ use thiserror::Error;
 pub enum CompilerError {
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Forbidden")]
+    Forbidden,
     #[error("Error sending request: {0}")]
     RequestError(String),
     #[error("Error decoding response: {0}")]
     DecodeError(String),
     #[error("Server fn error: {0}")]
     ServerFnError(ServerFnErrorErr),
+    #[error("Invalid compile options: {0}")]
+    InvalidOptions(String),
+    #[error("Request too large: {0}")]
+    TooLarge(String),
+    #[error("Too many requests, try again in {0}s")]
+    RateLimited(u64),
+    #[error("The compiler took too long")]
+    Timeout,
+    #[error("The compiler ran out of resources: {0}")]
+    ResourceExhausted(String),
+    #[error("The compiler is busy, try again")]
+    Busy,
+    #[error("Rejected by the compiler: {0}")]
+    Rejected(String),
+    #[error("Compilation cancelled")]
+    Cancelled,
 }
 
 impl FromServerFnError for CompilerError {
@@ -28,6 +44,8 @@ impl FromServerFnError for CompilerError {
 #[cfg(not(target_arch = "wasm32"))]
 mod server_imports {
     pub use crate::db::AppState;
+    pub use crate::utils::compile_cache::CompileCache;
+    pub use crate::utils::compiler_client::CompilerClient;
     pub use crate::utils::cookie::{self, CookieKey};
     pub use axum::Extension;
     pub use http::StatusCode;
@@ -35,214 +53,523 @@ mod server_imports {
     pub use leptos_axum::ResponseOptions;
 }
 
-static COMPILER_HOST: LazyLock <String> = LazyLock::new( | | std::env::var("COMPILER_HOST").expect("No COMPILER_HOST set"));
+/// Most files a project may send.
+pub const MAX_FILES: usize = 32;
+/// Most bytes of source a single request may send, across all files.
+pub const MAX_SOURCE_BYTES: usize = 256 * 1024;
+/// Compile and format requests a user may make per `RATE_WINDOW`.
+pub const COMPILE_RATE_LIMIT: usize = 20;
+/// Syntax checks a user may make per `RATE_WINDOW`.
+pub const SYNTAX_CHECK_RATE_LIMIT: usize = 60;
+pub const RATE_WINDOW: std::time::Duration = std::time::Duration::from_secs(60);
+/// Most bytes of compile results kept in the server's cache.
+pub const COMPILE_CACHE_BYTES: usize = 64 * 1024 * 1024;
 
-#[derive(Serialize, Deserialize)]
-struct RequestBody {
-    b64data: String,
+#[cfg(not(target_arch = "wasm32"))]
+fn check_size<'a>(sources: impl IntoIterator<Item = &'a str>) -> Result<(), CompilerError> {
+    let (count, bytes) = sources.into_iter().fold((0, 0), |(count, bytes), source| {
+        (count + 1, bytes + source.len())
+    });
+    if count > MAX_FILES {
+        return Err(CompilerError::TooLarge(format!(
+            "{} files, at most {} allowed",
+            count, MAX_FILES
+        )));
+    }
+    if bytes > MAX_SOURCE_BYTES {
+        return Err(CompilerError::TooLarge(format!(
+            "{} bytes of source, at most {} allowed",
+            bytes, MAX_SOURCE_BYTES
+        )));
+    }
+    Ok(())
+}
+
+/// The signed in caller, setting the response status when there is none.
+#[cfg(not(target_arch = "wasm32"))]
+async fn signed_in() -> Result<crate::db::models::user::UserData, CompilerError> {
+    use crate::db::models::user::UserData;
+    use server_imports::*;
+    let userdata: Result<Extension<UserData>, _> = extract().await;
+    userdata.map(|Extension(user)| user).map_err(|_| {
+        expect_context::<ResponseOptions>().set_status(StatusCode::UNAUTHORIZED);
+        CompilerError::Unauthorized
+    })
+}
+
+/// Checks the caller is signed in and within `limiter`'s rate, setting the
+/// response status when not, and returns the caller.
+#[cfg(not(target_arch = "wasm32"))]
+async fn authorize(
+    limiter: fn(&server_imports::AppState) -> &crate::utils::rate_limit::RateLimiter,
+) -> Result<crate::db::models::user::UserData, CompilerError> {
+    use server_imports::*;
+    let state = expect_context::<AppState>();
+    let response = expect_context::<ResponseOptions>();
+    let user = signed_in().await?;
+    limiter(&state).check(user.id).map_err(|wait| {
+        response.set_status(StatusCode::TOO_MANY_REQUESTS);
+        CompilerError::RateLimited(wait.as_secs().max(1))
+    })?;
+    Ok(user)
+}
+
+/// Checks the caller is signed in as an admin, setting the response status when not.
+#[cfg(not(target_arch = "wasm32"))]
+async fn authorize_admin() -> Result<(), CompilerError> {
+    use crate::db::models::user::UserType;
+    use server_imports::*;
+    if signed_in().await?.user_type != UserType::Admin {
+        expect_context::<ResponseOptions>().set_status(StatusCode::FORBIDDEN);
+        return Err(CompilerError::Forbidden);
+    }
+    Ok(())
+}
+
+/// Version of the service's toolchain, for cache keys. Asked for again once the
+/// cache's copy is stale.
+#[cfg(not(target_arch = "wasm32"))]
+async fn toolchain_version() -> Result<String, CompilerError> {
+    use server_imports::*;
+    let state = expect_context::<AppState>();
+    if let Some(version) = state.compile_cache.toolchain() {
+        return Ok(version);
+    }
+    let version = state.compiler.version().await?;
+    state.compile_cache.set_toolchain(version.clone());
+    Ok(version)
+}
+
+/// Cache key for a compile, or `None` when the toolchain version is unknown and
+/// results can't be told apart.
+#[cfg(not(target_arch = "wasm32"))]
+async fn cache_key(files: &[SourceFile], options: &CompileOptions) -> Option<String> {
+    let version = toolchain_version().await.ok()?;
+    Some(server_imports::CompileCache::key(&version, files, options))
+}
+
+/// One source or header file of a C project, named relative to the project root.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SourceFile {
+    pub name: String,
+    pub code: String,
+}
+
+/// Headers bundled with the compiler service, always on the include path.
+pub const BUNDLED_HEADERS: [&str; 1] = ["emu.h"];
+
+impl SourceFile {
+    pub fn new(name: &str, code: String) -> Self {
+        SourceFile {
+            name: name.to_string(),
+            code,
+        }
+    }
+
+    pub fn is_header(&self) -> bool {
+        self.name.ends_with(".h")
+    }
+
+    /// Plain `.c` or `.h` names without directories; the bundled library's
+    /// names are taken.
+    pub fn valid_name(name: &str) -> bool {
+        let Some(stem) = name.strip_suffix(".c").or_else(|| name.strip_suffix(".h")) else {
+            return false;
+        };
+        !stem.is_empty()
+            && !stem.starts_with(['.', '-'])
+            && stem
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            && !BUNDLED_HEADERS.contains(&name)
+            && name != "emu.c"
+    }
+}
+
+/// Most `-D` defines a compile may pass.
+pub const MAX_DEFINES: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Sccz80,
+    Sdcc,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::Sccz80, Backend::Sdcc];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Sccz80 => "sccz80",
+            Backend::Sdcc => "sdcc",
+        }
+    }
+}
+
+/// C runtime the program is linked with.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Crt {
+    #[default]
+    Startup0,
+    Startup31,
+    /// No startup code; `main` is entered at the origin.
+    None,
+}
+
+impl Crt {
+    pub const ALL: [Crt; 3] = [Crt::Startup0, Crt::Startup31, Crt::None];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Crt::Startup0 => "startup=0",
+            Crt::Startup31 => "startup=31",
+            Crt::None => "no CRT",
+        }
+    }
+}
+
+/// A preprocessor define, `NAME` or `NAME=VALUE`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Define {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl Define {
+    pub fn parse(define: &str) -> Result<Self, String> {
+        let (name, value) = match define.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (define, None),
+        };
+        let define = Define {
+            name: name.to_string(),
+            value,
+        };
+        define.validate()?;
+        Ok(define)
+    }
+
+    /// Whitespace separated defines, as typed in the options panel.
+    pub fn parse_list(defines: &str) -> Result<Vec<Self>, String> {
+        defines.split_whitespace().map(Define::parse).collect()
+    }
+
+    pub fn format_list(defines: &[Define]) -> String {
+        defines
+            .iter()
+            .map(|define| match &define.value {
+                Some(value) => format!("{}={}", define.name, value),
+                None => define.name.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Identifiers for names; values are limited to characters that cannot
+    /// change the meaning of the compiler's command line.
+    pub fn validate(&self) -> Result<(), String> {
+        let name_ok = self.name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !name_ok {
+            return Err(format!("invalid define name \"{}\"", self.name));
+        }
+        let value_ok = self.value.as_ref().is_none_or(|value| {
+            value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '+' | '-'))
+        });
+        if !value_ok {
+            return Err(format!("invalid value for define {}", self.name));
+        }
+        Ok(())
+    }
 }
 
-impl RequestBody {
-    pub fn new(code: String) -> Self {
-        let b64data = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &code);
-        Self { b64data }
+/// Compiler settings for a C project, saved with it. The compiler service only
+/// turns these into flags from its own whitelist.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct CompileOptions {
+    /// `-O0` to `-O3`.
+    pub optimization: u8,
+    pub backend: Backend,
+    /// Address the code is linked at, and loaded at in the emulator.
+    pub origin: u16,
+    pub crt: Crt,
+    /// Link the math library.
+    pub math: bool,
+    /// Also return the assembler listing and linker map.
+    pub listing: bool,
+    pub defines: Vec<Define>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            optimization: 3,
+            backend: Backend::default(),
+            origin: 0,
+            crt: Crt::default(),
+            math: true,
+            listing: false,
+            defines: vec![],
+        }
     }
 }
-#[derive(Serialize, Deserialize)]
-struct EncCompileData {
-    rc: i32,
-    b64stdout: String,
-    b64stderr: String,
-    b64data: String,
+
+impl CompileOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.optimization > 3 {
+            return Err(format!("optimization level {} is not 0-3", self.optimization));
+        }
+        if self.defines.len() > MAX_DEFINES {
+            return Err(format!("more than {} defines", MAX_DEFINES));
+        }
+        self.defines.iter().try_for_each(Define::validate)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CompileData {
     pub rc: i32,
     pub stdout: String,
     pub stderr: String,
     pub data: Vec<u8>,
+    /// Assembler listings of every module, empty unless `CompileOptions::listing`.
+    pub listing: String,
+    /// Linker map with symbol addresses, empty unless `CompileOptions::listing`.
+    pub map: String,
+    /// Served from the server's cache instead of a new build.
+    #[serde(default)]
+    pub cached: bool,
 }
 
-impl EncCompileData {
-    pub fn decode(&self) -> Result<CompileData, CompilerError> {
-        let decode_str = |data: &str| {
-            let decoded = base64::engine::general_purpose::STANDARD
-                .decode(data)
-                .map_err(|e| format!("Failed to decode base64 data: {}", e))?;
-            String::from_utf8(decoded)
-                .map_err(|e| format!("Failed to convert decoded data to string: {}", e))
-        };
-        let stdout = decode_str(&self.b64stdout).map_err(|e| {
-            CompilerError::DecodeError(format!("Failed to decode base64 data: {}", e).to_string())
-        })?;
-        let stderr = decode_str(&self.b64stderr).map_err(|e| {
-            CompilerError::DecodeError(format!("Failed to decode stderr: {}", e).to_string())
-        })?;
-        let data = base64::engine::general_purpose::STANDARD
-            .decode(&self.b64data)
-            .map_err(|e| {
-                CompilerError::DecodeError(format!("Failed to decode base64 data: {}", e))
-            })?;
-        Ok(CompileData {
-            rc: self.rc,
-            stdout,
-            stderr,
-            data,
-        })
-    }
+/// Stage of a streamed compile.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompilePhase {
+    /// Waiting for a free compiler worker.
+    Queued,
+    Compiling,
+    Linking,
 }
-#[derive(Serialize, Deserialize)]
-pub struct EncFormatData {
-    b64data: String,
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FormatData {
-    pub data: String,
+/// One step of a streamed compile, sent as a line of JSON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CompileEvent {
+    /// The job to pass to `c_compile_cancel`.
+    Started {
+        job: String,
+    },
+    Phase {
+        phase: CompilePhase,
+        /// Source being compiled, in the `Compiling` phase.
+        file: Option<String>,
+    },
+    Output {
+        stream: OutputStream,
+        line: String,
+    },
+    Done {
+        data: CompileData,
+    },
+    Failed {
+        error: CompilerError,
+    },
 }
 
-impl EncFormatData {
-    pub fn decode(&self) -> Result<FormatData, CompilerError> {
-        let data = base64::engine::general_purpose::STANDARD
-            .decode(&self.b64data)
-            .map_err(|e| {
-                CompilerError::DecodeError(format!("Failed to decode base64 data: {}", e))
-            })?;
-        let data = String::from_utf8(data).map_err(|e| {
-            CompilerError::DecodeError(format!("Failed to convert decoded data to string: {}", e))
-        })?;
-        Ok(FormatData { data })
+impl CompileEvent {
+    /// The event as a line of JSON. Non-ASCII characters are escaped so a chunk
+    /// boundary can never split one.
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_string(self).unwrap();
+        let mut line = String::with_capacity(json.len() + 1);
+        for c in json.chars() {
+            if c.is_ascii() {
+                line.push(c);
+            } else {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    line.push_str(&format!("\\u{:04x}", unit));
+                }
+            }
+        }
+        line.push('\n');
+        line
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct EncSyntaxCheckData {
-    pub rc: i32,
-    pub b64stderr: String,
+/// Splits the chunks of a compile stream back into events.
+#[derive(Default)]
+pub struct CompileEventReader {
+    pending: String,
+}
+
+impl CompileEventReader {
+    /// Events completed by `chunk`; a partial line waits for the next chunk.
+    pub fn push(&mut self, chunk: &str) -> Result<Vec<CompileEvent>, CompilerError> {
+        self.pending.push_str(chunk);
+        let Some(end) = self.pending.rfind('\n') else {
+            return Ok(vec![]);
+        };
+        let lines: String = self.pending.drain(..=end).collect();
+        lines
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|err| CompilerError::DecodeError(err.to_string()))
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FormatData {
+    pub data: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SyntaxCheckData {
     pub rc: i32,
     pub stderr: String,
 }
 
-impl EncSyntaxCheckData {
-    pub fn decode(&self) -> Result<SyntaxCheckData, CompilerError> {
-        let stderr = base64::engine::general_purpose::STANDARD
-            .decode(&self.b64stderr)
-            .map_err(|e| {
-                CompilerError::DecodeError(format!("Failed to decode base64 data: {}", e))
-            })?;
-        let stderr = String::from_utf8(stderr).map_err(|e| {
-            CompilerError::DecodeError(format!("Failed to convert decoded data to string: {}", e))
-        })?;
-        Ok(SyntaxCheckData {
-            rc: self.rc,
-            stderr,
+#[server(CCompile, endpoint = "/ccompile")]
+pub async fn c_compile(
+    files: Vec<SourceFile>,
+    options: CompileOptions,
+) -> Result<CompileData, CompilerError> {
+    options.validate().map_err(CompilerError::InvalidOptions)?;
+    check_size(files.iter().map(|file| file.code.as_str()))?;
+    authorize(|state| state.compile_limiter.as_ref()).await?;
+    let state = expect_context::<server_imports::AppState>();
+    let key = cache_key(&files, &options).await;
+    if let Some(mut data) = key.as_ref().and_then(|key| state.compile_cache.get(key)) {
+        data.cached = true;
+        return Ok(data);
+    }
+    let data = state.compiler.compile(files, options).await?;
+    if let Some(key) = key {
+        state.compile_cache.insert(key, data.clone());
+    }
+    Ok(data)
+}
+
+/// Compiles like `c_compile`, streaming the build's phases and output lines as
+/// they happen and ending with its result. The first event names the job for
+/// `c_compile_cancel`; a cached result replays its output instead.
+#[server(CCompileStream, endpoint = "/ccompile_stream", output = StreamingText)]
+pub async fn c_compile_stream(
+    files: Vec<SourceFile>,
+    options: CompileOptions,
+) -> Result<TextStream<CompilerError>, CompilerError> {
+    use futures::StreamExt;
+    options.validate().map_err(CompilerError::InvalidOptions)?;
+    check_size(files.iter().map(|file| file.code.as_str()))?;
+    let user = authorize(|state| state.compile_limiter.as_ref()).await?;
+    let state = expect_context::<server_imports::AppState>();
+    let key = cache_key(&files, &options).await;
+    if let Some(mut data) = key.as_ref().and_then(|key| state.compile_cache.get(key)) {
+        data.cached = true;
+        let output = [
+            (OutputStream::Stdout, &data.stdout),
+            (OutputStream::Stderr, &data.stderr),
+        ]
+        .into_iter()
+        .flat_map(|(stream, text)| {
+            text.lines().map(move |line| CompileEvent::Output {
+                stream,
+                line: line.to_string(),
+            })
         })
+        .collect::<Vec<_>>();
+        let events = output.into_iter().chain([CompileEvent::Done { data }]);
+        return Ok(TextStream::new(futures::stream::iter(
+            events.map(|event| Ok(event.encode())),
+        )));
     }
+    let events = state.compiler.compile_stream(files, options).await?;
+    let cache = state.compile_cache.clone();
+    let jobs = state.compile_jobs.clone();
+    let mut started = None;
+    let events = events.inspect(move |event| match event {
+        CompileEvent::Started { job } => {
+            jobs.start(job, user.id);
+            started = Some(job.clone());
+        }
+        CompileEvent::Done { .. } | CompileEvent::Failed { .. } => {
+            if let CompileEvent::Done { data } = event {
+                if let Some(key) = &key {
+                    cache.insert(key.clone(), data.clone());
+                }
+            }
+            if let Some(job) = started.take() {
+                jobs.finish(&job);
+            }
+        }
+        _ => {}
+    });
+    Ok(TextStream::new(events.map(|event| Ok(event.encode()))))
 }
 
-#[server(CCompile, endpoint = "/ccompile")]
-pub async fn c_compile(code: String) -> Result<CompileData, CompilerError> {
-    use crate::db::models::user::UserData;
+/// Stops a job the caller started with `c_compile_stream`; its stream ends with
+/// `Cancelled`.
+#[server(CCompileCancel, endpoint = "/ccompile_cancel")]
+pub async fn c_compile_cancel(job: String) -> Result<(), CompilerError> {
     use server_imports::*;
-    //encode code in b64
-    let data = RequestBody::new(code);
+    let user = signed_in().await?;
+    if job.is_empty() || !job.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(CompilerError::Rejected(format!("invalid job \"{}\"", job)));
+    }
     let state = expect_context::<AppState>();
-    let response = expect_context::<ResponseOptions>();
-    let userdata: Result<Extension<UserData>, _> = extract().await;
-    match userdata {
-        Ok(_) => {
-            let reqwest_client = &state.reqwest_client;
-            let response = reqwest_client
-                .post(format!("http://{}/compile",*COMPILER_HOST))
-                .header(
-                    "Content-Length",
-                    serde_json::to_string(&data).unwrap().len(),
-                )
-                .json(&data)
-                .send()
-                .await
-                .map_err(|e| CompilerError::RequestError(e.to_string()))?
-                .json::<EncCompileData>()
-                .await
-                .map_err(|e| CompilerError::RequestError(e.to_string()))?;
-            response.decode()
-        }
-        Err(_) => {
-            response.set_status(StatusCode::UNAUTHORIZED);
-            Err(CompilerError::Unauthorized)
-        }
+    // Unknown and finished jobs are refused the same way as other users' jobs.
+    if state.compile_jobs.owner(&job) != Some(user.id) {
+        expect_context::<ResponseOptions>().set_status(StatusCode::FORBIDDEN);
+        return Err(CompilerError::Forbidden);
     }
+    state.compiler.cancel(job).await
+}
+
+/// Empties the compile cache, returning how many results were dropped. Admins only.
+#[server(FlushCompileCache, endpoint = "/ccompile_cache/flush")]
+pub async fn flush_compile_cache() -> Result<usize, CompilerError> {
+    authorize_admin().await?;
+    let state = expect_context::<server_imports::AppState>();
+    Ok(state.compile_cache.flush())
 }
 
 #[server(CFormat, endpoint = "/cformat")]
 pub async fn c_format(code: String) -> Result<FormatData, CompilerError> {
-    use crate::db::models::user::UserData;
-    use server_imports::*;
-    //encode code in b64
-    let data = RequestBody::new(code);
-    let state = expect_context::<AppState>();
-    let response = expect_context::<ResponseOptions>();
-    let userdata: Result<Extension<UserData>, _> = extract().await;
-    match userdata {
-        Ok(_) => {
-            let reqwest_client = &state.reqwest_client;
-            let response = reqwest_client
-                .post(format!("http://{}/format",*COMPILER_HOST))
-                .header(
-                    "Content-Length",
-                    serde_json::to_string(&data).unwrap().len(),
-                )
-                .json(&data)
-                .send()
-                .await
-                .map_err(|e| CompilerError::RequestError(e.to_string()))?
-                .json::<EncFormatData>()
-                .await
-                .map_err(|e| CompilerError::RequestError(e.to_string()))?;
-            response.decode()
-        }
-        Err(_) => {
-            response.set_status(StatusCode::UNAUTHORIZED);
-            Err(CompilerError::Unauthorized)
-        }
-    }
+    check_size([code.as_str()])?;
+    authorize(|state| state.compile_limiter.as_ref()).await?;
+    let state = expect_context::<server_imports::AppState>();
+    state.compiler.format(code).await
 }
 
+/// Checks a project for errors without building it. Only the defines of
+/// `options` apply, so code that depends on them is checked as it would build.
 #[server(CSyntaxCheck, endpoint = "/csyntax_check")]
-pub async fn c_syntax_check(code: String) -> Result<SyntaxCheckData, CompilerError> {
-    use crate::db::models::user::UserData;
-    use server_imports::*;
-    //encode code in b64
-    let data = RequestBody::new(code);
-    let state = expect_context::<AppState>();
-    let response = expect_context::<ResponseOptions>();
-    let userdata: Result<Extension<UserData>, _> = extract().await;
-    match userdata {
-        Ok(_) => {
-            let reqwest_client = &state.reqwest_client;
-            let response = reqwest_client
-                .post(format!("http://{}/syntax_check",*COMPILER_HOST))
-                .header(
-                    "Content-Length",
-                    serde_json::to_string(&data).unwrap().len(),
-                )
-                .json(&data)
-                .send()
-                .await
-                .map_err(|e| CompilerError::RequestError(e.to_string()))?
-                .json::<EncSyntaxCheckData>()
-                .await
-                .map_err(|e| CompilerError::RequestError(e.to_string()))?;
-            response.decode()
-        }
-        Err(_) => {
-            response.set_status(StatusCode::UNAUTHORIZED);
-            Err(CompilerError::Unauthorized)
-        }
-    }
+pub async fn c_syntax_check(
+    files: Vec<SourceFile>,
+    options: CompileOptions,
+) -> Result<SyntaxCheckData, CompilerError> {
+    options.validate().map_err(CompilerError::InvalidOptions)?;
+    check_size(files.iter().map(|file| file.code.as_str()))?;
+    authorize(|state| state.syntax_check_limiter.as_ref()).await?;
+    let state = expect_context::<server_imports::AppState>();
+    state.compiler.syntax_check(files, options).await
 }
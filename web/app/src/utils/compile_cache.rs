@@ -0,0 +1,99 @@
+use crate::utils::ccompiler::{CompileData, CompileOptions, SourceFile};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long the service's toolchain version is trusted before it is asked again.
+const TOOLCHAIN_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Default)]
+struct Entries {
+    results: HashMap<String, CompileData>,
+    /// Keys from least to most recently used.
+    order: VecDeque<String>,
+    bytes: usize,
+}
+
+/// Compile results by a hash of the sources, options and toolchain version,
+/// so unchanged programs are not rebuilt. Least recently used results are
+/// dropped once they take up more than `max_bytes`.
+pub struct CompileCache {
+    max_bytes: usize,
+    entries: Mutex<Entries>,
+    toolchain: Mutex<Option<(String, Instant)>>,
+}
+
+fn size(data: &CompileData) -> usize {
+    data.data.len() + data.stdout.len() + data.stderr.len() + data.listing.len() + data.map.len()
+}
+
+impl CompileCache {
+    pub fn new(max_bytes: usize) -> Self {
+        CompileCache {
+            max_bytes,
+            entries: Mutex::new(Entries::default()),
+            toolchain: Mutex::new(None),
+        }
+    }
+
+    pub fn key(toolchain: &str, files: &[SourceFile], options: &CompileOptions) -> String {
+        let request = serde_json::to_vec(&(toolchain, files, options)).unwrap();
+        format!("{:x}", Sha256::digest(request))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CompileData> {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        let data = entries.results.get(key).cloned()?;
+        if let Some(position) = entries.order.iter().position(|other| other == key) {
+            let key = entries.order.remove(position).unwrap();
+            entries.order.push_back(key);
+        }
+        Some(data)
+    }
+
+    pub fn insert(&self, key: String, data: CompileData) {
+        let data_size = size(&data);
+        if data_size > self.max_bytes {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(old) = entries.results.insert(key.clone(), data) {
+            entries.bytes -= size(&old);
+            entries.order.retain(|other| *other != key);
+        }
+        entries.bytes += data_size;
+        entries.order.push_back(key);
+        while entries.bytes > self.max_bytes {
+            let Some(oldest) = entries.order.pop_front() else {
+                break;
+            };
+            if let Some(old) = entries.results.remove(&oldest) {
+                entries.bytes -= size(&old);
+            }
+        }
+    }
+
+    /// Drops every result, returning how many there were.
+    pub fn flush(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap_or_else(|err| err.into_inner());
+        let count = entries.results.len();
+        *entries = Entries::default();
+        *self.toolchain.lock().unwrap_or_else(|err| err.into_inner()) = None;
+        count
+    }
+
+    /// The toolchain version last seen, unless it is due to be asked again.
+    pub fn toolchain(&self) -> Option<String> {
+        let toolchain = self.toolchain.lock().unwrap_or_else(|err| err.into_inner());
+        toolchain
+            .as_ref()
+            .filter(|(_, fetched)| fetched.elapsed() < TOOLCHAIN_TTL)
+            .map(|(version, _)| version.clone())
+    }
+
+    pub fn set_toolchain(&self, version: String) {
+        *self.toolchain.lock().unwrap_or_else(|err| err.into_inner()) =
+            Some((version, Instant::now()));
+    }
+}
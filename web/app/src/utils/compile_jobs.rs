@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Jobs tracked before old ones are dropped.
+const PRUNE_ABOVE: usize = 1024;
+/// How long a job is remembered if its stream never finishes; longer than the
+/// service lets a compile run.
+const JOB_TTL: Duration = Duration::from_secs(600);
+
+/// Which user started each streamed compile, so only they can cancel it.
+#[derive(Default)]
+pub struct CompileJobs {
+    owners: Mutex<HashMap<String, (i32, Instant)>>,
+}
+
+impl CompileJobs {
+    pub fn new() -> Self {
+        CompileJobs::default()
+    }
+
+    /// Records `user` as the owner of `job`.
+    pub fn start(&self, job: &str, user: i32) {
+        let now = Instant::now();
+        let mut owners = self.owners.lock().unwrap_or_else(|err| err.into_inner());
+        if owners.len() > PRUNE_ABOVE {
+            owners.retain(|_, (_, started)| now.duration_since(*started) < JOB_TTL);
+        }
+        owners.insert(job.to_string(), (user, now));
+    }
+
+    /// Forgets `job` once its stream has ended.
+    pub fn finish(&self, job: &str) {
+        let mut owners = self.owners.lock().unwrap_or_else(|err| err.into_inner());
+        owners.remove(job);
+    }
+
+    /// The user who started `job`, if it's still running.
+    pub fn owner(&self, job: &str) -> Option<i32> {
+        let owners = self.owners.lock().unwrap_or_else(|err| err.into_inner());
+        owners.get(job).map(|(user, _)| *user)
+    }
+}
@@ -0,0 +1,625 @@
+use crate::utils::ccompiler::{
+    CompileData, CompileEvent, CompileOptions, CompilePhase, CompilerError, FormatData,
+    OutputStream, SourceFile, SyntaxCheckData,
+};
+use base64::Engine;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long to wait for the service: its queue wait and build time, plus slack.
+pub const SERVICE_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// The C compiler service as the server functions use it, reached through
+/// `AppState::compiler`.
+pub trait CompilerClient: Send + Sync {
+    /// Identifies the toolchain, for compile cache keys.
+    fn version(&self) -> BoxFuture<'_, Result<String, CompilerError>>;
+
+    fn compile(
+        &self,
+        files: Vec<SourceFile>,
+        options: CompileOptions,
+    ) -> BoxFuture<'_, Result<CompileData, CompilerError>>;
+
+    fn format(&self, code: String) -> BoxFuture<'_, Result<FormatData, CompilerError>>;
+
+    /// Only the defines of `options` are used.
+    fn syntax_check(
+        &self,
+        files: Vec<SourceFile>,
+        options: CompileOptions,
+    ) -> BoxFuture<'_, Result<SyntaxCheckData, CompilerError>>;
+
+    /// Starts a compile job and follows it. The stream opens with `Started` and
+    /// ends after `Done` or `Failed`; dropping it cancels the job.
+    fn compile_stream(
+        &self,
+        files: Vec<SourceFile>,
+        options: CompileOptions,
+    ) -> BoxFuture<'_, Result<BoxStream<'static, CompileEvent>, CompilerError>>;
+
+    fn cancel(&self, job: String) -> BoxFuture<'_, Result<(), CompilerError>>;
+}
+
+/// Error body the compiler service sends with a non-success status.
+#[derive(Deserialize)]
+struct ServiceError {
+    code: String,
+    message: String,
+}
+
+impl From<ServiceError> for CompilerError {
+    fn from(error: ServiceError) -> Self {
+        match error.code.as_str() {
+            "timeout" => CompilerError::Timeout,
+            "resource_exhausted" => CompilerError::ResourceExhausted(error.message),
+            "busy" => CompilerError::Busy,
+            "too_large" => CompilerError::TooLarge(error.message),
+            "invalid_request" => CompilerError::Rejected(error.message),
+            "cancelled" => CompilerError::Cancelled,
+            _ => CompilerError::RequestError(format!("{}: {}", error.code, error.message)),
+        }
+    }
+}
+
+fn request_error(err: reqwest::Error) -> CompilerError {
+    if err.is_timeout() {
+        CompilerError::Timeout
+    } else if err.is_decode() {
+        CompilerError::DecodeError(err.to_string())
+    } else {
+        CompilerError::RequestError(err.to_string())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RequestBody {
+    b64data: String,
+}
+
+impl RequestBody {
+    pub fn new(code: String) -> Self {
+        let b64data = base64::engine::general_purpose::STANDARD.encode(&code);
+        Self { b64data }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncSourceFile {
+    name: String,
+    b64data: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProjectRequestBody {
+    files: Vec<EncSourceFile>,
+}
+
+impl ProjectRequestBody {
+    pub fn new(files: Vec<SourceFile>) -> Self {
+        let files = files
+            .into_iter()
+            .map(|file| EncSourceFile {
+                name: file.name,
+                b64data: base64::engine::general_purpose::STANDARD.encode(&file.code),
+            })
+            .collect();
+        Self { files }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompileRequestBody {
+    #[serde(flatten)]
+    project: ProjectRequestBody,
+    options: CompileOptions,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncCompileData {
+    rc: i32,
+    b64stdout: String,
+    b64stderr: String,
+    b64data: String,
+    #[serde(default)]
+    b64listing: String,
+    #[serde(default)]
+    b64map: String,
+}
+
+impl EncCompileData {
+    pub fn decode(&self) -> Result<CompileData, CompilerError> {
+        let decode = |field: &str, data: &str| {
+            base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| {
+                    CompilerError::DecodeError(format!("Failed to decode {}: {}", field, e))
+                })
+        };
+        let decode_str = |field: &str, data: &str| {
+            String::from_utf8(decode(field, data)?).map_err(|e| {
+                CompilerError::DecodeError(format!("Failed to decode {}: {}", field, e))
+            })
+        };
+        let stdout = decode_str("stdout", &self.b64stdout)?;
+        let stderr = decode_str("stderr", &self.b64stderr)?;
+        let data = decode("data", &self.b64data)?;
+        let listing = decode_str("listing", &self.b64listing)?;
+        let map = decode_str("map", &self.b64map)?;
+        Ok(CompileData {
+            rc: self.rc,
+            stdout,
+            stderr,
+            data,
+            listing,
+            map,
+            cached: false,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncFormatData {
+    b64data: String,
+}
+
+impl EncFormatData {
+    pub fn decode(&self) -> Result<FormatData, CompilerError> {
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(&self.b64data)
+            .map_err(|e| {
+                CompilerError::DecodeError(format!("Failed to decode base64 data: {}", e))
+            })?;
+        let data = String::from_utf8(data).map_err(|e| {
+            CompilerError::DecodeError(format!("Failed to convert decoded data to string: {}", e))
+        })?;
+        Ok(FormatData { data })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncSyntaxCheckData {
+    rc: i32,
+    b64stderr: String,
+}
+
+impl EncSyntaxCheckData {
+    pub fn decode(&self) -> Result<SyntaxCheckData, CompilerError> {
+        let stderr = base64::engine::general_purpose::STANDARD
+            .decode(&self.b64stderr)
+            .map_err(|e| {
+                CompilerError::DecodeError(format!("Failed to decode base64 data: {}", e))
+            })?;
+        let stderr = String::from_utf8(stderr).map_err(|e| {
+            CompilerError::DecodeError(format!("Failed to convert decoded data to string: {}", e))
+        })?;
+        Ok(SyntaxCheckData {
+            rc: self.rc,
+            stderr,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ToolchainVersion {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct JobId {
+    id: String,
+}
+
+/// Event lines the compiler service streams for a job.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServiceEvent {
+    Phase {
+        phase: CompilePhase,
+        #[serde(default)]
+        file: Option<String>,
+    },
+    Output {
+        stream: OutputStream,
+        line: String,
+    },
+    Result(EncCompileData),
+    Error(ServiceError),
+}
+
+/// Follows a job's event stream on the compiler service.
+struct JobEvents {
+    response: reqwest::Response,
+    pending: Vec<u8>,
+    finished: bool,
+}
+
+impl JobEvents {
+    async fn next_event(&mut self) -> Option<CompileEvent> {
+        while !self.finished {
+            if let Some(end) = self.pending.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.pending.drain(..=end).collect();
+                if line.trim_ascii().is_empty() {
+                    continue;
+                }
+                return Some(self.translate(&line));
+            }
+            match self.response.chunk().await {
+                Ok(Some(chunk)) => self.pending.extend_from_slice(&chunk),
+                Ok(None) => {
+                    self.finished = true;
+                    return Some(CompileEvent::Failed {
+                        error: CompilerError::RequestError(
+                            "Compiler service ended the stream early".to_string(),
+                        ),
+                    });
+                }
+                Err(err) => {
+                    self.finished = true;
+                    return Some(CompileEvent::Failed {
+                        error: request_error(err),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn translate(&mut self, line: &[u8]) -> CompileEvent {
+        let event = match serde_json::from_slice::<ServiceEvent>(line) {
+            Ok(event) => event,
+            Err(err) => {
+                self.finished = true;
+                return CompileEvent::Failed {
+                    error: CompilerError::DecodeError(err.to_string()),
+                };
+            }
+        };
+        match event {
+            ServiceEvent::Phase { phase, file } => CompileEvent::Phase { phase, file },
+            ServiceEvent::Output { stream, line } => CompileEvent::Output { stream, line },
+            ServiceEvent::Result(data) => {
+                self.finished = true;
+                match data.decode() {
+                    Ok(data) => CompileEvent::Done { data },
+                    Err(error) => CompileEvent::Failed { error },
+                }
+            }
+            ServiceEvent::Error(error) => {
+                self.finished = true;
+                CompileEvent::Failed {
+                    error: error.into(),
+                }
+            }
+        }
+    }
+}
+
+/// Talks to the compiler service in `ccompiler/api.py` over HTTP.
+pub struct HttpCompilerClient {
+    client: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+}
+
+impl HttpCompilerClient {
+    /// `base_url` is where the service listens, e.g. `http://ccompiler:4560`.
+    pub fn new(client: reqwest::Client, base_url: impl Into<String>) -> Self {
+        HttpCompilerClient {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            timeout: SERVICE_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    /// Turns a non-success response into the error the service reported.
+    async fn check(response: reqwest::Response) -> Result<reqwest::Response, CompilerError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        Err(match response.json::<ServiceError>().await {
+            Ok(error) => error.into(),
+            Err(_) => CompilerError::RequestError(format!("Compiler service returned {}", status)),
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, CompilerError> {
+        let response = self
+            .client
+            .get(self.url(path))
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(request_error)?;
+        Self::check(response)
+            .await?
+            .json::<T>()
+            .await
+            .map_err(request_error)
+    }
+
+    /// Posts `data` to the service and reads back its JSON answer.
+    async fn post<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        data: &impl Serialize,
+    ) -> Result<T, CompilerError> {
+        // The service turns away bodies without a length, so never stream one.
+        let body = serde_json::to_vec(data).unwrap();
+        let response = self
+            .client
+            .post(self.url(path))
+            .header("Content-Type", "application/json")
+            .header("Content-Length", body.len())
+            .body(body)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(request_error)?;
+        Self::check(response)
+            .await?
+            .json::<T>()
+            .await
+            .map_err(request_error)
+    }
+}
+
+impl CompilerClient for HttpCompilerClient {
+    fn version(&self) -> BoxFuture<'_, Result<String, CompilerError>> {
+        async move { Ok(self.get::<ToolchainVersion>("version").await?.version) }.boxed()
+    }
+
+    fn compile(
+        &self,
+        files: Vec<SourceFile>,
+        options: CompileOptions,
+    ) -> BoxFuture<'_, Result<CompileData, CompilerError>> {
+        async move {
+            let data = CompileRequestBody {
+                project: ProjectRequestBody::new(files),
+                options,
+            };
+            self.post::<EncCompileData>("compile", &data)
+                .await?
+                .decode()
+        }
+        .boxed()
+    }
+
+    fn format(&self, code: String) -> BoxFuture<'_, Result<FormatData, CompilerError>> {
+        async move {
+            self.post::<EncFormatData>("format", &RequestBody::new(code))
+                .await?
+                .decode()
+        }
+        .boxed()
+    }
+
+    fn syntax_check(
+        &self,
+        files: Vec<SourceFile>,
+        options: CompileOptions,
+    ) -> BoxFuture<'_, Result<SyntaxCheckData, CompilerError>> {
+        async move {
+            let data = CompileRequestBody {
+                project: ProjectRequestBody::new(files),
+                options,
+            };
+            self.post::<EncSyntaxCheckData>("syntax_check", &data)
+                .await?
+                .decode()
+        }
+        .boxed()
+    }
+
+    fn compile_stream(
+        &self,
+        files: Vec<SourceFile>,
+        options: CompileOptions,
+    ) -> BoxFuture<'_, Result<BoxStream<'static, CompileEvent>, CompilerError>> {
+        async move {
+            let data = CompileRequestBody {
+                project: ProjectRequestBody::new(files),
+                options,
+            };
+            let job = self.post::<JobId>("jobs", &data).await?.id;
+            // No timeout here: the service limits each build step itself.
+            let response = self
+                .client
+                .get(self.url(&format!("jobs/{}/events", job)))
+                .send()
+                .await
+                .map_err(request_error)?;
+            let events = JobEvents {
+                response: Self::check(response).await?,
+                pending: vec![],
+                finished: false,
+            };
+            // Dropping the stream closes the service's stream too, which
+            // cancels the job.
+            let events = futures::stream::once(async move { CompileEvent::Started { job } }).chain(
+                futures::stream::unfold(events, |mut events| async move {
+                    events.next_event().await.map(|event| (event, events))
+                }),
+            );
+            Ok(events.boxed())
+        }
+        .boxed()
+    }
+
+    fn cancel(&self, job: String) -> BoxFuture<'_, Result<(), CompilerError>> {
+        async move {
+            self.post::<JobId>(&format!("jobs/{}/cancel", job), &())
+                .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// In-process stand-in for the compiler service. Answers with fixed results,
+/// formats nothing and counts what it was asked to do.
+pub struct FakeCompilerClient {
+    version: String,
+    compile: Result<CompileData, CompilerError>,
+    output: Vec<(OutputStream, String)>,
+    syntax_check: Result<SyntaxCheckData, CompilerError>,
+    compiles: AtomicUsize,
+    cancelled: Mutex<Vec<String>>,
+}
+
+impl Default for FakeCompilerClient {
+    fn default() -> Self {
+        FakeCompilerClient {
+            version: "fake".to_string(),
+            compile: Ok(CompileData {
+                rc: 0,
+                stdout: String::new(),
+                stderr: String::new(),
+                // `halt`
+                data: vec![0x76],
+                listing: String::new(),
+                map: String::new(),
+                cached: false,
+            }),
+            output: vec![],
+            syntax_check: Ok(SyntaxCheckData {
+                rc: 0,
+                stderr: String::new(),
+            }),
+            compiles: AtomicUsize::new(0),
+            cancelled: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl FakeCompilerClient {
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = version.to_string();
+        self
+    }
+
+    /// What every compile returns, streamed or not.
+    pub fn with_compile(mut self, result: Result<CompileData, CompilerError>) -> Self {
+        self.compile = result;
+        self
+    }
+
+    /// Lines a streamed compile prints before its result.
+    pub fn with_output(mut self, output: Vec<(OutputStream, String)>) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn with_syntax_check(mut self, result: Result<SyntaxCheckData, CompilerError>) -> Self {
+        self.syntax_check = result;
+        self
+    }
+
+    /// Compiles run so far, streamed or not.
+    pub fn compiles(&self) -> usize {
+        self.compiles.load(Ordering::SeqCst)
+    }
+
+    /// Jobs cancelled so far.
+    pub fn cancelled(&self) -> Vec<String> {
+        self.cancelled
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+impl CompilerClient for FakeCompilerClient {
+    fn version(&self) -> BoxFuture<'_, Result<String, CompilerError>> {
+        futures::future::ready(Ok(self.version.clone())).boxed()
+    }
+
+    fn compile(
+        &self,
+        _files: Vec<SourceFile>,
+        _options: CompileOptions,
+    ) -> BoxFuture<'_, Result<CompileData, CompilerError>> {
+        self.compiles.fetch_add(1, Ordering::SeqCst);
+        futures::future::ready(self.compile.clone()).boxed()
+    }
+
+    fn format(&self, code: String) -> BoxFuture<'_, Result<FormatData, CompilerError>> {
+        futures::future::ready(Ok(FormatData { data: code })).boxed()
+    }
+
+    fn syntax_check(
+        &self,
+        _files: Vec<SourceFile>,
+        _options: CompileOptions,
+    ) -> BoxFuture<'_, Result<SyntaxCheckData, CompilerError>> {
+        futures::future::ready(self.syntax_check.clone()).boxed()
+    }
+
+    fn compile_stream(
+        &self,
+        files: Vec<SourceFile>,
+        _options: CompileOptions,
+    ) -> BoxFuture<'_, Result<BoxStream<'static, CompileEvent>, CompilerError>> {
+        let job = self.compiles.fetch_add(1, Ordering::SeqCst);
+        let mut events = vec![
+            CompileEvent::Started {
+                job: format!("{:032x}", job),
+            },
+            CompileEvent::Phase {
+                phase: CompilePhase::Queued,
+                file: None,
+            },
+        ];
+        events.extend(
+            files
+                .into_iter()
+                .filter(|file| !file.is_header())
+                .map(|file| CompileEvent::Phase {
+                    phase: CompilePhase::Compiling,
+                    file: Some(file.name),
+                }),
+        );
+        events.push(CompileEvent::Phase {
+            phase: CompilePhase::Linking,
+            file: None,
+        });
+        events.extend(
+            self.output
+                .iter()
+                .map(|(stream, line)| CompileEvent::Output {
+                    stream: *stream,
+                    line: line.clone(),
+                }),
+        );
+        events.push(match self.compile.clone() {
+            Ok(data) => CompileEvent::Done { data },
+            Err(error) => CompileEvent::Failed { error },
+        });
+        futures::future::ready(Ok(futures::stream::iter(events).boxed())).boxed()
+    }
+
+    fn cancel(&self, job: String) -> BoxFuture<'_, Result<(), CompilerError>> {
+        self.cancelled
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(job);
+        futures::future::ready(Ok(())).boxed()
+    }
+}
@@ -1,40 +1,134 @@
 use leptos::logging::log;
+use leptos::prelude::*;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// Entries kept before the oldest ones are dropped.
+pub const DEFAULT_LOG_LIMIT: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
 pub enum LogLevel {
     Info,
     Warning,
     Error,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+impl LogLevel {
+    pub const ALL: [LogLevel; 3] = [LogLevel::Info, LogLevel::Warning, LogLevel::Error];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Info => "INFO",
+            LogLevel::Warning => "WARN",
+            LogLevel::Error => "ERROR",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Log {
+    /// Unique within its store, for keyed lists.
+    #[serde(skip)]
+    pub id: u64,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Emulated cycle count when the entry was logged.
+    pub cycles: u64,
     pub level: LogLevel,
     pub short_message: &'static str,
     pub message: String,
+    /// How many identical entries in a row this one stands for; the timestamps
+    /// are those of the latest.
+    pub repeats: usize,
+}
+
+impl Log {
+    fn same_as(&self, other: &Log) -> bool {
+        self.level == other.level
+            && self.short_message == other.short_message
+            && self.message == other.message
+    }
+}
+
+/// Which entries the log panel shows and exports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogFilter {
+    pub levels: Vec<LogLevel>,
+    /// Case-insensitive text matched against both messages.
+    pub text: String,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            levels: LogLevel::ALL.to_vec(),
+            text: String::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn matches(&self, log: &Log) -> bool {
+        if !self.levels.contains(&log.level) {
+            return false;
+        }
+        if self.text.is_empty() {
+            return true;
+        }
+        let text = self.text.to_lowercase();
+        log.short_message.to_lowercase().contains(&text)
+            || log.message.to_lowercase().contains(&text)
+    }
 }
 
 pub struct LogStore {
-    logs: Vec<Log>,
+    logs: VecDeque<Log>,
+    limit: usize,
+    /// Cycle count stamped on new entries, kept up to date by the emulator.
+    clock: u64,
+    next_id: u64,
+    /// Bumped whenever the entries or the limit change, so views can follow
+    /// the log without tracking everything else in the emulator config.
+    revision: RwSignal<u64>,
 }
 
 impl Default for LogStore {
     fn default() -> Self {
-        LogStore { logs: Vec::new() }
+        LogStore {
+            logs: VecDeque::new(),
+            limit: DEFAULT_LOG_LIMIT,
+            clock: 0,
+            next_id: 0,
+            revision: RwSignal::new(0),
+        }
     }
 }
 
 impl LogStore {
     pub fn log(&mut self, level: LogLevel, short_message: &'static str, message: String) {
         let log = Log {
+            id: self.next_id,
             timestamp: chrono::Utc::now(),
+            cycles: self.clock,
             level,
             short_message,
             message,
+            repeats: 1,
         };
         log!("Log: {}, {}", short_message, log.message);
-        self.logs.push(log);
+        match self.logs.back_mut() {
+            Some(last) if last.same_as(&log) => {
+                last.timestamp = log.timestamp;
+                last.cycles = log.cycles;
+                last.repeats += 1;
+            }
+            _ => {
+                self.next_id += 1;
+                self.logs.push_back(log);
+                self.trim();
+            }
+        }
+        self.changed();
     }
 
     pub fn log_info(&mut self, short_message: &'static str, message: String) {
@@ -50,9 +144,74 @@ impl LogStore {
     }
 
     pub fn last_log(&self) -> Option<&Log> {
-        self.logs.last()
+        self.logs.back()
     }
-    pub fn get_logs(&self) -> &Vec<Log> {
+    pub fn get_logs(&self) -> &VecDeque<Log> {
         &self.logs
     }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(1);
+        self.trim();
+        self.changed();
+    }
+
+    pub fn set_clock(&mut self, cycles: u64) {
+        self.clock = cycles;
+    }
+
+    pub fn clear(&mut self) {
+        self.logs.clear();
+        self.changed();
+    }
+
+    pub fn revision(&self) -> ReadSignal<u64> {
+        self.revision.read_only()
+    }
+
+    fn changed(&self) {
+        self.revision.update(|revision| *revision += 1);
+    }
+
+    fn trim(&mut self) {
+        let excess = self.logs.len().saturating_sub(self.limit);
+        self.logs.drain(..excess);
+    }
+
+    pub fn filtered<'a>(
+        &'a self,
+        filter: &'a LogFilter,
+    ) -> impl DoubleEndedIterator<Item = &'a Log> {
+        self.logs.iter().filter(|log| filter.matches(log))
+    }
+
+    /// One line per entry: wall clock, cycles, level, messages and repeat count.
+    pub fn export_text(&self, filter: &LogFilter) -> String {
+        let mut text = String::new();
+        for log in self.filtered(filter) {
+            let _ = write!(
+                text,
+                "{} [{}] {:<5} {}: {}",
+                log.timestamp.to_rfc3339(),
+                log.cycles,
+                log.level.name(),
+                log.short_message,
+                log.message
+            );
+            if log.repeats > 1 {
+                let _ = write!(text, " (x{})", log.repeats);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn export_json(&self, filter: &LogFilter) -> String {
+        let logs: Vec<&Log> = self.filtered(filter).collect();
+        serde_json::to_string_pretty(&logs).unwrap_or_default()
+    }
 }
@@ -1,5 +1,13 @@
 pub mod ccompiler;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod compile_cache;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod compile_jobs;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod compiler_client;
 pub mod cookie;
 pub mod fetch;
 pub mod icons;
 pub mod logger;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rate_limit;
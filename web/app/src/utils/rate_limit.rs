@@ -0,0 +1,49 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Users tracked before idle ones are dropped.
+const PRUNE_ABOVE: usize = 1024;
+
+/// Allows each user at most `limit` requests in any `window`.
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    hits: Mutex<HashMap<i32, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        RateLimiter {
+            limit,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request from `user`, or returns how long until one is allowed.
+    pub fn check(&self, user: i32) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap_or_else(|err| err.into_inner());
+        if hits.len() > PRUNE_ABOVE {
+            hits.retain(|_, times| {
+                times
+                    .back()
+                    .is_some_and(|last| now.duration_since(*last) < self.window)
+            });
+        }
+        let times = hits.entry(user).or_default();
+        while times
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= self.window)
+        {
+            times.pop_front();
+        }
+        if times.len() >= self.limit {
+            let oldest = times.front().copied().unwrap_or(now);
+            return Err(self.window.saturating_sub(now.duration_since(oldest)));
+        }
+        times.push_back(now);
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+//! `HttpCompilerClient` against a stand-in for the compiler service, served on
+//! a local port with canned answers.
+
+use app::utils::ccompiler::{
+    CompileEvent, CompileOptions, CompilePhase, CompilerError, Define, OutputStream, SourceFile,
+};
+use app::utils::compiler_client::{CompilerClient, HttpCompilerClient};
+use axum::extract::Path;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::Engine;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Serves `router` on a free local port, returning its base URL.
+async fn serve(router: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    format!("http://{}", address)
+}
+
+fn client(base_url: String) -> HttpCompilerClient {
+    HttpCompilerClient::new(reqwest::Client::new(), base_url)
+}
+
+fn b64(data: impl AsRef<[u8]>) -> String {
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
+
+fn project() -> Vec<SourceFile> {
+    vec![
+        SourceFile::new(
+            "main.c",
+            "#include \"util.h\"\nint main() { return 0; }\n".to_string(),
+        ),
+        SourceFile::new("util.h", "int util(void);\n".to_string()),
+    ]
+}
+
+fn service_error(status: StatusCode, code: &str, message: &str) -> impl IntoResponse {
+    (status, Json(json!({ "code": code, "message": message })))
+}
+
+#[tokio::test]
+async fn compile_encodes_project_and_decodes_result() {
+    let seen = Arc::new(Mutex::new(None::<(HeaderMap, Value)>));
+    let router = Router::new().route(
+        "/compile",
+        post({
+            let seen = seen.clone();
+            move |headers: HeaderMap, Json(body): Json<Value>| async move {
+                *seen.lock().unwrap() = Some((headers, body));
+                Json(json!({
+                    "rc": 0,
+                    "b64stdout": b64("built\n"),
+                    "b64stderr": b64(""),
+                    "b64data": b64([0x3e, 0x05, 0x76]),
+                    "b64listing": b64("listing"),
+                    "b64map": b64("_main = $0000"),
+                }))
+            }
+        }),
+    );
+    let client = client(serve(router).await);
+
+    let data = client
+        .compile(project(), CompileOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(data.rc, 0);
+    assert_eq!(data.stdout, "built\n");
+    assert_eq!(data.data, vec![0x3e, 0x05, 0x76]);
+    assert_eq!(data.listing, "listing");
+    assert_eq!(data.map, "_main = $0000");
+    assert!(!data.cached);
+
+    let (headers, body) = seen.lock().unwrap().take().unwrap();
+    assert!(headers.contains_key("content-length"));
+    assert_eq!(body["files"][0]["name"], "main.c");
+    assert_eq!(body["files"][0]["b64data"], b64(&project()[0].code));
+    assert_eq!(body["files"][1]["name"], "util.h");
+    assert_eq!(body["options"]["optimization"], 3);
+}
+
+#[tokio::test]
+async fn format_and_syntax_check_decode_results() {
+    let router = Router::new()
+        .route(
+            "/format",
+            post(|Json(body): Json<Value>| async move {
+                // Echo the code back, as astyle would for tidy input.
+                Json(json!({ "b64data": body["b64data"] }))
+            }),
+        )
+        .route(
+            "/syntax_check",
+            post(|Json(body): Json<Value>| async move {
+                // The defines are checked with, so the options go along.
+                assert_eq!(body["options"]["defines"][0]["name"], "FAST");
+                Json(json!({
+                    "rc": 1,
+                    "b64stderr": b64("main.c:1:5: error: expected ';'\n"),
+                }))
+            }),
+        );
+    let client = client(serve(router).await);
+
+    let formatted = client.format("int x;\n".to_string()).await.unwrap();
+    assert_eq!(formatted.data, "int x;\n");
+    let options = CompileOptions {
+        defines: vec![Define::parse("FAST").unwrap()],
+        ..CompileOptions::default()
+    };
+    let checked = client.syntax_check(project(), options).await.unwrap();
+    assert_eq!(checked.rc, 1);
+    assert_eq!(checked.stderr, "main.c:1:5: error: expected ';'\n");
+}
+
+#[tokio::test]
+async fn bad_base64_is_a_decode_error() {
+    let router = Router::new().route(
+        "/compile",
+        post(|| async {
+            Json(json!({
+                "rc": 0,
+                "b64stdout": "not base64!",
+                "b64stderr": "",
+                "b64data": "",
+            }))
+        }),
+    );
+    let client = client(serve(router).await);
+
+    let result = client.compile(project(), CompileOptions::default()).await;
+    assert!(
+        matches!(result, Err(CompilerError::DecodeError(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn malformed_json_is_a_decode_error() {
+    let router = Router::new().route("/version", get(|| async { "zcc v2.3" }));
+    let client = client(serve(router).await);
+
+    let result = client.version().await;
+    assert!(
+        matches!(result, Err(CompilerError::DecodeError(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn slow_service_times_out() {
+    let router = Router::new().route(
+        "/syntax_check",
+        post(|| async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Json(json!({ "rc": 0, "b64stderr": "" }))
+        }),
+    );
+    let client = client(serve(router).await).with_timeout(Duration::from_millis(200));
+
+    let result = client
+        .syntax_check(project(), CompileOptions::default())
+        .await;
+    assert!(
+        matches!(result, Err(CompilerError::Timeout)),
+        "{:?}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn service_errors_map_to_compiler_errors() {
+    let router = Router::new()
+        .route(
+            "/compile",
+            post(|| async {
+                service_error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "busy",
+                    "All workers are busy",
+                )
+            }),
+        )
+        .route(
+            "/format",
+            post(|| async {
+                service_error(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request",
+                    "Invalid file name",
+                )
+            }),
+        )
+        .route(
+            "/syntax_check",
+            post(|| async { (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error") }),
+        );
+    let client = client(serve(router).await);
+
+    let compiled = client.compile(project(), CompileOptions::default()).await;
+    assert!(
+        matches!(compiled, Err(CompilerError::Busy)),
+        "{:?}",
+        compiled
+    );
+    let formatted = client.format(String::new()).await;
+    assert!(
+        matches!(&formatted, Err(CompilerError::Rejected(message)) if message == "Invalid file name"),
+        "{:?}",
+        formatted
+    );
+    let checked = client
+        .syntax_check(project(), CompileOptions::default())
+        .await;
+    assert!(
+        matches!(checked, Err(CompilerError::RequestError(_))),
+        "{:?}",
+        checked
+    );
+}
+
+#[tokio::test]
+async fn unreachable_service_is_a_request_error() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    drop(listener);
+    let client = client(format!("http://{}", address));
+
+    let result = client.version().await;
+    assert!(
+        matches!(result, Err(CompilerError::RequestError(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[tokio::test]
+async fn compile_stream_follows_the_job() {
+    let events = [
+        json!({ "type": "phase", "phase": "queued" }),
+        json!({ "type": "phase", "phase": "compiling", "file": "main.c" }),
+        json!({ "type": "output", "stream": "stderr", "line": "main.c:2: warning: unused" }),
+        json!({ "type": "phase", "phase": "linking" }),
+        json!({
+            "type": "result",
+            "rc": 0,
+            "b64stdout": "",
+            "b64stderr": b64("main.c:2: warning: unused\n"),
+            "b64data": b64([0x76]),
+        }),
+    ]
+    .iter()
+    .map(|event| format!("{}\n", event))
+    .collect::<String>();
+    let router = Router::new()
+        .route("/jobs", post(|| async { Json(json!({ "id": "0a1b" })) }))
+        .route(
+            "/jobs/{id}/events",
+            get(move |Path(id): Path<String>| {
+                let events = events.clone();
+                async move {
+                    assert_eq!(id, "0a1b");
+                    events
+                }
+            }),
+        );
+    let client = client(serve(router).await);
+
+    let events = client
+        .compile_stream(project(), CompileOptions::default())
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(&events[0], CompileEvent::Started { job } if job == "0a1b"));
+    assert!(matches!(
+        &events[1],
+        CompileEvent::Phase {
+            phase: CompilePhase::Queued,
+            file: None
+        }
+    ));
+    assert!(matches!(
+        &events[2],
+        CompileEvent::Phase { phase: CompilePhase::Compiling, file: Some(file) } if file == "main.c"
+    ));
+    assert!(matches!(
+        &events[3],
+        CompileEvent::Output { stream: OutputStream::Stderr, line } if line == "main.c:2: warning: unused"
+    ));
+    assert!(matches!(
+        &events[4],
+        CompileEvent::Phase {
+            phase: CompilePhase::Linking,
+            file: None
+        }
+    ));
+    match &events[5] {
+        CompileEvent::Done { data } => assert_eq!(data.data, vec![0x76]),
+        event => panic!("expected the result, got {:?}", event),
+    }
+    assert_eq!(events.len(), 6);
+}
+
+#[tokio::test]
+async fn compile_stream_reports_cancel_and_early_end() {
+    let router = Router::new()
+        .route("/jobs", post(|| async { Json(json!({ "id": "cafe" })) }))
+        .route(
+            "/jobs/{id}/events",
+            get(|| async {
+                format!(
+                    "{}\n",
+                    json!({ "type": "error", "code": "cancelled", "message": "Compilation cancelled" })
+                )
+            }),
+        );
+    let client = client(serve(router).await);
+    let events = client
+        .compile_stream(project(), CompileOptions::default())
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        events.last(),
+        Some(CompileEvent::Failed {
+            error: CompilerError::Cancelled
+        })
+    ));
+
+    let router = Router::new()
+        .route("/jobs", post(|| async { Json(json!({ "id": "cafe" })) }))
+        .route(
+            "/jobs/{id}/events",
+            get(|| async { format!("{}\n", json!({ "type": "phase", "phase": "queued" })) }),
+        );
+    let client = client(serve(router).await);
+    let events = client
+        .compile_stream(project(), CompileOptions::default())
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await;
+    assert!(matches!(
+        events.last(),
+        Some(CompileEvent::Failed {
+            error: CompilerError::RequestError(_)
+        })
+    ));
+}
+
+#[tokio::test]
+async fn cancel_posts_to_the_job() {
+    let cancelled = Arc::new(Mutex::new(vec![]));
+    let router = Router::new().route(
+        "/jobs/{id}/cancel",
+        post({
+            let cancelled = cancelled.clone();
+            move |Path(id): Path<String>| async move {
+                cancelled.lock().unwrap().push(id.clone());
+                Json(json!({ "id": id }))
+            }
+        }),
+    );
+    let client = client(serve(router).await);
+
+    client.cancel("beef".to_string()).await.unwrap();
+    assert_eq!(*cancelled.lock().unwrap(), vec!["beef".to_string()]);
+}
@@ -0,0 +1,359 @@
+//! The compiler server functions, called as a request would call them, with
+//! `FakeCompilerClient` standing in for the service.
+#![cfg(feature = "ssr")]
+
+use app::db::models::user::{UserData, UserType};
+use app::db::AppState;
+use app::utils::ccompiler::{
+    c_compile, c_compile_cancel, c_compile_stream, c_format, c_syntax_check, flush_compile_cache,
+    CompileData, CompileEvent, CompileEventReader, CompileOptions, CompilerError, OutputStream,
+    SourceFile, COMPILE_RATE_LIMIT, RATE_WINDOW,
+};
+use app::utils::compile_cache::CompileCache;
+use app::utils::compile_jobs::CompileJobs;
+use app::utils::compiler_client::FakeCompilerClient;
+use app::utils::rate_limit::RateLimiter;
+use diesel::r2d2::{ConnectionManager, Pool};
+use futures::StreamExt;
+use http::StatusCode;
+use leptos::prelude::*;
+use leptos_axum::ResponseOptions;
+use std::sync::Arc;
+
+/// State for a test's requests, and the owner their context is provided on;
+/// keep it alive for the test.
+fn setup(compiler: Arc<FakeCompilerClient>, compile_limit: usize) -> (Owner, AppState) {
+    let owner = Owner::new();
+    owner.set();
+    let state = AppState {
+        leptos_options: LeptosOptions::builder().output_name("test").build(),
+        // Never connected to; the compiler functions don't touch the database.
+        pool: Pool::builder().build_unchecked(ConnectionManager::new("postgres://unused")),
+        reqwest_client: reqwest::Client::new(),
+        compile_limiter: Arc::new(RateLimiter::new(compile_limit, RATE_WINDOW)),
+        syntax_check_limiter: Arc::new(RateLimiter::new(compile_limit, RATE_WINDOW)),
+        compile_cache: Arc::new(CompileCache::new(1024 * 1024)),
+        compile_jobs: Arc::new(CompileJobs::new()),
+        compiler,
+    };
+    (owner, state)
+}
+
+fn user(id: i32, user_type: UserType) -> UserData {
+    UserData {
+        id,
+        username: format!("user{}", id),
+        email: format!("user{}@example.com", id),
+        user_type,
+    }
+}
+
+/// Sets up the context a request from `user` would get, as the auth middleware
+/// and the leptos handler provide it, and returns its response options.
+fn request(state: &AppState, user: Option<UserData>) -> ResponseOptions {
+    provide_context(state.clone());
+    let response = ResponseOptions::default();
+    provide_context(response.clone());
+    let (mut parts, ()) = http::Request::new(()).into_parts();
+    if let Some(user) = user {
+        parts.extensions.insert(user);
+    }
+    provide_context(parts);
+    response
+}
+
+fn status(response: &ResponseOptions) -> Option<StatusCode> {
+    response.0.read().unwrap().status
+}
+
+fn project() -> Vec<SourceFile> {
+    vec![SourceFile::new(
+        "main.c",
+        "int main() { return 0; }\n".to_string(),
+    )]
+}
+
+#[tokio::test]
+async fn signed_out_callers_are_unauthorized() {
+    let compiler = Arc::new(FakeCompilerClient::default());
+    let (_owner, state) = setup(compiler.clone(), COMPILE_RATE_LIMIT);
+
+    let response = request(&state, None);
+    let result = c_compile(project(), CompileOptions::default()).await;
+    assert!(
+        matches!(result, Err(CompilerError::Unauthorized)),
+        "{:?}",
+        result
+    );
+    assert_eq!(status(&response), Some(StatusCode::UNAUTHORIZED));
+
+    request(&state, None);
+    let result = c_format("int x;".to_string()).await;
+    assert!(
+        matches!(result, Err(CompilerError::Unauthorized)),
+        "{:?}",
+        result
+    );
+    request(&state, None);
+    let result = c_syntax_check(project(), CompileOptions::default()).await;
+    assert!(
+        matches!(result, Err(CompilerError::Unauthorized)),
+        "{:?}",
+        result
+    );
+    request(&state, None);
+    let result = c_compile_stream(project(), CompileOptions::default()).await;
+    assert!(matches!(result, Err(CompilerError::Unauthorized)));
+    request(&state, None);
+    let result = c_compile_cancel("0a".to_string()).await;
+    assert!(
+        matches!(result, Err(CompilerError::Unauthorized)),
+        "{:?}",
+        result
+    );
+    assert_eq!(compiler.compiles(), 0);
+    assert!(compiler.cancelled().is_empty());
+}
+
+#[tokio::test]
+async fn compile_is_cached_per_source_and_options() {
+    let compiler = Arc::new(FakeCompilerClient::default());
+    let (_owner, state) = setup(compiler.clone(), COMPILE_RATE_LIMIT);
+
+    request(&state, Some(user(1, UserType::User)));
+    let first = c_compile(project(), CompileOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(first.data, vec![0x76]);
+    assert!(!first.cached);
+
+    request(&state, Some(user(2, UserType::User)));
+    let second = c_compile(project(), CompileOptions::default())
+        .await
+        .unwrap();
+    assert!(second.cached);
+    assert_eq!(compiler.compiles(), 1);
+
+    let options = CompileOptions {
+        optimization: 0,
+        ..CompileOptions::default()
+    };
+    request(&state, Some(user(1, UserType::User)));
+    let other = c_compile(project(), options).await.unwrap();
+    assert!(!other.cached);
+    assert_eq!(compiler.compiles(), 2);
+}
+
+#[tokio::test]
+async fn compile_errors_from_the_service_are_returned() {
+    let compiler = Arc::new(FakeCompilerClient::default().with_compile(Err(CompilerError::Busy)));
+    let (_owner, state) = setup(compiler.clone(), COMPILE_RATE_LIMIT);
+
+    request(&state, Some(user(1, UserType::User)));
+    let result = c_compile(project(), CompileOptions::default()).await;
+    assert!(matches!(result, Err(CompilerError::Busy)), "{:?}", result);
+
+    // Failures are not cached.
+    request(&state, Some(user(1, UserType::User)));
+    let _ = c_compile(project(), CompileOptions::default()).await;
+    assert_eq!(compiler.compiles(), 2);
+}
+
+#[tokio::test]
+async fn invalid_requests_never_reach_the_service() {
+    let compiler = Arc::new(FakeCompilerClient::default());
+    let (_owner, state) = setup(compiler.clone(), COMPILE_RATE_LIMIT);
+
+    let options = CompileOptions {
+        optimization: 9,
+        ..CompileOptions::default()
+    };
+    request(&state, Some(user(1, UserType::User)));
+    let result = c_compile(project(), options).await;
+    assert!(
+        matches!(result, Err(CompilerError::InvalidOptions(_))),
+        "{:?}",
+        result
+    );
+
+    request(&state, Some(user(1, UserType::User)));
+    let result = c_compile_cancel("../version".to_string()).await;
+    assert!(
+        matches!(result, Err(CompilerError::Rejected(_))),
+        "{:?}",
+        result
+    );
+    assert_eq!(compiler.compiles(), 0);
+    assert!(compiler.cancelled().is_empty());
+}
+
+#[tokio::test]
+async fn compiles_are_rate_limited_per_user() {
+    let compiler = Arc::new(FakeCompilerClient::default());
+    let (_owner, state) = setup(compiler.clone(), 1);
+
+    request(&state, Some(user(1, UserType::User)));
+    c_compile(project(), CompileOptions::default())
+        .await
+        .unwrap();
+    let response = request(&state, Some(user(1, UserType::User)));
+    let result = c_compile(project(), CompileOptions::default()).await;
+    assert!(
+        matches!(result, Err(CompilerError::RateLimited(_))),
+        "{:?}",
+        result
+    );
+    assert_eq!(status(&response), Some(StatusCode::TOO_MANY_REQUESTS));
+
+    request(&state, Some(user(2, UserType::User)));
+    c_compile(project(), CompileOptions::default())
+        .await
+        .unwrap();
+}
+
+async fn stream_events(files: Vec<SourceFile>) -> Vec<CompileEvent> {
+    let mut stream = Box::pin(
+        c_compile_stream(files, CompileOptions::default())
+            .await
+            .unwrap()
+            .into_inner(),
+    );
+    let mut reader = CompileEventReader::default();
+    let mut events = vec![];
+    while let Some(chunk) = stream.next().await {
+        events.extend(reader.push(&chunk.unwrap()).unwrap());
+    }
+    events
+}
+
+#[tokio::test]
+async fn compile_stream_sends_progress_then_replays_from_cache() {
+    let compiler = Arc::new(
+        FakeCompilerClient::default()
+            .with_compile(Ok(CompileData {
+                rc: 0,
+                stdout: String::new(),
+                stderr: "main.c:1: warning: unused\n".to_string(),
+                data: vec![0x76],
+                listing: String::new(),
+                map: String::new(),
+                cached: false,
+            }))
+            .with_output(vec![(
+                OutputStream::Stderr,
+                "main.c:1: warning: unused".to_string(),
+            )]),
+    );
+    let (_owner, state) = setup(compiler.clone(), COMPILE_RATE_LIMIT);
+
+    request(&state, Some(user(1, UserType::User)));
+    let events = stream_events(project()).await;
+    assert!(matches!(events.first(), Some(CompileEvent::Started { .. })));
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, CompileEvent::Phase { .. })));
+    assert!(matches!(
+        events.last(),
+        Some(CompileEvent::Done { data }) if !data.cached
+    ));
+
+    request(&state, Some(user(1, UserType::User)));
+    let events = stream_events(project()).await;
+    assert_eq!(compiler.compiles(), 1);
+    assert!(matches!(
+        &events[0],
+        CompileEvent::Output { stream: OutputStream::Stderr, line } if line == "main.c:1: warning: unused"
+    ));
+    assert!(matches!(
+        events.last(),
+        Some(CompileEvent::Done { data }) if data.cached
+    ));
+}
+
+#[tokio::test]
+async fn cancelling_a_job_the_caller_never_started_is_rejected() {
+    let compiler = Arc::new(FakeCompilerClient::default());
+    let (_owner, state) = setup(compiler.clone(), COMPILE_RATE_LIMIT);
+
+    let response = request(&state, Some(user(1, UserType::User)));
+    let result = c_compile_cancel("0a1b".to_string()).await;
+    assert!(
+        matches!(result, Err(CompilerError::Forbidden)),
+        "{:?}",
+        result
+    );
+    assert_eq!(status(&response), Some(StatusCode::FORBIDDEN));
+    assert!(compiler.cancelled().is_empty());
+}
+
+#[tokio::test]
+async fn only_the_user_who_started_a_job_can_cancel_it() {
+    let compiler = Arc::new(FakeCompilerClient::default());
+    let (_owner, state) = setup(compiler.clone(), COMPILE_RATE_LIMIT);
+
+    request(&state, Some(user(1, UserType::User)));
+    let mut stream = Box::pin(
+        c_compile_stream(project(), CompileOptions::default())
+            .await
+            .unwrap()
+            .into_inner(),
+    );
+    let mut reader = CompileEventReader::default();
+    let job = loop {
+        let chunk = stream.next().await.unwrap().unwrap();
+        let events = reader.push(&chunk).unwrap();
+        if let Some(CompileEvent::Started { job }) = events.into_iter().next() {
+            break job;
+        }
+    };
+
+    request(&state, Some(user(2, UserType::User)));
+    let result = c_compile_cancel(job.clone()).await;
+    assert!(
+        matches!(result, Err(CompilerError::Forbidden)),
+        "{:?}",
+        result
+    );
+    assert!(compiler.cancelled().is_empty());
+
+    request(&state, Some(user(1, UserType::User)));
+    c_compile_cancel(job.clone()).await.unwrap();
+    assert_eq!(compiler.cancelled(), vec![job]);
+}
+
+#[tokio::test]
+async fn only_admins_flush_the_cache() {
+    let compiler = Arc::new(FakeCompilerClient::default());
+    let (_owner, state) = setup(compiler.clone(), COMPILE_RATE_LIMIT);
+    request(&state, Some(user(1, UserType::User)));
+    c_compile(project(), CompileOptions::default())
+        .await
+        .unwrap();
+
+    let response = request(&state, Some(user(1, UserType::User)));
+    let result = flush_compile_cache().await;
+    assert!(
+        matches!(result, Err(CompilerError::Forbidden)),
+        "{:?}",
+        result
+    );
+    assert_eq!(status(&response), Some(StatusCode::FORBIDDEN));
+
+    request(&state, None);
+    let result = flush_compile_cache().await;
+    assert!(
+        matches!(result, Err(CompilerError::Unauthorized)),
+        "{:?}",
+        result
+    );
+
+    request(&state, Some(user(2, UserType::Admin)));
+    assert_eq!(flush_compile_cache().await.unwrap(), 1);
+
+    request(&state, Some(user(1, UserType::User)));
+    let recompiled = c_compile(project(), CompileOptions::default())
+        .await
+        .unwrap();
+    assert!(!recompiled.cached);
+    assert_eq!(compiler.compiles(), 2);
+}
@@ -0,0 +1,89 @@
+use crate::RunnerError;
+use serde::{Deserialize, Serialize};
+
+const ADDRESS_SPACE: usize = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegionKind {
+    Ram,
+    /// Ignores writes from the CPU; only loading a binary changes it.
+    Rom,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub kind: RegionKind,
+    pub start: u16,
+    pub size: usize,
+}
+
+/// Memory layout of a machine. Regions are mapped back to back, so they must be
+/// listed in address order starting at 0 with no gaps.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MachineConfig {
+    pub regions: Vec<Region>,
+}
+
+impl Default for MachineConfig {
+    /// The web emulator's layout, with the display area as plain RAM.
+    fn default() -> Self {
+        let display_start = 0x4000;
+        let display_size = 192 * 128;
+        let post_start = display_start + display_size;
+        MachineConfig {
+            regions: vec![
+                Region {
+                    kind: RegionKind::Ram,
+                    start: 0,
+                    size: display_start,
+                },
+                Region {
+                    kind: RegionKind::Ram,
+                    start: display_start as u16,
+                    size: display_size,
+                },
+                Region {
+                    kind: RegionKind::Ram,
+                    start: post_start as u16,
+                    size: ADDRESS_SPACE - post_start,
+                },
+            ],
+        }
+    }
+}
+
+impl MachineConfig {
+    pub fn from_json(json: &str) -> Result<Self, RunnerError> {
+        let config: MachineConfig =
+            serde_json::from_str(json).map_err(|err| RunnerError::Config(err.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), RunnerError> {
+        let mut next = 0usize;
+        for region in &self.regions {
+            if region.start as usize != next {
+                return Err(RunnerError::Config(format!(
+                    "region at {:#06X} should start at {:#06X}",
+                    region.start, next
+                )));
+            }
+            if region.size == 0 {
+                return Err(RunnerError::Config(format!(
+                    "region at {:#06X} is empty",
+                    region.start
+                )));
+            }
+            next += region.size;
+            if next > ADDRESS_SPACE {
+                return Err(RunnerError::Config(format!(
+                    "region at {:#06X} ends past 0xFFFF",
+                    region.start
+                )));
+            }
+        }
+        Ok(())
+    }
+}
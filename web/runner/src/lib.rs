@@ -0,0 +1,18 @@
+//! Headless Z80 runner: builds a machine from a memory map, loads binaries and
+//! runs them without any UI, for tests, grading and offline scripts.
+
+mod config;
+mod machine;
+mod state;
+
+pub use config::{MachineConfig, Region, RegionKind};
+pub use machine::{RunOutcome, Runner, Stop};
+pub use state::{MachineState, MemoryDump, Registers};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RunnerError {
+    #[error("invalid machine config: {0}")]
+    Config(String),
+    #[error("failed to load binary: {0}")]
+    Load(String),
+}
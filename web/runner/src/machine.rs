@@ -0,0 +1,158 @@
+use crate::config::{MachineConfig, RegionKind};
+use crate::state::MachineState;
+use crate::RunnerError;
+use emu_lib::cpu::z80::Z80;
+use emu_lib::cpu::Cpu;
+use emu_lib::emulator::Emulator;
+use emu_lib::memory::errors::{MemoryRWCommonError, MemoryReadError, MemoryWriteError};
+use emu_lib::memory::memdevices::RAM;
+use emu_lib::memory::{Memory, MemoryDevice};
+use serde::Serialize;
+
+/// Read-only memory. CPU writes are dropped like on real hardware; loading a
+/// binary goes through `write_8_force`.
+struct RomDevice {
+    data: Vec<u8>,
+}
+
+impl MemoryDevice for RomDevice {
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn read_8(&self, addr: u16) -> Result<u8, MemoryReadError> {
+        self.data
+            .get(addr as usize)
+            .copied()
+            .ok_or_else(|| MemoryRWCommonError::OutOfBounds(addr).into())
+    }
+
+    fn write_8(&mut self, addr: u16, _value: u8) -> Result<(), MemoryWriteError> {
+        if addr as usize >= self.data.len() {
+            return Err(MemoryRWCommonError::OutOfBounds(addr).into());
+        }
+        Ok(())
+    }
+
+    fn write_8_force(&mut self, addr: u16, value: u8) -> Result<(), MemoryWriteError> {
+        match self.data.get_mut(addr as usize) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            }
+            None => Err(MemoryRWCommonError::OutOfBounds(addr).into()),
+        }
+    }
+}
+
+/// Why a run ended.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Stop {
+    CycleLimit,
+    Halt,
+    Breakpoint { address: u16 },
+    Error { message: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RunOutcome {
+    pub stop: Stop,
+    pub cycles: u64,
+    pub instructions: u64,
+}
+
+/// A Z80 machine driven one instruction at a time with no timers or UI, so the
+/// same binary and limits always produce the same state.
+pub struct Runner {
+    emu: Emulator<Z80>,
+}
+
+impl Runner {
+    pub fn new(config: &MachineConfig) -> Result<Self, RunnerError> {
+        config.validate()?;
+        let mut memory = Memory::new();
+        for region in &config.regions {
+            match region.kind {
+                RegionKind::Ram => memory.add_device(Box::new(RAM::new(region.size))),
+                RegionKind::Rom => memory.add_device(Box::new(RomDevice {
+                    data: vec![0; region.size],
+                })),
+            }
+        }
+        Ok(Runner {
+            emu: Emulator::<Z80>::new_w_mem(memory),
+        })
+    }
+
+    pub fn emulator(&self) -> &Emulator<Z80> {
+        &self.emu
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Emulator<Z80> {
+        &mut self.emu
+    }
+
+    /// Copies `bytes` into memory at `address`, ROM included.
+    pub fn load(&mut self, bytes: &[u8], address: u16) -> Result<(), RunnerError> {
+        if address as usize + bytes.len() > 0x10000 {
+            return Err(RunnerError::Load(format!(
+                "{} bytes at {:#06X} do not fit in memory",
+                bytes.len(),
+                address
+            )));
+        }
+        for (offset, byte) in bytes.iter().enumerate() {
+            let target = address.wrapping_add(offset as u16);
+            self.emu
+                .memory
+                .write_8_force(target, *byte)
+                .map_err(|err| RunnerError::Load(format!("{:#06X}: {:?}", target, err)))?;
+        }
+        Ok(())
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        if !self.emu.breakpoints.contains(&address) {
+            self.emu.breakpoints.push(address);
+        }
+    }
+
+    /// Steps until the CPU halts, hits a breakpoint or errors, or, when
+    /// `max_cycles` is given, until at least that many cycles have run. Like
+    /// the web Run button, a breakpoint at the starting address is stepped past.
+    pub fn run(&mut self, max_cycles: Option<u64>) -> RunOutcome {
+        let mut cycles = 0u64;
+        let mut instructions = 0u64;
+        let stop = loop {
+            if max_cycles.is_some_and(|max| cycles >= max) {
+                break Stop::CycleLimit;
+            }
+            if self.emu.cpu.halted() {
+                break Stop::Halt;
+            }
+            let before = self.emu.cycles;
+            if let Err(err) = self.emu.step() {
+                break Stop::Error {
+                    message: err.to_string(),
+                };
+            }
+            cycles += ((self.emu.cycles - before) as u64).max(1);
+            instructions += 1;
+            let pc = self.emu.cpu.registers.pc;
+            if self.emu.breakpoints.contains(&pc) {
+                break Stop::Breakpoint { address: pc };
+            }
+        };
+        RunOutcome {
+            stop,
+            cycles,
+            instructions,
+        }
+    }
+
+    /// Registers and counters, plus the bytes of `memory` when given.
+    pub fn state(&self, memory: Option<(u16, u16)>) -> MachineState {
+        MachineState::capture(&self.emu, memory)
+    }
+}
@@ -0,0 +1,140 @@
+use runner::{MachineConfig, MachineState, RunOutcome, Runner};
+use serde::Serialize;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: emu-runner [options]
+
+Options:
+  --config <file>          Memory map as JSON (default: the web emulator's layout)
+  --load <file>[@addr]     Load a binary, at 0 unless an address is given; repeatable
+  --break <addr>           Stop when PC reaches addr; repeatable
+  --cycles <n>             Stop after at least n cycles (default: 100000000)
+  --memory <start>-<end>   Include this memory range in the dump
+  --output <file>          Write the JSON report here instead of stdout
+  --help                   Show this message
+
+Addresses and counts accept decimal or 0x-prefixed hex.";
+
+/// Cycle cap when `--cycles` is not given, so programs that never halt, like
+/// the display demos, still finish: about 28 seconds of the web emulator's clock.
+const DEFAULT_CYCLES: u64 = 100_000_000;
+
+#[derive(Default)]
+struct Args {
+    config: Option<String>,
+    loads: Vec<(String, u16)>,
+    breakpoints: Vec<u16>,
+    cycles: Option<u64>,
+    memory: Option<(u16, u16)>,
+    output: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    outcome: RunOutcome,
+    state: MachineState,
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number: {}", text))
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let value = parse_number(text)?;
+    u16::try_from(value).map_err(|_| format!("address out of range: {}", text))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        if arg == "--help" {
+            return Ok(None);
+        }
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--config" => parsed.config = Some(value()?),
+            "--load" => {
+                let value = value()?;
+                let (file, address) = match value.rsplit_once('@') {
+                    Some((file, address)) => (file.to_string(), parse_address(address)?),
+                    None => (value, 0),
+                };
+                parsed.loads.push((file, address));
+            }
+            "--break" => parsed.breakpoints.push(parse_address(&value()?)?),
+            "--cycles" => parsed.cycles = Some(parse_number(&value()?)?),
+            "--memory" => {
+                let value = value()?;
+                let (start, end) = value
+                    .split_once('-')
+                    .ok_or_else(|| format!("expected <start>-<end>, got {}", value))?;
+                let (start, end) = (parse_address(start)?, parse_address(end)?);
+                if start > end {
+                    return Err(format!("memory range {} is backwards", value));
+                }
+                parsed.memory = Some((start, end));
+            }
+            "--output" => parsed.output = Some(value()?),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(Some(parsed))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let config = match &args.config {
+        Some(path) => {
+            let json = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+            MachineConfig::from_json(&json).map_err(|err| err.to_string())?
+        }
+        None => MachineConfig::default(),
+    };
+    let mut runner = Runner::new(&config).map_err(|err| err.to_string())?;
+    for (file, address) in &args.loads {
+        let bytes = std::fs::read(file).map_err(|err| format!("{}: {}", file, err))?;
+        runner
+            .load(&bytes, *address)
+            .map_err(|err| format!("{}: {}", file, err))?;
+    }
+    for address in &args.breakpoints {
+        runner.add_breakpoint(*address);
+    }
+    let report = Report {
+        outcome: runner.run(Some(args.cycles.unwrap_or(DEFAULT_CYCLES))),
+        state: runner.state(args.memory),
+    };
+    let json = serde_json::to_string_pretty(&report).expect("report serializes");
+    match &args.output {
+        Some(path) => std::fs::write(path, json).map_err(|err| format!("{}: {}", path, err)),
+        None => {
+            println!("{}", json);
+            Ok(())
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use emu_lib::cpu::z80::Z80;
+use emu_lib::cpu::Cpu;
+use emu_lib::emulator::Emulator;
+use emu_lib::memory::MemoryDevice;
+use serde::Serialize;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Registers {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub af_alt: u16,
+    pub bc_alt: u16,
+    pub de_alt: u16,
+    pub hl_alt: u16,
+    pub pc: u16,
+    pub sp: u16,
+    pub ix: u16,
+    pub iy: u16,
+    pub i: u8,
+    pub r: u8,
+    pub im: u8,
+    pub iff1: bool,
+    pub iff2: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MemoryDump {
+    pub start: u16,
+    /// Upper-case hex, two digits per byte. Unmapped bytes read as 00.
+    pub bytes: String,
+}
+
+/// Everything a test or grader needs to compare two runs, in a JSON-friendly shape.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MachineState {
+    pub registers: Registers,
+    pub halted: bool,
+    pub cycles: u64,
+    pub instructions: u64,
+    pub breakpoints: Vec<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryDump>,
+}
+
+impl MachineState {
+    pub fn capture(emu: &Emulator<Z80>, memory: Option<(u16, u16)>) -> Self {
+        let registers = &emu.cpu.registers;
+        MachineState {
+            registers: Registers {
+                af: registers.gp.af,
+                bc: registers.gp.bc,
+                de: registers.gp.de,
+                hl: registers.gp.hl,
+                af_alt: registers.gp_alt.af,
+                bc_alt: registers.gp_alt.bc,
+                de_alt: registers.gp_alt.de,
+                hl_alt: registers.gp_alt.hl,
+                pc: registers.pc,
+                sp: registers.sp,
+                ix: registers.ix,
+                iy: registers.iy,
+                i: registers.i,
+                r: registers.r,
+                im: registers.im,
+                iff1: registers.iff1,
+                iff2: registers.iff2,
+            },
+            halted: emu.cpu.halted(),
+            cycles: emu.cycles as u64,
+            instructions: emu.instructions as u64,
+            breakpoints: emu.breakpoints.clone(),
+            memory: memory.map(|(start, end)| MemoryDump {
+                start,
+                bytes: (start..=end)
+                    .map(|address| format!("{:02X}", emu.memory.read_8(address).unwrap_or(0)))
+                    .collect(),
+            }),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("machine state serializes")
+    }
+}
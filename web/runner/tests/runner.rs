@@ -0,0 +1,108 @@
+//! Small hand-assembled programs run to each kind of stop.
+
+use runner::{MachineConfig, Region, RegionKind, RunOutcome, Runner, Stop};
+
+fn run(program: &[u8], breakpoints: &[u16], max_cycles: Option<u64>) -> (Runner, RunOutcome) {
+    let mut runner = Runner::new(&MachineConfig::default()).unwrap();
+    runner.load(program, 0).unwrap();
+    for address in breakpoints {
+        runner.add_breakpoint(*address);
+    }
+    let outcome = runner.run(max_cycles);
+    (runner, outcome)
+}
+
+fn byte_at(runner: &Runner, address: u16) -> String {
+    runner.state(Some((address, address))).memory.unwrap().bytes
+}
+
+#[test]
+fn halt_stops_the_run() {
+    // LD A,0x05; HALT
+    let (runner, outcome) = run(&[0x3E, 0x05, 0x76], &[], None);
+    assert_eq!(outcome.stop, Stop::Halt);
+    assert_eq!(outcome.instructions, 2);
+
+    let state = runner.state(None);
+    assert!(state.halted);
+    assert_eq!(state.registers.af >> 8, 0x05);
+    assert_eq!(state.cycles, outcome.cycles);
+    assert_eq!(state.instructions, 2);
+    assert!(state.memory.is_none());
+}
+
+#[test]
+fn breakpoint_stops_before_the_instruction() {
+    // NOP; NOP; NOP; HALT
+    let (runner, outcome) = run(&[0x00, 0x00, 0x00, 0x76], &[0x0002], None);
+    assert_eq!(outcome.stop, Stop::Breakpoint { address: 0x0002 });
+    assert_eq!(outcome.instructions, 2);
+
+    let state = runner.state(None);
+    assert!(!state.halted);
+    assert_eq!(state.registers.pc, 0x0002);
+    assert_eq!(state.breakpoints, vec![0x0002]);
+}
+
+#[test]
+fn cycle_limit_ends_an_endless_loop() {
+    // JR -2
+    let (runner, outcome) = run(&[0x18, 0xFE], &[], Some(100));
+    assert_eq!(outcome.stop, Stop::CycleLimit);
+    assert!(outcome.cycles >= 100);
+    // No single instruction takes more than 23 cycles.
+    assert!(outcome.cycles < 100 + 23);
+
+    let state = runner.state(None);
+    assert!(!state.halted);
+    assert_eq!(state.registers.pc, 0x0000);
+    assert_eq!(state.cycles, outcome.cycles);
+}
+
+#[test]
+fn rom_ignores_cpu_writes() {
+    let config = MachineConfig {
+        regions: vec![
+            Region {
+                kind: RegionKind::Rom,
+                start: 0,
+                size: 0x100,
+            },
+            Region {
+                kind: RegionKind::Ram,
+                start: 0x100,
+                size: 0xFF00,
+            },
+        ],
+    };
+    let mut runner = Runner::new(&config).unwrap();
+    // LD A,0x42; LD (0x0010),A; LD (0x0100),A; HALT
+    let program = [0x3E, 0x42, 0x32, 0x10, 0x00, 0x32, 0x00, 0x01, 0x76];
+    runner.load(&program, 0).unwrap();
+
+    let outcome = runner.run(None);
+    assert_eq!(outcome.stop, Stop::Halt);
+    assert_eq!(outcome.instructions, 4);
+    assert_eq!(byte_at(&runner, 0x0010), "00");
+    assert_eq!(byte_at(&runner, 0x0100), "42");
+    // The program itself is still intact.
+    assert_eq!(byte_at(&runner, 0x0000), "3E");
+}
+
+#[test]
+fn runs_are_deterministic() {
+    // LD A,0x00; LD B,0x10; loop: INC A; DJNZ loop; HALT
+    let program = [0x3E, 0x00, 0x06, 0x10, 0x3C, 0x10, 0xFD, 0x76];
+    let (first, first_outcome) = run(&program, &[], None);
+    let (second, second_outcome) = run(&program, &[], None);
+    assert_eq!(first_outcome, second_outcome);
+    assert_eq!(first.state(Some((0, 7))), second.state(Some((0, 7))));
+    assert_eq!(first.state(None).registers.af >> 8, 0x10);
+}
+
+#[test]
+fn binaries_must_fit_in_memory() {
+    let mut runner = Runner::new(&MachineConfig::default()).unwrap();
+    assert!(runner.load(&[0x00, 0x00], 0xFFFF).is_err());
+    assert!(runner.load(&[0x00], 0xFFFF).is_ok());
+}
@@ -1,6 +1,13 @@
 #![recursion_limit = "512"]
 
 use app::db::{establish_connection, AppState};
+use app::utils::ccompiler::{
+    COMPILE_CACHE_BYTES, COMPILE_RATE_LIMIT, RATE_WINDOW, SYNTAX_CHECK_RATE_LIMIT,
+};
+use app::utils::compile_cache::CompileCache;
+use app::utils::compile_jobs::CompileJobs;
+use app::utils::compiler_client::HttpCompilerClient;
+use app::utils::rate_limit::RateLimiter;
 use app::*;
 use axum::http::{HeaderValue, Method};
 use axum::middleware::from_fn_with_state;
@@ -9,6 +16,7 @@ use fileserv::file_and_error_handler;
 use leptos::prelude::*;
 use leptos_axum::{generate_route_list, LeptosRoutes};
 use reqwest::Client;
+use std::sync::Arc;
 use tower_http::compression::predicate::{NotForContentType, SizeAbove};
 use tower_http::compression::{CompressionLayer, Predicate};
 use tower_http::cors::{Any, CorsLayer};
@@ -54,14 +62,27 @@ async fn main() {
     let leptos_options = conf.leptos_options;
     let addr = leptos_options.site_addr;
     let routes = generate_route_list(App);
+    let Ok(compiler_host) = std::env::var("COMPILER_HOST") else {
+        log::error!("COMPILER_HOST is not set");
+        std::process::exit(1);
+    };
+    let reqwest_client = Client::builder()
+        .build()
+        .expect("Could not create reqwest client");
 
     // build our application with a route
     let state = AppState {
         leptos_options: leptos_options.clone(),
         pool: pool.clone(),
-        reqwest_client: Client::builder()
-            .build()
-            .expect("Could not create reqwest client"),
+        compiler: Arc::new(HttpCompilerClient::new(
+            reqwest_client.clone(),
+            format!("http://{}", compiler_host),
+        )),
+        reqwest_client,
+        compile_limiter: Arc::new(RateLimiter::new(COMPILE_RATE_LIMIT, RATE_WINDOW)),
+        syntax_check_limiter: Arc::new(RateLimiter::new(SYNTAX_CHECK_RATE_LIMIT, RATE_WINDOW)),
+        compile_cache: Arc::new(CompileCache::new(COMPILE_CACHE_BYTES)),
+        compile_jobs: Arc::new(CompileJobs::new()),
     };
     let state_clone = state.clone();
     let app = Router::new()